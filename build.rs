@@ -0,0 +1,14 @@
+use std::{env, path::PathBuf};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // A protoc of our own, so building needs no system install.
+    env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    env::set_var("PROTOC_INCLUDE", protoc_bin_vendored::include_path()?);
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    tonic_build::configure()
+        .build_client(false)
+        .file_descriptor_set_path(out_dir.join("todo_descriptor.bin"))
+        .compile(&["proto/todo.proto"], &["proto"])?;
+    Ok(())
+}
@@ -0,0 +1,5 @@
+-- Add up migration script here
+
+ALTER TABLE todos ADD COLUMN IF NOT EXISTS user_id UUID REFERENCES users (id) ON DELETE CASCADE;
+
+CREATE INDEX todos_user_id_idx ON todos (user_id);
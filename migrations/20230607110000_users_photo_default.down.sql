@@ -0,0 +1,3 @@
+-- Add down migration script here
+
+ALTER TABLE users ALTER COLUMN photo DROP DEFAULT;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+
+ALTER TABLE users ALTER COLUMN photo SET DEFAULT '';
@@ -0,0 +1,9 @@
+-- Add down migration script here
+
+ALTER TABLE todos
+    DROP COLUMN due_at,
+    DROP COLUMN priority,
+    DROP COLUMN completed_at,
+    DROP COLUMN reminded_at;
+
+DROP TYPE IF EXISTS todo_priority;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+
+CREATE TYPE todo_priority AS ENUM ('low', 'normal', 'high', 'urgent');
+
+ALTER TABLE todos
+    ADD COLUMN due_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN priority todo_priority NOT NULL DEFAULT 'normal',
+    ADD COLUMN completed_at TIMESTAMP WITH TIME ZONE,
+    ADD COLUMN reminded_at TIMESTAMP WITH TIME ZONE;
+
+UPDATE todos SET completed_at = updated_at WHERE complete;
+
+CREATE INDEX todos_due_at_idx ON todos (due_at) WHERE due_at IS NOT NULL;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+ALTER TABLE todos
+    DROP CONSTRAINT IF EXISTS todos_rrule_due_at_check,
+    DROP COLUMN rrule,
+    DROP COLUMN timezone;
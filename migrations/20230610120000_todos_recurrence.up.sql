@@ -0,0 +1,6 @@
+-- Add up migration script here
+
+ALTER TABLE todos
+    ADD COLUMN rrule TEXT,
+    ADD COLUMN timezone VARCHAR(64) NOT NULL DEFAULT 'UTC',
+    ADD CONSTRAINT todos_rrule_due_at_check CHECK (rrule IS NULL OR due_at IS NOT NULL);
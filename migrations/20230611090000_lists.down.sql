@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+ALTER TABLE todos DROP COLUMN IF EXISTS list_id;
+
+DROP TABLE IF EXISTS lists;
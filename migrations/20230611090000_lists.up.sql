@@ -0,0 +1,19 @@
+-- Add up migration script here
+
+CREATE TABLE IF NOT EXISTS lists (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    archived_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, name),
+    UNIQUE (id, user_id)
+);
+
+-- Referencing (id, user_id) keeps todos in lists of their own owner.
+ALTER TABLE todos
+    ADD COLUMN list_id UUID,
+    ADD CONSTRAINT todos_list_id_fkey FOREIGN KEY (list_id, user_id) REFERENCES lists (id, user_id);
+
+CREATE INDEX todos_list_id_idx ON todos (list_id);
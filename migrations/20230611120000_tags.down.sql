@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+DROP TABLE IF EXISTS todo_tags;
+
+ALTER TABLE todos DROP CONSTRAINT IF EXISTS todos_id_user_id_key;
+
+DROP TABLE IF EXISTS tags;
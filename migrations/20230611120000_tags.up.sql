@@ -0,0 +1,25 @@
+-- Add up migration script here
+
+CREATE TABLE IF NOT EXISTS tags (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name VARCHAR(64) NOT NULL,
+    color VARCHAR(7),
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, name),
+    UNIQUE (id, user_id)
+);
+
+ALTER TABLE todos ADD CONSTRAINT todos_id_user_id_key UNIQUE (id, user_id);
+
+-- Both sides carry the owner, so a todo can only get its owner's tags.
+CREATE TABLE IF NOT EXISTS todo_tags (
+    todo_id UUID NOT NULL,
+    tag_id UUID NOT NULL,
+    user_id UUID NOT NULL,
+    PRIMARY KEY (todo_id, tag_id),
+    CONSTRAINT todo_tags_todo_id_fkey FOREIGN KEY (todo_id, user_id) REFERENCES todos (id, user_id) ON DELETE CASCADE,
+    CONSTRAINT todo_tags_tag_id_fkey FOREIGN KEY (tag_id, user_id) REFERENCES tags (id, user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX todo_tags_tag_id_idx ON todo_tags (tag_id);
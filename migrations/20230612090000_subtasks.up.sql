@@ -0,0 +1,8 @@
+-- Add up migration script here
+
+ALTER TABLE todos
+    ADD COLUMN parent_id UUID,
+    ADD CONSTRAINT todos_parent_id_fkey FOREIGN KEY (parent_id, user_id) REFERENCES todos (id, user_id) ON DELETE CASCADE,
+    ADD CONSTRAINT todos_parent_id_check CHECK (parent_id <> id);
+
+CREATE INDEX todos_parent_id_idx ON todos (parent_id);
@@ -0,0 +1,16 @@
+-- Add up migration script here
+
+-- Ranks compare bytewise, see rank.rs. Existing todos keep their creation
+-- order.
+ALTER TABLE todos ADD COLUMN position TEXT COLLATE "C";
+
+UPDATE todos SET position = ranked.position
+FROM (
+    SELECT id, LPAD(TO_HEX(ROW_NUMBER() OVER (PARTITION BY user_id ORDER BY created_at, id)), 8, '0') || 'V' AS position
+    FROM todos
+) ranked
+WHERE todos.id = ranked.id;
+
+ALTER TABLE todos ALTER COLUMN position SET NOT NULL;
+
+CREATE INDEX todos_user_id_position_idx ON todos (user_id, position);
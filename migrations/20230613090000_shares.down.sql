@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+DROP TABLE IF EXISTS shares;
+
+DROP TYPE IF EXISTS share_status;
+DROP TYPE IF EXISTS share_role;
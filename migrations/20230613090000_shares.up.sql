@@ -0,0 +1,27 @@
+-- Add up migration script here
+
+CREATE TYPE share_role AS ENUM ('viewer', 'editor');
+CREATE TYPE share_status AS ENUM ('pending', 'accepted', 'declined');
+
+-- A todo or list shared by its owner with whoever uses the mail address.
+-- user_id is the recipient, known once they answer the invitation.
+CREATE TABLE IF NOT EXISTS shares (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    owner_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    todo_id UUID,
+    list_id UUID,
+    mail VARCHAR(255) NOT NULL,
+    user_id UUID REFERENCES users (id) ON DELETE CASCADE,
+    role share_role NOT NULL DEFAULT 'viewer',
+    status share_status NOT NULL DEFAULT 'pending',
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CONSTRAINT shares_todo_id_fkey FOREIGN KEY (todo_id, owner_id) REFERENCES todos (id, user_id) ON DELETE CASCADE,
+    CONSTRAINT shares_list_id_fkey FOREIGN KEY (list_id, owner_id) REFERENCES lists (id, user_id) ON DELETE CASCADE,
+    CONSTRAINT shares_target_check CHECK ((todo_id IS NULL) <> (list_id IS NULL)),
+    UNIQUE (todo_id, mail),
+    UNIQUE (list_id, mail)
+);
+
+CREATE INDEX shares_mail_idx ON shares (mail);
+CREATE INDEX shares_user_id_idx ON shares (user_id);
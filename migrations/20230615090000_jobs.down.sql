@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP TABLE IF EXISTS jobs;
+DROP TYPE IF EXISTS job_status;
@@ -0,0 +1,27 @@
+-- Add up migration script here
+
+CREATE TYPE job_status AS ENUM ('queued', 'running', 'succeeded', 'dead');
+
+-- Work done outside the request path. A running job whose lock expired is
+-- picked up again, a job out of attempts is dead until retried by an admin.
+-- unique_key keeps recurring jobs from being queued twice for the same
+-- occurrence by different instances.
+CREATE TABLE IF NOT EXISTS jobs (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    kind VARCHAR(64) NOT NULL,
+    payload JSONB NOT NULL DEFAULT 'null',
+    status job_status NOT NULL DEFAULT 'queued',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL,
+    run_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    locked_until TIMESTAMP WITH TIME ZONE,
+    last_error TEXT,
+    unique_key TEXT UNIQUE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX jobs_queued_idx ON jobs (run_at) WHERE status = 'queued';
+CREATE INDEX jobs_running_idx ON jobs (locked_until) WHERE status = 'running';
+CREATE INDEX jobs_status_idx ON jobs (status, updated_at DESC);
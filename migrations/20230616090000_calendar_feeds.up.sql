@@ -0,0 +1,16 @@
+-- Add up migration script here
+
+-- Private iCalendar subscription URLs. A feed covers all of its user's todos
+-- or, with a list, only those in that list.
+CREATE TABLE IF NOT EXISTS calendar_feeds (
+    id UUID PRIMARY KEY NOT NULL DEFAULT (uuid_generate_v4()),
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    list_id UUID,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    last_polled_at TIMESTAMP WITH TIME ZONE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    CONSTRAINT calendar_feeds_list_id_fkey FOREIGN KEY (list_id, user_id) REFERENCES lists (id, user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX calendar_feeds_user_id_idx ON calendar_feeds (user_id);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP INDEX IF EXISTS todo_events_user_id_id_idx;
+DROP TABLE IF EXISTS dav_objects;
@@ -0,0 +1,17 @@
+-- Add up migration script here
+
+-- Names and UIDs CalDAV clients gave the todos they created. Other todos are
+-- served as `<id>.ics` with their id as UID. Rows outlive their todo, so a
+-- sync can still name a todo that was purged.
+CREATE TABLE IF NOT EXISTS dav_objects (
+    todo_id UUID PRIMARY KEY NOT NULL,
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    uid VARCHAR(255) NOT NULL,
+    UNIQUE (user_id, name)
+);
+
+CREATE INDEX dav_objects_user_id_uid_idx ON dav_objects (user_id, uid);
+
+-- Sync tokens are event ids, looked up per user.
+CREATE INDEX todo_events_user_id_id_idx ON todo_events (user_id, id);
@@ -0,0 +1,15 @@
+-- Add down migration script here
+
+DROP INDEX IF EXISTS todos_user_id_title_key;
+
+-- Titles go back to being unique across all users. Every todo but the oldest
+-- holding a title gets its id appended, so the constraint can be added.
+UPDATE todos SET title = LEFT(title, 200) || ' (' || id || ')'
+WHERE id IN (
+    SELECT id FROM (
+        SELECT id, ROW_NUMBER() OVER (PARTITION BY title ORDER BY created_at, id) AS n FROM todos
+    ) numbered
+    WHERE n > 1
+);
+
+ALTER TABLE todos ADD CONSTRAINT todos_title_key UNIQUE (title);
@@ -0,0 +1,8 @@
+-- Add up migration script here
+
+ALTER TABLE todos DROP CONSTRAINT IF EXISTS todos_title_key;
+
+-- Titles are unique among the open todos of a user outside the trash, so
+-- completed occurrences of a recurring todo keep the title of the series.
+CREATE UNIQUE INDEX todos_user_id_title_key ON todos (user_id, title)
+    WHERE deleted_at IS NULL AND complete IS NOT TRUE;
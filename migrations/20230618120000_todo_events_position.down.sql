@@ -0,0 +1,9 @@
+-- Add down migration script here
+
+DROP TRIGGER IF EXISTS todo_events_position ON todo_events;
+DROP FUNCTION IF EXISTS todo_events_position();
+DROP SEQUENCE IF EXISTS todo_events_position_seq;
+DROP INDEX IF EXISTS todo_events_user_id_position_idx;
+DROP INDEX IF EXISTS todo_events_position_idx;
+CREATE INDEX IF NOT EXISTS todo_events_user_id_id_idx ON todo_events (user_id, id);
+ALTER TABLE todo_events DROP COLUMN IF EXISTS position;
@@ -0,0 +1,36 @@
+-- Add up migration script here
+
+-- Ids are handed out when an event is inserted, but the write recording it
+-- can commit after a later one. Positions are handed out when the write
+-- commits, one at a time, so nothing ever appears behind a position a
+-- reader has already passed. Existing events keep their id as position.
+ALTER TABLE todo_events ADD COLUMN position BIGINT;
+UPDATE todo_events SET position = id;
+CREATE UNIQUE INDEX todo_events_position_idx ON todo_events (position);
+
+-- CalDAV sync tokens are positions now, looked up per user.
+DROP INDEX IF EXISTS todo_events_user_id_id_idx;
+CREATE INDEX todo_events_user_id_position_idx ON todo_events (user_id, position);
+
+CREATE SEQUENCE todo_events_position_seq;
+SELECT setval('todo_events_position_seq', GREATEST((SELECT MAX(id) FROM todo_events), 1));
+
+CREATE FUNCTION todo_events_position() RETURNS trigger AS $$
+BEGIN
+    -- Held until the commit is visible, so positions follow commit order.
+    -- This serializes the commits of writes to todos, but only from here on:
+    -- the trigger runs last, so the lock covers handing out the positions and
+    -- the commit itself. Feeds resume from a single position and the live
+    -- feed drops what is behind the last one it passed, both of which rely
+    -- on that order. Readers bounding their reads by the oldest open
+    -- transaction would need a cursor of the transaction id and the position
+    -- instead.
+    PERFORM pg_advisory_xact_lock('todo_events'::regclass::oid::bigint);
+    UPDATE todo_events SET position = nextval('todo_events_position_seq') WHERE id = NEW.id;
+    RETURN NULL;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE CONSTRAINT TRIGGER todo_events_position AFTER INSERT ON todo_events
+    DEFERRABLE INITIALLY DEFERRED
+    FOR EACH ROW EXECUTE FUNCTION todo_events_position();
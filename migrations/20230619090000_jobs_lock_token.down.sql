@@ -0,0 +1,3 @@
+-- Add down migration script here
+
+ALTER TABLE jobs DROP COLUMN IF EXISTS lock_token;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+
+-- Set anew on every claim, so an instance that lets its lock expire cannot
+-- record the outcome of a job another instance took over.
+ALTER TABLE jobs ADD COLUMN lock_token UUID;
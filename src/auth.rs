@@ -1,22 +1,110 @@
-use crate::{model::UserModel, schema::JWT, AppState};
+use crate::{
+    handlers::auth::verify_password,
+    model::{PersonalTokenModel, UserModel},
+    schema::JWT,
+    AppState,
+};
 use axum::{
     extract::State,
-    http::{header, Request, StatusCode},
+    http::{header, Method, Request, StatusCode},
     middleware::Next,
     response::IntoResponse,
-    Json,
+    Extension, Json,
 };
 use axum_extra::extract::cookie::CookieJar;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use jsonwebtoken::{decode, DecodingKey, Validation};
+use rand_core::{OsRng, RngCore};
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
 
+/// Prefix of every personal access token, used to tell them apart from JWTs.
+pub const TOKEN_PREFIX: &str = "pat_";
+
+/// Role of users allowed to administer the service.
+pub const ADMIN_ROLE: &str = "admin";
+
+/// Scopes a personal access token can be granted.
+pub const SCOPES: [&str; 2] = ["todos:read", "todos:write"];
+
+/// How recent a sign-in through a login provider has to be to stand in for
+/// the password of an account without one.
+const REAUTHENTICATION_MINUTES: i64 = 10;
+
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
     pub status: &'static str,
     pub message: String,
 }
 
+/// How the current request was authenticated.
+#[derive(Debug, Clone)]
+pub enum Credential {
+    /// Signed in at `signed_in_at`, through `provider` or with a password.
+    Session {
+        signed_in_at: i64,
+        provider: Option<String>,
+    },
+    PersonalToken {
+        scopes: Vec<String>,
+    },
+}
+
+impl Credential {
+    /// Sessions are allowed everything, tokens only what they were granted.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match self {
+            Credential::Session { .. } => true,
+            Credential::PersonalToken { scopes, .. } => scopes.iter().any(|s| s == scope),
+        }
+    }
+
+    /// Requires a scope, e.g. `todos:write`. The error explains the refusal.
+    pub fn require_scope(&self, scope: &str) -> Result<(), String> {
+        if !self.has_scope(scope) {
+            return Err(format!("This token is missing the {} scope", scope));
+        }
+        Ok(())
+    }
+
+    /// Requires a session rather than a personal access token.
+    pub fn require_session(&self) -> Result<(), String> {
+        if let Credential::PersonalToken { .. } = self {
+            return Err("This action requires a signed in session".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether this is a session signed in through a login provider only
+    /// minutes ago. Accounts without a password confirm sensitive changes
+    /// by signing in again.
+    pub fn recently_signed_in_through_provider(&self) -> bool {
+        match self {
+            Credential::Session {
+                signed_in_at,
+                provider: Some(_),
+            } => chrono::Utc::now().timestamp() - signed_in_at < REAUTHENTICATION_MINUTES * 60,
+            _ => false,
+        }
+    }
+}
+
+/// A random, URL safe secret for links and tokens; store only its hash.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn generate_token() -> String {
+    format!("{}{}", TOKEN_PREFIX, random_token())
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
 pub async fn auth<B>(
     cookie_jar: CookieJar,
     State(data): State<Arc<AppState>>,
@@ -47,8 +135,52 @@ pub async fn auth<B>(
         (StatusCode::UNAUTHORIZED, Json(json_error))
     })?;
 
+    let (user, credential) = authenticate(&data, &token).await?;
+
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(credential);
+    Ok(next.run(req).await)
+}
+
+/// Finds the user a session JWT or a personal access token belongs to.
+pub async fn authenticate(
+    data: &AppState,
+    token: &str,
+) -> Result<(UserModel, Credential), (StatusCode, Json<ErrorResponse>)> {
+    let (user_id, credential) = if token.starts_with(TOKEN_PREFIX) {
+        personal_token_credential(data, token).await?
+    } else {
+        session_credential(data, token)?
+    };
+
+    let user = sqlx::query_as!(UserModel, "SELECT * FROM users WHERE id = $1", user_id)
+        .fetch_optional(&data.db)
+        .await
+        .map_err(|e| {
+            let json_error = ErrorResponse {
+                status: "fail",
+                message: format!("Error fetching user from database: {}", e),
+            };
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(json_error))
+        })?;
+
+    let user = user.ok_or_else(|| {
+        let json_error = ErrorResponse {
+            status: "fail",
+            message: "The user belonging to this token no longer exists".to_string(),
+        };
+        (StatusCode::UNAUTHORIZED, Json(json_error))
+    })?;
+
+    Ok((user, credential))
+}
+
+fn session_credential(
+    data: &AppState,
+    token: &str,
+) -> Result<(uuid::Uuid, Credential), (StatusCode, Json<ErrorResponse>)> {
     let claims = decode::<JWT>(
-        &token,
+        token,
         &DecodingKey::from_secret(data.env.jwt_secret.as_ref()),
         &Validation::default(),
     )
@@ -69,7 +201,95 @@ pub async fn auth<B>(
         (StatusCode::UNAUTHORIZED, Json(json_error))
     })?;
 
-    let user = sqlx::query_as!(UserModel, "SELECT * FROM users WHERE id = $1", user_id)
+    let credential = Credential::Session {
+        signed_in_at: claims.iat as i64,
+        provider: claims.provider,
+    };
+    Ok((user_id, credential))
+}
+
+async fn personal_token_credential(
+    data: &AppState,
+    token: &str,
+) -> Result<(uuid::Uuid, Credential), (StatusCode, Json<ErrorResponse>)> {
+    let personal_token = sqlx::query_as!(
+        PersonalTokenModel,
+        "UPDATE personal_tokens SET last_used_at = NOW() WHERE token_hash = $1 AND (expires_at IS NULL OR expires_at > NOW()) RETURNING id, user_id, name, scopes, expires_at, last_used_at, created_at",
+        hash_token(token)
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(|e| {
+        let json_error = ErrorResponse {
+            status: "fail",
+            message: format!("Error fetching token from database: {}", e),
+        };
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(json_error))
+    })?
+    .ok_or_else(|| {
+        let json_error = ErrorResponse {
+            status: "fail",
+            message: "Invalid or expired token".to_string(),
+        };
+        (StatusCode::UNAUTHORIZED, Json(json_error))
+    })?;
+
+    Ok((
+        personal_token.user_id,
+        Credential::PersonalToken {
+            scopes: personal_token.scopes,
+        },
+    ))
+}
+
+/// A failure of `basic_auth`, which challenges the client to authenticate.
+type BasicAuthError = (
+    StatusCode,
+    [(header::HeaderName, &'static str); 1],
+    Json<ErrorResponse>,
+);
+
+/// Challenge sent with a 401 from `basic_auth`.
+const BASIC_CHALLENGE: &str = "Basic realm=\"Todos\", charset=\"UTF-8\"";
+
+/// HTTP Basic authentication for clients that cannot obtain a token, such as
+/// CalDAV apps. The user name is the mail and the password either the
+/// account's or a personal access token acting as app password, whose scopes
+/// apply as usual.
+pub async fn basic_auth<B>(
+    State(data): State<Arc<AppState>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Result<impl IntoResponse, BasicAuthError> {
+    let unauthorized = |message: &str| {
+        let json_error = ErrorResponse {
+            status: "fail",
+            message: message.to_string(),
+        };
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, BASIC_CHALLENGE)],
+            Json(json_error),
+        )
+    };
+
+    let (mail, password) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|auth_header| auth_header.to_str().ok())
+        .and_then(|auth_value| auth_value.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .and_then(|decoded| {
+            decoded
+                .split_once(':')
+                .map(|(mail, password)| (mail.to_ascii_lowercase(), password.to_string()))
+        })
+        .ok_or_else(|| {
+            unauthorized("You are not logged in, please provide your mail and password")
+        })?;
+
+    let user = sqlx::query_as!(UserModel, "SELECT * FROM users WHERE mail = $1", mail)
         .fetch_optional(&data.db)
         .await
         .map_err(|e| {
@@ -77,17 +297,88 @@ pub async fn auth<B>(
                 status: "fail",
                 message: format!("Error fetching user from database: {}", e),
             };
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(json_error))
-        })?;
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                [(header::WWW_AUTHENTICATE, BASIC_CHALLENGE)],
+                Json(json_error),
+            )
+        })?
+        .ok_or_else(|| unauthorized("Invalid mail or password"))?;
 
-    let user = user.ok_or_else(|| {
+    let credential = if password.starts_with(TOKEN_PREFIX) {
+        let (user_id, credential) = personal_token_credential(&data, &password)
+            .await
+            .map_err(|_| unauthorized("Invalid mail or password"))?;
+        if user_id != user.id {
+            return Err(unauthorized("Invalid mail or password"));
+        }
+        credential
+    } else if verify_password(&user.password, &password) {
+        Credential::Session {
+            signed_in_at: chrono::Utc::now().timestamp(),
+            provider: None,
+        }
+    } else {
+        return Err(unauthorized("Invalid mail or password"));
+    };
+
+    req.extensions_mut().insert(user);
+    req.extensions_mut().insert(credential);
+    Ok(next.run(req).await)
+}
+
+/// Requires `todos:read` for safe methods and `todos:write` for everything else.
+pub async fn todo_scope<B>(
+    Extension(credential): Extension<Credential>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    let scope = match *req.method() {
+        Method::GET | Method::HEAD => "todos:read",
+        _ => "todos:write",
+    };
+
+    if let Err(message) = credential.require_scope(scope) {
         let json_error = ErrorResponse {
             status: "fail",
-            message: "The user belonging to this token no longer exists".to_string(),
+            message,
         };
-        (StatusCode::UNAUTHORIZED, Json(json_error))
-    })?;
+        return Err((StatusCode::FORBIDDEN, Json(json_error)));
+    }
+
+    Ok(next.run(req).await)
+}
+
+/// Rejects personal access tokens, e.g. so a token cannot mint new tokens.
+pub async fn session_only<B>(
+    Extension(credential): Extension<Credential>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    if let Err(message) = credential.require_session() {
+        let json_error = ErrorResponse {
+            status: "fail",
+            message,
+        };
+        return Err((StatusCode::FORBIDDEN, Json(json_error)));
+    }
+
+    Ok(next.run(req).await)
+}
+
+/// Rejects everyone but admins.
+pub async fn admin_only<B>(
+    Extension(user): Extension<UserModel>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+    if user.role != ADMIN_ROLE {
+        let json_error = ErrorResponse {
+            status: "fail",
+            message: "This action requires an admin".to_string(),
+        };
+        return Err((StatusCode::FORBIDDEN, Json(json_error)));
+    }
 
-    req.extensions_mut().insert(user);
     Ok(next.run(req).await)
 }
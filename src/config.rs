@@ -4,6 +4,48 @@ pub struct Config {
     pub jwt_secret: String,
     pub jwt_expire: String,
     pub jwt_maxage: i32,
+    pub app_url: String,
+    pub oidc_providers: Vec<OidcProvider>,
+    pub storage: StorageConfig,
+    pub avatar_max_bytes: usize,
+    pub trash_retention_days: i64,
+    pub notifier: NotifierConfig,
+    pub reminder_lead_minutes: i64,
+    pub graphql_max_depth: usize,
+    pub graphql_max_complexity: usize,
+    pub grpc_port: u16,
+    pub webhook_allowed_hosts: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum NotifierConfig {
+    Log,
+    Mail,
+}
+
+#[derive(Debug, Clone)]
+pub enum StorageConfig {
+    Local {
+        dir: String,
+    },
+    S3 {
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+        public_url: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct OidcProvider {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_url: String,
+    pub scopes: String,
 }
 
 impl Config {
@@ -12,12 +54,113 @@ impl Config {
         let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
         let jwt_expire = std::env::var("JWT_EXPIRED_IN").expect("JWT_EXPIRED_IN must be set");
         let jwt_maxage = std::env::var("JWT_MAXAGE").expect("JWT_MAXAGE must be set");
+        let app_url =
+            std::env::var("APP_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+        let oidc_providers = std::env::var("OIDC_PROVIDERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(OidcProvider::init)
+            .collect();
+        let storage = StorageConfig::init();
+        let avatar_max_bytes = std::env::var("AVATAR_MAX_BYTES")
+            .map(|max| max.parse::<usize>().unwrap())
+            .unwrap_or(5 * 1024 * 1024);
+        let trash_retention_days = std::env::var("TRASH_RETENTION_DAYS")
+            .map(|days| days.parse::<i64>().unwrap())
+            .unwrap_or(30);
+        let notifier = match std::env::var("NOTIFIER").as_deref() {
+            Ok("mail") => NotifierConfig::Mail,
+            Ok("log") | Err(_) => NotifierConfig::Log,
+            Ok(other) => panic!("Unknown NOTIFIER: {}", other),
+        };
+        let reminder_lead_minutes = std::env::var("REMINDER_LEAD_MINUTES")
+            .map(|minutes| minutes.parse::<i64>().unwrap())
+            .unwrap_or(60);
+        let graphql_max_depth = std::env::var("GRAPHQL_MAX_DEPTH")
+            .map(|depth| depth.parse::<usize>().unwrap())
+            .unwrap_or(10);
+        let graphql_max_complexity = std::env::var("GRAPHQL_MAX_COMPLEXITY")
+            .map(|complexity| complexity.parse::<usize>().unwrap())
+            .unwrap_or(1000);
+        let grpc_port = std::env::var("GRPC_PORT")
+            .map(|port| port.parse::<u16>().unwrap())
+            .unwrap_or(50051);
+        let webhook_allowed_hosts = std::env::var("WEBHOOK_ALLOWED_HOSTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|host| host.trim().to_ascii_lowercase())
+            .filter(|host| !host.is_empty())
+            .collect();
 
         return Config {
             database_url: database_url,
             jwt_secret: jwt_secret,
             jwt_expire: jwt_expire,
             jwt_maxage: jwt_maxage.parse::<i32>().unwrap(),
+            app_url,
+            oidc_providers,
+            storage,
+            avatar_max_bytes,
+            trash_retention_days,
+            notifier,
+            reminder_lead_minutes,
+            graphql_max_depth,
+            graphql_max_complexity,
+            grpc_port,
+            webhook_allowed_hosts,
         };
     }
+
+    pub fn oidc_provider(&self, name: &str) -> Option<&OidcProvider> {
+        self.oidc_providers.iter().find(|p| p.name == name)
+    }
+}
+
+impl OidcProvider {
+    /// Reads `OIDC_<NAME>_*` for a provider listed in `OIDC_PROVIDERS`.
+    fn init(name: &str) -> OidcProvider {
+        let var = |key: &str| {
+            let key = format!("OIDC_{}_{}", name.to_ascii_uppercase(), key);
+            std::env::var(&key).unwrap_or_else(|_| panic!("{} must be set", key))
+        };
+
+        OidcProvider {
+            name: name.to_string(),
+            issuer: var("ISSUER").trim_end_matches('/').to_string(),
+            client_id: var("CLIENT_ID"),
+            client_secret: var("CLIENT_SECRET"),
+            redirect_url: var("REDIRECT_URL"),
+            scopes: std::env::var(format!("OIDC_{}_SCOPES", name.to_ascii_uppercase()))
+                .unwrap_or_else(|_| "openid email profile".to_string()),
+        }
+    }
+}
+
+impl StorageConfig {
+    fn init() -> StorageConfig {
+        let var = |key: &str| std::env::var(key).unwrap_or_else(|_| panic!("{} must be set", key));
+
+        match std::env::var("STORAGE_BACKEND").as_deref() {
+            Ok("s3") => {
+                let endpoint = var("S3_ENDPOINT").trim_end_matches('/').to_string();
+                let bucket = var("S3_BUCKET");
+                let public_url = std::env::var("S3_PUBLIC_URL")
+                    .unwrap_or_else(|_| format!("{}/{}", endpoint, bucket));
+                StorageConfig::S3 {
+                    region: std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+                    access_key: var("S3_ACCESS_KEY"),
+                    secret_key: var("S3_SECRET_KEY"),
+                    public_url: public_url.trim_end_matches('/').to_string(),
+                    endpoint,
+                    bucket,
+                }
+            }
+            Ok("local") | Err(_) => StorageConfig::Local {
+                dir: std::env::var("STORAGE_LOCAL_DIR").unwrap_or_else(|_| "uploads".to_string()),
+            },
+            Ok(other) => panic!("Unknown STORAGE_BACKEND: {}", other),
+        }
+    }
 }
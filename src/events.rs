@@ -0,0 +1,275 @@
+//! Fan-out of changes to connected clients. Todo events are recorded in
+//! `todo_events` as part of every write and announced with `pg_notify` when
+//! the write commits, as are changes to users. `tasks::listen_events` runs on
+//! every instance and publishes what any of them announced to the local
+//! subscribers.
+
+use crate::{model::TodoEventModel, schema::StreamOptions, store, AppState};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Executor, Pool, Postgres};
+use std::{
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+/// How many events a subscriber can fall behind before it lags and has to
+/// catch up from `todo_events`.
+const CAPACITY: usize = 1024;
+/// How many missed events are replayed at most. A client that missed more
+/// is told to start over.
+const REPLAY_LIMIT: i64 = 1000;
+/// How long the users sharing with a follower are cached.
+const SHARERS_TTL: Duration = Duration::from_secs(30);
+
+/// Notification channel carrying the ids of new todo events.
+pub const TODO_CHANNEL: &str = "todo_events";
+/// Notification channel carrying `UserChange`s as JSON.
+pub const USER_CHANNEL: &str = "user_events";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserChange {
+    pub id: Uuid,
+    pub kind: UserChangeKind,
+}
+
+/// Something that changed on any of the instances.
+#[derive(Debug, Clone)]
+pub enum Change {
+    Todo(Arc<TodoEventModel>),
+    User(UserChange),
+}
+
+#[derive(Clone)]
+pub struct EventHub {
+    sender: broadcast::Sender<Change>,
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        EventHub { sender }
+    }
+
+    pub fn publish(&self, change: Change) {
+        // Nobody listening is fine.
+        let _ = self.sender.send(change);
+    }
+
+    /// Changes from now on, todo events in the order of their positions.
+    /// Around a reconnect of the listener a todo event can come twice, its
+    /// position tells.
+    pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.sender.subscribe()
+    }
+}
+
+/// Announces a change to a user to all instances once the surrounding
+/// transaction, if any, commits.
+pub async fn notify_user<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    id: Uuid,
+    kind: UserChangeKind,
+) -> Result<(), sqlx::Error> {
+    let payload = serde_json::to_string(&UserChange { id, kind }).unwrap_or_default();
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(USER_CHANNEL)
+        .bind(payload)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// What a follower is sent.
+pub enum Update {
+    Event(Arc<TodoEventModel>),
+    /// More was missed than can be replayed, carries the position to go on
+    /// after.
+    Reset(i64),
+}
+
+/// A user's view of the todo events: what they missed replayed from
+/// `todo_events`, then the live events about todos they can see.
+pub struct Feed {
+    user_id: Uuid,
+    /// Position of the last event looked at, seen by the user or not.
+    cursor: i64,
+    live: broadcast::Receiver<Change>,
+    sharers: HashSet<Uuid>,
+    sharers_at: Instant,
+    list_id: Option<Uuid>,
+    tags: Vec<Uuid>,
+}
+
+impl Feed {
+    /// Starts following after `lastEventId`, or from now on without it.
+    /// Returns the feed and the updates missed since then.
+    pub async fn open(
+        data: &AppState,
+        user_id: Uuid,
+        options: StreamOptions,
+    ) -> Result<(Feed, Vec<Update>), sqlx::Error> {
+        // Subscribe before looking at the table so nothing falls in between.
+        let live = data.events.subscribe();
+        let mut feed = Feed {
+            user_id,
+            cursor: 0,
+            live,
+            sharers: HashSet::new(),
+            sharers_at: Instant::now(),
+            list_id: options.list_id,
+            tags: options.tags.unwrap_or_default(),
+        };
+        feed.refresh_sharers(&data.db).await?;
+
+        let updates = match options.last_event_id {
+            Some(last_event_id) => {
+                feed.cursor = last_event_id;
+                feed.catch_up(&data.db).await?
+            }
+            None => {
+                feed.cursor = store::event::latest_position(&data.db).await?;
+                Vec::new()
+            }
+        };
+        Ok((feed, updates))
+    }
+
+    /// Waits for the next live change. Nothing is lost when the future is
+    /// dropped, so it can race a heartbeat.
+    pub async fn recv(&mut self) -> Result<Change, RecvError> {
+        self.live.recv().await
+    }
+
+    /// What the user gets to see of what `recv` returned. `None` once no
+    /// more events will come, also when the user was deleted.
+    pub async fn handle(
+        &mut self,
+        db: &Pool<Postgres>,
+        received: Result<Change, RecvError>,
+    ) -> Result<Option<Vec<Update>>, sqlx::Error> {
+        match received {
+            Ok(Change::User(change))
+                if change.id == self.user_id && change.kind == UserChangeKind::Deleted =>
+            {
+                Ok(None)
+            }
+            Ok(Change::User(_)) => Ok(Some(Vec::new())),
+            // Already replayed.
+            Ok(Change::Todo(event)) if position(&event) <= self.cursor => Ok(Some(Vec::new())),
+            Ok(Change::Todo(event)) => {
+                self.cursor = position(&event);
+                if self.visible(db, &event).await? && self.wanted(db, &event).await? {
+                    Ok(Some(vec![Update::Event(event)]))
+                } else {
+                    Ok(Some(Vec::new()))
+                }
+            }
+            // The subscriber fell behind, the table still has what it missed.
+            Err(RecvError::Lagged(_)) => self.catch_up(db).await.map(Some),
+            Err(RecvError::Closed) => Ok(None),
+        }
+    }
+
+    async fn catch_up(&mut self, db: &Pool<Postgres>) -> Result<Vec<Update>, sqlx::Error> {
+        let events = store::event::feed(db, self.user_id, self.cursor, REPLAY_LIMIT + 1).await?;
+        if events.len() as i64 > REPLAY_LIMIT {
+            self.cursor = store::event::latest_position(db).await?;
+            return Ok(vec![Update::Reset(self.cursor)]);
+        }
+
+        let mut updates = Vec::new();
+        for event in events {
+            self.cursor = position(&event);
+            if self.wanted(db, &event).await? {
+                updates.push(Update::Event(Arc::new(event)));
+            }
+        }
+        Ok(updates)
+    }
+
+    /// Whether the event is about a todo of the user or one shared with them.
+    async fn visible(
+        &mut self,
+        db: &Pool<Postgres>,
+        event: &TodoEventModel,
+    ) -> Result<bool, sqlx::Error> {
+        if event.user_id == self.user_id {
+            return Ok(true);
+        }
+
+        if self.sharers_at.elapsed() > SHARERS_TTL {
+            self.refresh_sharers(db).await?;
+        }
+        if !self.sharers.contains(&event.user_id) {
+            return Ok(false);
+        }
+        Ok(store::share::todo_access(db, self.user_id, event.todo_id)
+            .await?
+            .is_some())
+    }
+
+    /// Whether the event passes the list and tag filters. A todo that moved
+    /// out of the list or lost the tags still matches the event saying so.
+    async fn wanted(
+        &self,
+        db: &Pool<Postgres>,
+        event: &TodoEventModel,
+    ) -> Result<bool, sqlx::Error> {
+        if self.list_id.is_none() && self.tags.is_empty() {
+            return Ok(true);
+        }
+
+        let (in_list, tagged) =
+            store::todo::matches(db, event.todo_id, self.list_id, &self.tags).await?;
+        let in_list = in_list
+            || self
+                .list_id
+                .is_some_and(|list_id| mentions(&event.changes, "listId", &[list_id]));
+        let tagged = tagged || mentions(&event.changes, "tags", &self.tags);
+        Ok(in_list && tagged)
+    }
+
+    async fn refresh_sharers(&mut self, db: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+        self.sharers = store::share::sharers(db, self.user_id)
+            .await?
+            .into_iter()
+            .collect();
+        self.sharers_at = Instant::now();
+        Ok(())
+    }
+}
+
+/// Where a committed event falls in the feed.
+pub fn position(event: &TodoEventModel) -> i64 {
+    event.position.unwrap_or_default()
+}
+
+/// Whether the change of `field` in an event's `changes` was from or to one
+/// of the ids, or to or from a set with one of them.
+fn mentions(changes: &Value, field: &str, ids: &[Uuid]) -> bool {
+    let values = ["from", "to"].map(|side| &changes[field][side]);
+    values.iter().any(|value| {
+        let candidates = match value {
+            Value::Array(items) => items.iter().collect(),
+            value => vec![*value],
+        };
+        candidates.into_iter().any(|candidate| {
+            candidate
+                .as_str()
+                .and_then(|id| id.parse().ok())
+                .is_some_and(|id: Uuid| ids.contains(&id))
+        })
+    })
+}
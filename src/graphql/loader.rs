@@ -0,0 +1,200 @@
+use crate::{
+    model::{ListModel, ToDoModel, TodoEventModel},
+    store::{self, share::Access},
+};
+use async_graphql::dataloader::Loader;
+use sqlx::{Pool, Postgres};
+use std::{collections::HashMap, sync::Arc};
+use uuid::Uuid;
+
+/// Loads todos by id, the todos of lists and the subtasks of todos, each
+/// kind with one query for all the keys requested together. The caller
+/// checks access.
+pub struct TodoLoader {
+    db: Pool<Postgres>,
+}
+
+impl TodoLoader {
+    pub fn new(db: Pool<Postgres>) -> Self {
+        TodoLoader { db }
+    }
+}
+
+/// The todos of a list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListTodos(pub Uuid);
+
+/// The direct subtasks of a todo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Subtasks(pub Uuid);
+
+impl Loader<Uuid> for TodoLoader {
+    type Value = ToDoModel;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, ToDoModel>, Self::Error> {
+        let mut conn = self.db.acquire().await?;
+        let todos = store::todo::find_many(&mut conn, keys).await?;
+
+        Ok(todos.into_iter().map(|todo| (todo.id, todo)).collect())
+    }
+}
+
+impl Loader<ListTodos> for TodoLoader {
+    type Value = Vec<ToDoModel>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(
+        &self,
+        keys: &[ListTodos],
+    ) -> Result<HashMap<ListTodos, Vec<ToDoModel>>, Self::Error> {
+        let list_ids: Vec<Uuid> = keys.iter().map(|key| key.0).collect();
+        let mut conn = self.db.acquire().await?;
+        let todos = store::todo::in_lists(&mut conn, &list_ids).await?;
+
+        let mut by_list: HashMap<ListTodos, Vec<ToDoModel>> =
+            keys.iter().map(|key| (*key, Vec::new())).collect();
+        for todo in todos {
+            if let Some(list_id) = todo.list_id {
+                by_list.entry(ListTodos(list_id)).or_default().push(todo);
+            }
+        }
+        Ok(by_list)
+    }
+}
+
+impl Loader<Subtasks> for TodoLoader {
+    type Value = Vec<ToDoModel>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(
+        &self,
+        keys: &[Subtasks],
+    ) -> Result<HashMap<Subtasks, Vec<ToDoModel>>, Self::Error> {
+        let parent_ids: Vec<Uuid> = keys.iter().map(|key| key.0).collect();
+        let mut conn = self.db.acquire().await?;
+        let todos = store::todo::children_of(&mut conn, &parent_ids).await?;
+
+        let mut by_parent: HashMap<Subtasks, Vec<ToDoModel>> =
+            keys.iter().map(|key| (*key, Vec::new())).collect();
+        for todo in todos {
+            if let Some(parent_id) = todo.parent_id {
+                by_parent.entry(Subtasks(parent_id)).or_default().push(todo);
+            }
+        }
+        Ok(by_parent)
+    }
+}
+
+/// Loads lists by id, for all the keys requested together at once. The
+/// caller checks access.
+pub struct ListLoader {
+    db: Pool<Postgres>,
+}
+
+impl ListLoader {
+    pub fn new(db: Pool<Postgres>) -> Self {
+        ListLoader { db }
+    }
+}
+
+impl Loader<Uuid> for ListLoader {
+    type Value = ListModel;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[Uuid]) -> Result<HashMap<Uuid, ListModel>, Self::Error> {
+        let lists = store::list::find_many(&self.db, keys).await?;
+
+        Ok(lists.into_iter().map(|list| (list.id, list)).collect())
+    }
+}
+
+/// Loads the viewer's access to todos and lists, for all the keys requested
+/// together at once. Absent where the viewer may not see them.
+pub struct AccessLoader {
+    db: Pool<Postgres>,
+    user_id: Uuid,
+}
+
+impl AccessLoader {
+    pub fn new(db: Pool<Postgres>, user_id: Uuid) -> Self {
+        AccessLoader { db, user_id }
+    }
+}
+
+/// The viewer's access to a todo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TodoAccess(pub Uuid);
+
+/// The viewer's access to a list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListAccess(pub Uuid);
+
+impl Loader<TodoAccess> for AccessLoader {
+    type Value = Access;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[TodoAccess]) -> Result<HashMap<TodoAccess, Access>, Self::Error> {
+        let todo_ids: Vec<Uuid> = keys.iter().map(|key| key.0).collect();
+        let access = store::share::todos_access(&self.db, self.user_id, &todo_ids).await?;
+
+        Ok(access
+            .into_iter()
+            .map(|(id, access)| (TodoAccess(id), access))
+            .collect())
+    }
+}
+
+impl Loader<ListAccess> for AccessLoader {
+    type Value = Access;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, keys: &[ListAccess]) -> Result<HashMap<ListAccess, Access>, Self::Error> {
+        let list_ids: Vec<Uuid> = keys.iter().map(|key| key.0).collect();
+        let access = store::share::lists_access(&self.db, self.user_id, &list_ids).await?;
+
+        Ok(access
+            .into_iter()
+            .map(|(id, access)| (ListAccess(id), access))
+            .collect())
+    }
+}
+
+/// Loads the history of todos, for all the keys requested together at once.
+/// The caller checks access.
+pub struct EventLoader {
+    db: Pool<Postgres>,
+}
+
+impl EventLoader {
+    pub fn new(db: Pool<Postgres>) -> Self {
+        EventLoader { db }
+    }
+}
+
+/// The events of a todo, oldest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct History(pub Uuid);
+
+impl Loader<History> for EventLoader {
+    type Value = Vec<TodoEventModel>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(
+        &self,
+        keys: &[History],
+    ) -> Result<HashMap<History, Vec<TodoEventModel>>, Self::Error> {
+        let todo_ids: Vec<Uuid> = keys.iter().map(|key| key.0).collect();
+        let events = store::event::histories(&self.db, &todo_ids).await?;
+
+        let mut by_todo: HashMap<History, Vec<TodoEventModel>> =
+            keys.iter().map(|key| (*key, Vec::new())).collect();
+        for event in events {
+            by_todo
+                .entry(History(event.todo_id))
+                .or_default()
+                .push(event);
+        }
+        Ok(by_todo)
+    }
+}
@@ -0,0 +1,155 @@
+//! The GraphQL API at `/graphql`, for clients that want the user, their
+//! lists, todos and tags in one round trip. Requests pass the `auth`
+//! middleware like any other; the user and credential it found reach the
+//! resolvers as context data, together with loaders that batch the lookups
+//! of nested fields and live as long as the request. Subscriptions are
+//! served over a WebSocket at `/graphql/ws`.
+
+mod loader;
+mod mutation;
+mod query;
+mod subscription;
+mod types;
+
+use crate::{
+    auth::Credential,
+    config::Config,
+    handlers::todo::Denied,
+    model::UserModel,
+    store::{
+        self,
+        todo::{Failure, WriteError},
+    },
+    AppState,
+};
+use async_graphql::{dataloader::DataLoader, Context, Data, Error, ErrorExtensions, Guard, Schema};
+use loader::{AccessLoader, EventLoader, ListLoader, TodoLoader};
+use mutation::Mutation;
+use query::Query;
+use std::{fmt::Debug, sync::Arc};
+use subscription::Subscription;
+use uuid::Uuid;
+
+pub type TodoSchema = Schema<Query, Mutation, Subscription>;
+
+pub fn schema(config: &Config) -> TodoSchema {
+    Schema::build(Query, Mutation, Subscription)
+        .limit_depth(config.graphql_max_depth)
+        .limit_complexity(config.graphql_max_complexity)
+        .finish()
+}
+
+/// What the resolvers of a request or subscription work with.
+pub fn context(data: &Arc<AppState>, user: UserModel, credential: Credential) -> Data {
+    let mut context = Data::default();
+    context.insert(DataLoader::new(
+        TodoLoader::new(data.db.clone()),
+        tokio::spawn,
+    ));
+    context.insert(DataLoader::new(
+        ListLoader::new(data.db.clone()),
+        tokio::spawn,
+    ));
+    context.insert(DataLoader::new(
+        AccessLoader::new(data.db.clone(), user.id),
+        tokio::spawn,
+    ));
+    context.insert(DataLoader::new(
+        EventLoader::new(data.db.clone()),
+        tokio::spawn,
+    ));
+    context.insert(data.clone());
+    context.insert(user);
+    context.insert(credential);
+    context
+}
+
+fn state<'a>(ctx: &Context<'a>) -> &'a Arc<AppState> {
+    ctx.data_unchecked()
+}
+
+/// The signed in user.
+fn viewer<'a>(ctx: &Context<'a>) -> &'a UserModel {
+    ctx.data_unchecked()
+}
+
+/// Requires a scope of the credential, like `todo_scope` does for REST.
+struct Scope(&'static str);
+
+const READ: Scope = Scope("todos:read");
+const WRITE: Scope = Scope("todos:write");
+
+impl Guard for Scope {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        let credential: &Credential = ctx.data_unchecked();
+        credential
+            .require_scope(self.0)
+            .map_err(|message| error("FORBIDDEN", message))
+    }
+}
+
+/// Rejects personal access tokens, like `session_only` does for REST.
+struct SessionOnly;
+
+impl Guard for SessionOnly {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        ctx.data_unchecked::<Credential>()
+            .require_session()
+            .map_err(|message| error("FORBIDDEN", message))
+    }
+}
+
+/// An error with a machine readable `code` extension, named after the HTTP
+/// status the REST endpoint answers with.
+fn error(code: &'static str, message: impl Into<String>) -> Error {
+    Error::new(message).extend_with(|_, extensions| extensions.set("code", code))
+}
+
+fn database_error(err: impl Debug) -> Error {
+    error("INTERNAL_SERVER_ERROR", format!("{:?}", err))
+}
+
+fn todo_not_found(id: Uuid) -> Error {
+    error("NOT_FOUND", format!("ToDo with ID: {} not found", id))
+}
+
+fn denied(denied: Denied, id: Uuid) -> Error {
+    match denied {
+        Denied::NotFound => todo_not_found(id),
+        Denied::Forbidden => error(
+            "FORBIDDEN",
+            format!("You are not allowed to do that with ToDo with ID: {}", id),
+        ),
+        Denied::Database(err) => database_error(err),
+    }
+}
+
+/// Explains a failed write the way `handlers::todo` does.
+async fn write_failed(data: &AppState, owner_id: Uuid, id: Uuid, err: WriteError) -> Error {
+    if let WriteError::Rejected = err {
+        let current = match data.db.acquire().await {
+            Ok(mut conn) => store::todo::find(&mut conn, owner_id, id).await,
+            Err(err) => Err(err),
+        };
+        return match current {
+            Ok(Some(todo)) => error(
+                "PRECONDITION_FAILED",
+                format!(
+                    "ToDo with ID: {} was modified, current version is {}",
+                    id, todo.version
+                ),
+            ),
+            Ok(None) => todo_not_found(id),
+            Err(err) => database_error(err),
+        };
+    }
+
+    let (failure, message) = err.classify(id);
+    let code = match failure {
+        Failure::NotFound => "NOT_FOUND",
+        Failure::Conflict => "CONFLICT",
+        Failure::Invalid => "UNPROCESSABLE_ENTITY",
+        Failure::Internal => "INTERNAL_SERVER_ERROR",
+    };
+    error(code, message)
+}
@@ -0,0 +1,269 @@
+use super::{
+    database_error, denied, error, state,
+    types::{CreateTodoInput, Todo, UpdateTodoInput, User},
+    viewer, write_failed, SessionOnly, WRITE,
+};
+use crate::{
+    events::UserChangeKind,
+    handlers::{
+        auth::{announce, confirmed, password_hash, PHOTO_READ_ONLY, SIGN_IN_AGAIN},
+        todo::{authorize, creator, Denied},
+    },
+    model::{ToDoModel, UserModel},
+    recurrence,
+    schema::{CreateToDo, UpdateToDo},
+    store::{
+        self,
+        share::Role,
+        todo::{Actor, WriteError},
+    },
+    AppState,
+};
+use async_graphql::{Context, Object, Result};
+use uuid::Uuid;
+
+/// Writes to a todo are conditional on the `version` the client last saw,
+/// like `If-Match` for REST.
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    #[graphql(guard = "WRITE")]
+    async fn create_todo(&self, ctx: &Context<'_>, input: CreateTodoInput) -> Result<Todo> {
+        let data = state(ctx);
+        let todo = CreateToDo::from(input);
+        recurrence::validate(todo.rrule.as_deref(), todo.timezone.as_deref())
+            .map_err(|err| error("BAD_REQUEST", err.to_string()))?;
+        let actor = creator(&data.db, viewer(ctx).id, &todo)
+            .await
+            .map_err(|err| match err {
+                Denied::Forbidden => error("FORBIDDEN", "You are not allowed to add ToDos there"),
+                err => denied(err, todo.parent_id.unwrap_or_default()),
+            })?;
+
+        let created = match data.db.acquire().await {
+            Ok(mut conn) => store::todo::create(&mut conn, actor, &todo).await,
+            Err(err) => Err(err.into()),
+        };
+        match created {
+            Ok(todo) => Ok(Todo(todo)),
+            Err(err) => Err(write_failed(data, actor.owner_id, Uuid::nil(), err).await),
+        }
+    }
+
+    /// Changes a todo. With `cascade` completing or reopening it does the
+    /// same to its subtasks.
+    #[graphql(guard = "WRITE")]
+    async fn update_todo(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        version: i32,
+        input: UpdateTodoInput,
+        #[graphql(default)] cascade: bool,
+    ) -> Result<Todo> {
+        let data = state(ctx);
+        let changes = UpdateToDo::from(input);
+        recurrence::validate(
+            changes.rrule.clone().flatten().as_deref(),
+            changes.timezone.as_deref(),
+        )
+        .map_err(|err| error("BAD_REQUEST", err.to_string()))?;
+        let actor = editor(data, viewer(ctx), id).await?;
+
+        let updated = match data.db.acquire().await {
+            Ok(mut conn) => {
+                store::todo::update(&mut conn, actor, id, Some(version), &changes, cascade).await
+            }
+            Err(err) => Err(err.into()),
+        };
+        written(data, actor, id, updated).await
+    }
+
+    /// Moves a todo to the trash, by default with its subtasks. Returns
+    /// whether it was there to delete.
+    #[graphql(guard = "WRITE")]
+    async fn delete_todo(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        version: i32,
+        #[graphql(default = true)] cascade: bool,
+    ) -> Result<bool> {
+        let data = state(ctx);
+        let actor = editor(data, viewer(ctx), id).await?;
+
+        let deleted = match data.db.acquire().await {
+            Ok(mut conn) => store::todo::delete(&mut conn, actor, id, Some(version), cascade).await,
+            Err(err) => Err(err),
+        }
+        .map_err(database_error)?;
+        if !deleted {
+            return Err(write_failed(data, actor.owner_id, id, WriteError::Rejected).await);
+        }
+        Ok(true)
+    }
+
+    /// Moves a todo in the manual order, right after `after` or right before
+    /// `before`.
+    #[graphql(guard = "WRITE")]
+    async fn move_todo(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        version: i32,
+        after: Option<Uuid>,
+        before: Option<Uuid>,
+    ) -> Result<Todo> {
+        if after.is_none() && before.is_none() {
+            return Err(error("BAD_REQUEST", "Either after or before is required"));
+        }
+
+        let data = state(ctx);
+        let actor = editor(data, viewer(ctx), id).await?;
+        let moved = match data.db.acquire().await {
+            Ok(mut conn) => {
+                store::todo::reorder(&mut conn, actor, id, Some(version), after, before).await
+            }
+            Err(err) => Err(err.into()),
+        };
+        written(data, actor, id, moved).await
+    }
+
+    /// Restores an earlier revision of a todo as a new one.
+    #[graphql(guard = "WRITE")]
+    async fn revert_todo(
+        &self,
+        ctx: &Context<'_>,
+        id: Uuid,
+        version: i32,
+        revision: i32,
+    ) -> Result<Todo> {
+        let data = state(ctx);
+        let actor = editor(data, viewer(ctx), id).await?;
+        let reverted = match data.db.acquire().await {
+            Ok(mut conn) => {
+                store::todo::revert(&mut conn, actor, id, Some(version), revision).await
+            }
+            Err(err) => Err(err.into()),
+        };
+        written(data, actor, id, reverted).await
+    }
+
+    /// Takes a todo out of the trash together with the subtasks trashed
+    /// with it.
+    #[graphql(guard = "WRITE")]
+    async fn restore_todo(&self, ctx: &Context<'_>, id: Uuid) -> Result<Todo> {
+        let data = state(ctx);
+        let actor = editor(data, viewer(ctx), id).await?;
+        let restored = match data.db.acquire().await {
+            Ok(mut conn) => store::todo::restore(&mut conn, actor, id).await,
+            Err(err) => Err(err),
+        };
+        let restored = match restored {
+            Ok(restored) => restored,
+            Err(err) => {
+                return Err(write_failed(data, actor.owner_id, id, WriteError::Database(err)).await)
+            }
+        }
+        .ok_or_else(|| {
+            error(
+                "NOT_FOUND",
+                format!("ToDo with ID: {} is not in the trash", id),
+            )
+        })?;
+        Ok(Todo(restored))
+    }
+
+    #[graphql(guard = "SessionOnly")]
+    async fn update_me(
+        &self,
+        ctx: &Context<'_>,
+        name: Option<String>,
+        photo: Option<String>,
+    ) -> Result<User> {
+        if name.as_ref().is_some_and(|name| name.trim().is_empty()) {
+            return Err(error("BAD_REQUEST", "Name must not be empty"));
+        }
+
+        let data = state(ctx);
+        let user = viewer(ctx);
+        if photo.is_some_and(|photo| photo != user.photo) {
+            return Err(error("BAD_REQUEST", PHOTO_READ_ONLY));
+        }
+        let updated = sqlx::query_as!(
+            UserModel,
+            "UPDATE users SET name = $1, updated_at = $2 WHERE id = $3 RETURNING *",
+            name.map_or(user.name.clone(), |name| name.trim().to_string()),
+            chrono::Utc::now(),
+            user.id
+        )
+        .fetch_one(&data.db)
+        .await
+        .map_err(database_error)?;
+        announce(data, user.id, UserChangeKind::Updated).await;
+
+        Ok(User(updated))
+    }
+
+    /// Accounts created through a login provider may set a first password
+    /// without a current one, right after signing in through it.
+    #[graphql(guard = "SessionOnly")]
+    async fn change_password(
+        &self,
+        ctx: &Context<'_>,
+        current_password: Option<String>,
+        new_password: String,
+    ) -> Result<bool> {
+        let data = state(ctx);
+        let user = viewer(ctx);
+        if !confirmed(user, ctx.data_unchecked(), current_password.as_deref()) {
+            return Err(if user.password.is_empty() {
+                error("FORBIDDEN", SIGN_IN_AGAIN)
+            } else {
+                error("BAD_REQUEST", "Current password is incorrect")
+            });
+        }
+        if new_password.is_empty() {
+            return Err(error("BAD_REQUEST", "New password must not be empty"));
+        }
+
+        let hash = password_hash(&new_password).map_err(|err| {
+            error(
+                "INTERNAL_SERVER_ERROR",
+                format!("Error while hashing password: {}", err),
+            )
+        })?;
+        sqlx::query!(
+            "UPDATE users SET password = $1, updated_at = $2 WHERE id = $3",
+            hash,
+            chrono::Utc::now(),
+            user.id
+        )
+        .execute(&data.db)
+        .await
+        .map_err(database_error)?;
+        announce(data, user.id, UserChangeKind::Updated).await;
+
+        Ok(true)
+    }
+}
+
+/// Checks that the user may change the todo.
+async fn editor(data: &AppState, user: &UserModel, id: Uuid) -> Result<Actor> {
+    authorize(&data.db, user.id, id, Role::Editor)
+        .await
+        .map_err(|err| denied(err, id))
+}
+
+async fn written(
+    data: &AppState,
+    actor: Actor,
+    id: Uuid,
+    result: Result<ToDoModel, WriteError>,
+) -> Result<Todo> {
+    match result {
+        Ok(todo) => Ok(Todo(todo)),
+        Err(err) => Err(write_failed(data, actor.owner_id, id, err).await),
+    }
+}
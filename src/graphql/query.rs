@@ -0,0 +1,99 @@
+use super::{
+    database_error, denied, error, state,
+    types::{List, Tag, Todo, TodoFilter, User, FAN_OUT},
+    viewer, READ,
+};
+use crate::{
+    handlers::todo::{authorize, fetch_todos, Denied},
+    store::{self, share::Role},
+};
+use async_graphql::{Context, Object, Result};
+use uuid::Uuid;
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// The signed in user.
+    async fn me(&self, ctx: &Context<'_>) -> User {
+        User(viewer(ctx).clone())
+    }
+
+    /// A todo of the user or one shared with them.
+    #[graphql(guard = "READ")]
+    async fn todo(&self, ctx: &Context<'_>, id: Uuid) -> Result<Option<Todo>> {
+        let data = state(ctx);
+        let actor = match authorize(&data.db, viewer(ctx).id, id, Role::Viewer).await {
+            Ok(actor) => actor,
+            Err(Denied::NotFound) => return Ok(None),
+            Err(err) => return Err(denied(err, id)),
+        };
+
+        let mut conn = data.db.acquire().await.map_err(database_error)?;
+        let todo = store::todo::find(&mut conn, actor.owner_id, id)
+            .await
+            .map_err(database_error)?;
+        Ok(todo.map(Todo))
+    }
+
+    /// A page of the user's todos, or of those in a list or below a todo
+    /// shared with them. `sort` is a field such as `dueAt`, prefixed with `-`
+    /// for descending order.
+    #[graphql(guard = "READ", complexity = "limit * child_complexity")]
+    async fn todos(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<TodoFilter>,
+        #[graphql(default = 1, validator(minimum = 1))] page: usize,
+        #[graphql(default = 10, validator(minimum = 1, maximum = 100))] limit: usize,
+        #[graphql(default = "position")] sort: String,
+    ) -> Result<Vec<Todo>> {
+        let order = store::todo::order_by(&sort)
+            .ok_or_else(|| error("BAD_REQUEST", format!("Cannot sort by {}", sort)))?;
+        let options = filter.unwrap_or_default().options(page, limit, Some(sort));
+
+        let todos = fetch_todos(state(ctx), viewer(ctx).id, &options, &order)
+            .await
+            .map_err(database_error)?;
+        Ok(todos.into_iter().map(Todo).collect())
+    }
+
+    /// The user's active lists, or the archived ones, by name.
+    #[graphql(guard = "READ", complexity = "FAN_OUT * child_complexity")]
+    async fn lists(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] archived: bool,
+    ) -> Result<Vec<List>> {
+        let lists = store::list::list(&state(ctx).db, viewer(ctx).id, archived)
+            .await
+            .map_err(database_error)?;
+        Ok(lists.into_iter().map(List).collect())
+    }
+
+    /// A list of the user or one shared with them.
+    #[graphql(guard = "READ")]
+    async fn list(&self, ctx: &Context<'_>, id: Uuid) -> Result<Option<List>> {
+        let data = state(ctx);
+        let Some(access) = store::share::list_access(&data.db, viewer(ctx).id, id)
+            .await
+            .map_err(database_error)?
+        else {
+            return Ok(None);
+        };
+
+        let list = store::list::find(&data.db, access.owner_id, id)
+            .await
+            .map_err(database_error)?;
+        Ok(list.map(List))
+    }
+
+    /// The user's tags by name.
+    #[graphql(guard = "READ")]
+    async fn tags(&self, ctx: &Context<'_>) -> Result<Vec<Tag>> {
+        let tags = store::tag::list(&state(ctx).db, viewer(ctx).id)
+            .await
+            .map_err(database_error)?;
+        Ok(tags.into_iter().map(Tag).collect())
+    }
+}
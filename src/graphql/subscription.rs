@@ -0,0 +1,66 @@
+use super::{
+    database_error, state,
+    types::{FeedReset, TodoEvent, TodoUpdate},
+    viewer, READ,
+};
+use crate::{
+    events::{Feed, Update},
+    schema::StreamOptions,
+};
+use async_graphql::{Context, Result, Subscription as GraphQLSubscription};
+use futures::{stream, Stream};
+use std::collections::VecDeque;
+use uuid::Uuid;
+
+pub struct Subscription;
+
+#[GraphQLSubscription]
+impl Subscription {
+    /// Changes to the todos the user can see, as they happen. With the
+    /// `position` of the last event seen as `lastEventId` the events missed
+    /// since then come first.
+    #[graphql(guard = "READ")]
+    async fn todo_events(
+        &self,
+        ctx: &Context<'_>,
+        list_id: Option<Uuid>,
+        tags: Option<Vec<Uuid>>,
+        last_event_id: Option<i64>,
+    ) -> Result<impl Stream<Item = TodoUpdate>> {
+        let data = state(ctx).clone();
+        let options = StreamOptions {
+            last_event_id,
+            list_id,
+            tags,
+        };
+        let (feed, missed) = Feed::open(&data, viewer(ctx).id, options)
+            .await
+            .map_err(database_error)?;
+
+        let pending = VecDeque::from(missed);
+        Ok(stream::unfold(
+            (feed, pending, data),
+            |(mut feed, mut pending, data)| async move {
+                loop {
+                    if let Some(update) = pending.pop_front() {
+                        let update = match update {
+                            Update::Event(event) => TodoUpdate::Event(TodoEvent(event)),
+                            Update::Reset(position) => TodoUpdate::Reset(FeedReset { position }),
+                        };
+                        return Some((update, (feed, pending, data)));
+                    }
+
+                    let received = feed.recv().await;
+                    match feed.handle(&data.db, received).await {
+                        Ok(Some(updates)) => pending.extend(updates),
+                        Ok(None) => return None,
+                        Err(err) => {
+                            println!("🔥 Failed to follow todo events: {:?}", err);
+                            return None;
+                        }
+                    }
+                }
+            },
+        ))
+    }
+}
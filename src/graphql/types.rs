@@ -0,0 +1,479 @@
+use super::{
+    database_error,
+    loader::{
+        AccessLoader, EventLoader, History, ListAccess, ListLoader, ListTodos, Subtasks,
+        TodoAccess, TodoLoader,
+    },
+    viewer,
+};
+use crate::{
+    events,
+    model::{ListModel, TagModel, ToDoModel, TodoEventModel, UserModel},
+    schema::{CreateToDo, FilterOptions, TagMode, UpdateToDo},
+};
+use async_graphql::{
+    dataloader::DataLoader, Context, Enum, InputObject, Json, MaybeUndefined, Object, Result,
+    SimpleObject, Union,
+};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// What a field returning all todos of a list or all subtasks counts for
+/// towards the complexity limit, per field selected below it.
+pub const FAN_OUT: usize = 10;
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(name = "Priority", remote = "crate::model::Priority")]
+pub enum PriorityValue {
+    Low,
+    Normal,
+    High,
+    Urgent,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(name = "TagMode", remote = "crate::schema::TagMode")]
+pub enum TagModeValue {
+    Any,
+    All,
+}
+
+pub struct User(pub UserModel);
+
+#[Object]
+impl User {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn mail(&self) -> &str {
+        &self.0.mail
+    }
+
+    async fn role(&self) -> &str {
+        &self.0.role
+    }
+
+    async fn photo(&self) -> &str {
+        &self.0.photo
+    }
+
+    async fn verify(&self) -> Option<bool> {
+        self.0.verify
+    }
+
+    async fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.0.created_at
+    }
+
+    async fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.0.updated_at
+    }
+}
+
+pub struct Todo(pub ToDoModel);
+
+#[Object]
+impl Todo {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn title(&self) -> &str {
+        &self.0.title
+    }
+
+    async fn content(&self) -> &str {
+        &self.0.content
+    }
+
+    async fn complete(&self) -> Option<bool> {
+        self.0.complete
+    }
+
+    async fn created_at(&self) -> Option<DateTime<Utc>> {
+        self.0.created_at
+    }
+
+    async fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.0.updated_at
+    }
+
+    async fn user_id(&self) -> Option<Uuid> {
+        self.0.user_id
+    }
+
+    async fn version(&self) -> i32 {
+        self.0.version
+    }
+
+    async fn due_at(&self) -> Option<DateTime<Utc>> {
+        self.0.due_at
+    }
+
+    async fn priority(&self) -> PriorityValue {
+        self.0.priority.into()
+    }
+
+    async fn completed_at(&self) -> Option<DateTime<Utc>> {
+        self.0.completed_at
+    }
+
+    async fn rrule(&self) -> Option<&str> {
+        self.0.rrule.as_deref()
+    }
+
+    async fn timezone(&self) -> &str {
+        &self.0.timezone
+    }
+
+    async fn list_id(&self) -> Option<Uuid> {
+        self.0.list_id
+    }
+
+    async fn parent_id(&self) -> Option<Uuid> {
+        self.0.parent_id
+    }
+
+    async fn position(&self) -> &str {
+        &self.0.position
+    }
+
+    async fn tags(&self) -> Vec<Tag> {
+        self.0.tags.iter().cloned().map(Tag).collect()
+    }
+
+    /// The todo's list, if the user may see it. A todo shared on its own
+    /// does not share its list.
+    async fn list(&self, ctx: &Context<'_>) -> Result<Option<List>> {
+        let Some(list_id) = self.0.list_id else {
+            return Ok(None);
+        };
+        if self.0.user_id != Some(viewer(ctx).id) {
+            let access = ctx
+                .data_unchecked::<DataLoader<AccessLoader>>()
+                .load_one(ListAccess(list_id))
+                .await
+                .map_err(database_error)?;
+            if access.is_none() {
+                return Ok(None);
+            }
+        }
+
+        let list = ctx
+            .data_unchecked::<DataLoader<ListLoader>>()
+            .load_one(list_id)
+            .await
+            .map_err(database_error)?;
+        Ok(list.map(List))
+    }
+
+    /// The todo this is a subtask of, if the user may see it.
+    async fn parent(&self, ctx: &Context<'_>) -> Result<Option<Todo>> {
+        let Some(parent_id) = self.0.parent_id else {
+            return Ok(None);
+        };
+        if self.0.user_id != Some(viewer(ctx).id) {
+            let access = ctx
+                .data_unchecked::<DataLoader<AccessLoader>>()
+                .load_one(TodoAccess(parent_id))
+                .await
+                .map_err(database_error)?;
+            if access.is_none() {
+                return Ok(None);
+            }
+        }
+
+        let parent = ctx
+            .data_unchecked::<DataLoader<TodoLoader>>()
+            .load_one(parent_id)
+            .await
+            .map_err(database_error)?;
+        Ok(parent.map(Todo))
+    }
+
+    /// The direct subtasks in the manual order. Whoever may see a todo may
+    /// see its subtasks.
+    #[graphql(complexity = "FAN_OUT * child_complexity")]
+    async fn subtasks(&self, ctx: &Context<'_>) -> Result<Vec<Todo>> {
+        let subtasks = ctx
+            .data_unchecked::<DataLoader<TodoLoader>>()
+            .load_one(Subtasks(self.0.id))
+            .await
+            .map_err(database_error)?
+            .unwrap_or_default();
+        Ok(subtasks.into_iter().map(Todo).collect())
+    }
+
+    /// The revisions of the todo, oldest first.
+    #[graphql(complexity = "FAN_OUT * child_complexity")]
+    async fn history(&self, ctx: &Context<'_>) -> Result<Vec<TodoEvent>> {
+        let events = ctx
+            .data_unchecked::<DataLoader<EventLoader>>()
+            .load_one(History(self.0.id))
+            .await
+            .map_err(database_error)?
+            .unwrap_or_default();
+        Ok(events
+            .into_iter()
+            .map(|event| TodoEvent(Arc::new(event)))
+            .collect())
+    }
+}
+
+pub struct List(pub ListModel);
+
+#[Object]
+impl List {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn user_id(&self) -> Uuid {
+        self.0.user_id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn archived_at(&self) -> Option<DateTime<Utc>> {
+        self.0.archived_at
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    async fn updated_at(&self) -> DateTime<Utc> {
+        self.0.updated_at
+    }
+
+    async fn todo_count(&self) -> i64 {
+        self.0.todo_count
+    }
+
+    async fn completed_count(&self) -> i64 {
+        self.0.completed_count
+    }
+
+    /// Completed todos in percent, 0 for an empty list.
+    async fn completion(&self) -> f64 {
+        self.0.completion
+    }
+
+    /// The todos in the list in the manual order, subtasks included.
+    #[graphql(complexity = "FAN_OUT * child_complexity")]
+    async fn todos(&self, ctx: &Context<'_>) -> Result<Vec<Todo>> {
+        let todos = ctx
+            .data_unchecked::<DataLoader<TodoLoader>>()
+            .load_one(ListTodos(self.0.id))
+            .await
+            .map_err(database_error)?
+            .unwrap_or_default();
+        Ok(todos.into_iter().map(Todo).collect())
+    }
+}
+
+pub struct Tag(pub TagModel);
+
+#[Object]
+impl Tag {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn user_id(&self) -> Uuid {
+        self.0.user_id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn color(&self) -> Option<&str> {
+        self.0.color.as_deref()
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+}
+
+/// A change to a todo, as recorded in its history.
+pub struct TodoEvent(pub Arc<TodoEventModel>);
+
+#[Object]
+impl TodoEvent {
+    async fn id(&self) -> i64 {
+        self.0.id
+    }
+
+    async fn todo_id(&self) -> Uuid {
+        self.0.todo_id
+    }
+
+    async fn user_id(&self) -> Uuid {
+        self.0.user_id
+    }
+
+    async fn actor_id(&self) -> Option<Uuid> {
+        self.0.actor_id
+    }
+
+    async fn kind(&self) -> &str {
+        &self.0.kind
+    }
+
+    async fn version(&self) -> i32 {
+        self.0.version
+    }
+
+    /// The fields that changed, each with `from` and `to`.
+    async fn changes(&self) -> Json<&serde_json::Value> {
+        Json(&self.0.changes)
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    /// Where the event falls in the order changes committed in, what
+    /// `lastEventId` resumes after.
+    async fn position(&self) -> i64 {
+        events::position(&self.0)
+    }
+
+    /// The todo as it is now, `null` once it is in the trash or gone.
+    async fn todo(&self, ctx: &Context<'_>) -> Result<Option<Todo>> {
+        let todo = ctx
+            .data_unchecked::<DataLoader<TodoLoader>>()
+            .load_one(self.0.todo_id)
+            .await
+            .map_err(database_error)?;
+        Ok(todo.map(Todo))
+    }
+}
+
+/// More events were missed than can be replayed. Reload everything and go on
+/// after `position`.
+#[derive(SimpleObject)]
+pub struct FeedReset {
+    pub position: i64,
+}
+
+#[derive(Union)]
+pub enum TodoUpdate {
+    Event(TodoEvent),
+    Reset(FeedReset),
+}
+
+#[derive(InputObject, Default)]
+pub struct TodoFilter {
+    pub complete: Option<bool>,
+    pub priority: Option<PriorityValue>,
+    pub due_before: Option<DateTime<Utc>>,
+    pub due_after: Option<DateTime<Utc>>,
+    /// Only open todos whose due date has passed.
+    pub overdue: Option<bool>,
+    pub list_id: Option<Uuid>,
+    /// Direct subtasks of this todo.
+    pub parent_id: Option<Uuid>,
+    pub tags: Option<Vec<Uuid>>,
+    /// Whether todos need any or all of `tags`.
+    pub tag_mode: Option<TagModeValue>,
+}
+
+impl TodoFilter {
+    pub fn options(self, page: usize, limit: usize, sort: Option<String>) -> FilterOptions {
+        FilterOptions {
+            page: Some(page),
+            limit: Some(limit),
+            complete: self.complete,
+            priority: self.priority.map(Into::into),
+            due_before: self.due_before,
+            due_after: self.due_after,
+            overdue: self.overdue,
+            list_id: self.list_id,
+            parent_id: self.parent_id,
+            tags: self.tags,
+            tag_mode: self.tag_mode.map(TagMode::from),
+            sort,
+        }
+    }
+}
+
+#[derive(InputObject)]
+pub struct CreateTodoInput {
+    pub title: String,
+    pub content: String,
+    pub complete: Option<bool>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub priority: Option<PriorityValue>,
+    /// iCalendar RRULE, e.g. `FREQ=WEEKLY;BYDAY=MO`. Needs a due date.
+    pub rrule: Option<String>,
+    /// IANA time zone the rule is expanded in, `UTC` by default.
+    pub timezone: Option<String>,
+    pub list_id: Option<Uuid>,
+    pub tags: Option<Vec<Uuid>>,
+    /// Makes the todo a subtask.
+    pub parent_id: Option<Uuid>,
+}
+
+impl From<CreateTodoInput> for CreateToDo {
+    fn from(input: CreateTodoInput) -> Self {
+        CreateToDo {
+            title: input.title,
+            content: input.content,
+            complete: input.complete,
+            due_at: input.due_at,
+            priority: input.priority.map(Into::into),
+            rrule: input.rrule,
+            timezone: input.timezone,
+            list_id: input.list_id,
+            tags: input.tags,
+            parent_id: input.parent_id,
+        }
+    }
+}
+
+/// Fields left out stay as they are, fields set to `null` are cleared.
+#[derive(InputObject)]
+pub struct UpdateTodoInput {
+    pub title: Option<String>,
+    pub content: Option<String>,
+    pub complete: Option<bool>,
+    pub due_at: MaybeUndefined<DateTime<Utc>>,
+    pub priority: Option<PriorityValue>,
+    /// `null` ends the series.
+    pub rrule: MaybeUndefined<String>,
+    pub timezone: Option<String>,
+    pub list_id: MaybeUndefined<Uuid>,
+    /// Tag ids replacing the current tags.
+    pub tags: Option<Vec<Uuid>>,
+    /// `null` makes the todo a top level todo.
+    pub parent_id: MaybeUndefined<Uuid>,
+}
+
+impl From<UpdateTodoInput> for UpdateToDo {
+    fn from(input: UpdateTodoInput) -> Self {
+        UpdateToDo {
+            title: input.title,
+            content: input.content,
+            complete: input.complete,
+            due_at: input.due_at.into(),
+            priority: input.priority.map(Into::into),
+            rrule: input.rrule.into(),
+            timezone: input.timezone,
+            list_id: input.list_id.into(),
+            tags: input.tags,
+            parent_id: input.parent_id.into(),
+            version: None,
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use super::proto;
+use crate::{
+    events::{self, Update},
+    model::{Priority, TagModel, ToDoModel, TodoEventModel, UserModel},
+    schema::TagMode,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use prost_types::Timestamp;
+use tonic::Status;
+use uuid::Uuid;
+
+pub fn timestamp(at: DateTime<Utc>) -> Timestamp {
+    Timestamp {
+        seconds: at.timestamp(),
+        nanos: at.timestamp_subsec_nanos() as i32,
+    }
+}
+
+pub fn date_time(field: &str, at: Timestamp) -> Result<DateTime<Utc>, Status> {
+    u32::try_from(at.nanos)
+        .ok()
+        .and_then(|nanos| Utc.timestamp_opt(at.seconds, nanos).single())
+        .ok_or_else(|| Status::invalid_argument(format!("{} is not a valid time", field)))
+}
+
+pub fn uuid(field: &str, id: &str) -> Result<Uuid, Status> {
+    Uuid::parse_str(id).map_err(|_| Status::invalid_argument(format!("{} is not a UUID", field)))
+}
+
+pub fn uuids(field: &str, ids: &[String]) -> Result<Vec<Uuid>, Status> {
+    ids.iter().map(|id| uuid(field, id)).collect()
+}
+
+pub fn optional_uuid(field: &str, id: Option<&str>) -> Result<Option<Uuid>, Status> {
+    id.map(|id| uuid(field, id)).transpose()
+}
+
+/// `None` for `PRIORITY_UNSPECIFIED`.
+pub fn priority(value: i32) -> Result<Option<Priority>, Status> {
+    match proto::Priority::from_i32(value) {
+        Some(proto::Priority::Unspecified) => Ok(None),
+        Some(proto::Priority::Low) => Ok(Some(Priority::Low)),
+        Some(proto::Priority::Normal) => Ok(Some(Priority::Normal)),
+        Some(proto::Priority::High) => Ok(Some(Priority::High)),
+        Some(proto::Priority::Urgent) => Ok(Some(Priority::Urgent)),
+        None => Err(Status::invalid_argument("Unknown priority")),
+    }
+}
+
+/// `None` for `TAG_MODE_UNSPECIFIED`.
+pub fn tag_mode(value: i32) -> Result<Option<TagMode>, Status> {
+    match proto::TagMode::from_i32(value) {
+        Some(proto::TagMode::Unspecified) => Ok(None),
+        Some(proto::TagMode::Any) => Ok(Some(TagMode::Any)),
+        Some(proto::TagMode::All) => Ok(Some(TagMode::All)),
+        None => Err(Status::invalid_argument("Unknown tag mode")),
+    }
+}
+
+impl From<Priority> for proto::Priority {
+    fn from(priority: Priority) -> Self {
+        match priority {
+            Priority::Low => proto::Priority::Low,
+            Priority::Normal => proto::Priority::Normal,
+            Priority::High => proto::Priority::High,
+            Priority::Urgent => proto::Priority::Urgent,
+        }
+    }
+}
+
+impl From<TagModel> for proto::Tag {
+    fn from(tag: TagModel) -> Self {
+        proto::Tag {
+            id: tag.id.to_string(),
+            user_id: tag.user_id.to_string(),
+            name: tag.name,
+            color: tag.color,
+            created_at: Some(timestamp(tag.created_at)),
+        }
+    }
+}
+
+impl From<ToDoModel> for proto::Todo {
+    fn from(todo: ToDoModel) -> Self {
+        proto::Todo {
+            id: todo.id.to_string(),
+            title: todo.title,
+            content: todo.content,
+            complete: todo.complete.unwrap_or_default(),
+            created_at: todo.created_at.map(timestamp),
+            updated_at: todo.updated_at.map(timestamp),
+            user_id: todo.user_id.map(|id| id.to_string()).unwrap_or_default(),
+            version: todo.version,
+            due_at: todo.due_at.map(timestamp),
+            priority: proto::Priority::from(todo.priority) as i32,
+            completed_at: todo.completed_at.map(timestamp),
+            rrule: todo.rrule,
+            timezone: todo.timezone,
+            list_id: todo.list_id.map(|id| id.to_string()),
+            parent_id: todo.parent_id.map(|id| id.to_string()),
+            position: todo.position,
+            tags: todo.tags.0.into_iter().map(proto::Tag::from).collect(),
+        }
+    }
+}
+
+impl From<UserModel> for proto::User {
+    fn from(user: UserModel) -> Self {
+        proto::User {
+            id: user.id.to_string(),
+            name: user.name,
+            mail: user.mail,
+            role: user.role,
+            photo: user.photo,
+            verify: user.verify.unwrap_or_default(),
+            created_at: user.created_at.map(timestamp),
+            updated_at: user.updated_at.map(timestamp),
+        }
+    }
+}
+
+impl From<&TodoEventModel> for proto::TodoEvent {
+    fn from(event: &TodoEventModel) -> Self {
+        proto::TodoEvent {
+            id: event.id,
+            todo_id: event.todo_id.to_string(),
+            user_id: event.user_id.to_string(),
+            actor_id: event.actor_id.map(|id| id.to_string()),
+            kind: event.kind.clone(),
+            version: event.version,
+            changes: event.changes.to_string(),
+            created_at: Some(timestamp(event.created_at)),
+            position: events::position(event),
+        }
+    }
+}
+
+impl From<Update> for proto::TodoUpdate {
+    fn from(update: Update) -> Self {
+        let update = match update {
+            Update::Event(event) => proto::todo_update::Update::Event(event.as_ref().into()),
+            Update::Reset(id) => proto::todo_update::Update::Reset(id),
+        };
+        proto::TodoUpdate {
+            update: Some(update),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! The gRPC API from `proto/todo.proto`, for backend services. It runs on
+//! its own port next to the HTTP server and works through the same stores
+//! and access checks as the REST handlers. Calls carry the token as
+//! `authorization: Bearer <token>` metadata; tooling can discover the
+//! services through reflection.
+
+// Every call fails with a `tonic::Status`, large as it is.
+#![allow(clippy::result_large_err)]
+
+mod convert;
+mod todos;
+mod users;
+
+pub mod proto {
+    tonic::include_proto!("todo.v1");
+
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("todo_descriptor");
+}
+
+use crate::{
+    auth::{self, Credential},
+    handlers::todo::Denied,
+    model::UserModel,
+    store::{
+        self,
+        todo::{Failure, WriteError},
+    },
+    AppState,
+};
+use axum::http::StatusCode;
+use proto::{todos_server::TodosServer, users_server::UsersServer};
+use std::{fmt::Debug, net::SocketAddr, sync::Arc};
+use todos::TodoService;
+use tonic::{transport::Server, Request, Status};
+use users::UserService;
+use uuid::Uuid;
+
+/// Serves the gRPC API until the process ends.
+pub async fn serve(data: Arc<AppState>) {
+    let addr = SocketAddr::from(([0, 0, 0, 0], data.env.grpc_port));
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .build()
+        .unwrap();
+
+    println!("🚀 gRPC server started on port {}", data.env.grpc_port);
+    let served = Server::builder()
+        .add_service(reflection)
+        .add_service(TodosServer::new(TodoService { data: data.clone() }))
+        .add_service(UsersServer::new(UserService { data }))
+        .serve(addr)
+        .await;
+    if let Err(err) = served {
+        println!("🔥 gRPC server failed: {:?}", err);
+    }
+}
+
+/// The user and credential behind the `authorization` metadata of a call,
+/// checked like the `auth` middleware does.
+async fn authenticate<T>(
+    data: &AppState,
+    request: &Request<T>,
+) -> Result<(UserModel, Credential), Status> {
+    let token = request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| Status::unauthenticated("You are not logged in, please provide token"))?;
+
+    auth::authenticate(data, token)
+        .await
+        .map_err(|(status, json)| match status {
+            StatusCode::UNAUTHORIZED => Status::unauthenticated(json.0.message),
+            _ => Status::internal(json.0.message),
+        })
+}
+
+/// Requires a scope of the credential, like `todo_scope` does for REST.
+fn require_scope(credential: &Credential, scope: &str) -> Result<(), Status> {
+    credential
+        .require_scope(scope)
+        .map_err(Status::permission_denied)
+}
+
+/// Rejects personal access tokens, like `session_only` does for REST.
+fn session_only(credential: &Credential) -> Result<(), Status> {
+    credential
+        .require_session()
+        .map_err(Status::permission_denied)
+}
+
+fn database_error(err: impl Debug) -> Status {
+    Status::internal(format!("{:?}", err))
+}
+
+fn todo_not_found(id: Uuid) -> Status {
+    Status::not_found(format!("ToDo with ID: {} not found", id))
+}
+
+fn denied(denied: Denied, id: Uuid) -> Status {
+    match denied {
+        Denied::NotFound => todo_not_found(id),
+        Denied::Forbidden => Status::permission_denied(format!(
+            "You are not allowed to do that with ToDo with ID: {}",
+            id
+        )),
+        Denied::Database(err) => database_error(err),
+    }
+}
+
+/// Explains a failed write the way `handlers::todo` does.
+async fn write_failed(data: &AppState, owner_id: Uuid, id: Uuid, err: WriteError) -> Status {
+    if let WriteError::Rejected = err {
+        let current = match data.db.acquire().await {
+            Ok(mut conn) => store::todo::find(&mut conn, owner_id, id).await,
+            Err(err) => Err(err),
+        };
+        return match current {
+            Ok(Some(todo)) => Status::aborted(format!(
+                "ToDo with ID: {} was modified, current version is {}",
+                id, todo.version
+            )),
+            Ok(None) => todo_not_found(id),
+            Err(err) => database_error(err),
+        };
+    }
+
+    let (failure, message) = err.classify(id);
+    match failure {
+        Failure::NotFound => Status::not_found(message),
+        Failure::Conflict => Status::already_exists(message),
+        Failure::Invalid => Status::failed_precondition(message),
+        Failure::Internal => Status::internal(message),
+    }
+}
@@ -0,0 +1,377 @@
+use super::{
+    authenticate,
+    convert::{date_time, optional_uuid, priority, tag_mode, uuid, uuids},
+    database_error, denied, proto, require_scope, write_failed,
+};
+use crate::{
+    events::{Feed, Update},
+    handlers::todo::{authorize, creator, fetch_todos, Denied},
+    model::{ToDoModel, UserModel},
+    recurrence,
+    schema::{CreateToDo, FilterOptions, StreamOptions, UpdateToDo},
+    store::{
+        self,
+        share::Role,
+        todo::{Actor, WriteError},
+    },
+    AppState,
+};
+use futures::{stream, Stream, TryStreamExt};
+use proto::todos_server::Todos;
+use std::{collections::VecDeque, pin::Pin, sync::Arc};
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+/// How many todos `ListTodos` reads from the database at a time.
+const PAGE_SIZE: usize = 100;
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+pub struct TodoService {
+    pub data: Arc<AppState>,
+}
+
+impl TodoService {
+    /// The signed in user, who needs `scope`.
+    async fn user<T>(&self, request: &Request<T>, scope: &str) -> Result<UserModel, Status> {
+        let (user, credential) = authenticate(&self.data, request).await?;
+        require_scope(&credential, scope)?;
+        Ok(user)
+    }
+
+    /// Checks that the user may change the todo.
+    async fn editor(&self, user: &UserModel, id: Uuid) -> Result<Actor, Status> {
+        authorize(&self.data.db, user.id, id, Role::Editor)
+            .await
+            .map_err(|err| denied(err, id))
+    }
+
+    async fn written(
+        &self,
+        actor: Actor,
+        id: Uuid,
+        result: Result<ToDoModel, WriteError>,
+    ) -> Result<Response<proto::Todo>, Status> {
+        match result {
+            Ok(todo) => Ok(Response::new(todo.into())),
+            Err(err) => Err(write_failed(&self.data, actor.owner_id, id, err).await),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Todos for TodoService {
+    async fn get_todo(
+        &self,
+        request: Request<proto::GetTodoRequest>,
+    ) -> Result<Response<proto::Todo>, Status> {
+        let user = self.user(&request, "todos:read").await?;
+        let id = uuid("id", &request.get_ref().id)?;
+        let actor = authorize(&self.data.db, user.id, id, Role::Viewer)
+            .await
+            .map_err(|err| denied(err, id))?;
+
+        let mut conn = self.data.db.acquire().await.map_err(database_error)?;
+        match store::todo::find(&mut conn, actor.owner_id, id).await {
+            Ok(Some(todo)) => Ok(Response::new(todo.into())),
+            Ok(None) => Err(denied(Denied::NotFound, id)),
+            Err(err) => Err(database_error(err)),
+        }
+    }
+
+    type ListTodosStream = ResponseStream<proto::Todo>;
+
+    async fn list_todos(
+        &self,
+        request: Request<proto::ListTodosRequest>,
+    ) -> Result<Response<Self::ListTodosStream>, Status> {
+        let user = self.user(&request, "todos:read").await?;
+        let body = request.into_inner();
+        let sort = match body.sort.as_str() {
+            "" => "position".to_string(),
+            sort => sort.to_string(),
+        };
+        let order = store::todo::order_by(&sort)
+            .ok_or_else(|| Status::invalid_argument(format!("Cannot sort by {}", sort)))?;
+        let options = FilterOptions {
+            page: Some(1),
+            limit: Some(PAGE_SIZE),
+            complete: body.complete,
+            priority: priority(body.priority)?,
+            due_before: body
+                .due_before
+                .map(|at| date_time("due_before", at))
+                .transpose()?,
+            due_after: body
+                .due_after
+                .map(|at| date_time("due_after", at))
+                .transpose()?,
+            overdue: body.overdue.then_some(true),
+            list_id: optional_uuid("list_id", body.list_id.as_deref())?,
+            parent_id: optional_uuid("parent_id", body.parent_id.as_deref())?,
+            tags: (!body.tags.is_empty())
+                .then(|| uuids("tags", &body.tags))
+                .transpose()?,
+            tag_mode: tag_mode(body.tag_mode)?,
+            sort: Some(sort),
+        };
+
+        // Pages are read as the client takes them.
+        let data = self.data.clone();
+        let pages = stream::try_unfold(Some(options), move |options| {
+            let data = data.clone();
+            let order = order.clone();
+            async move {
+                let Some(mut options) = options else {
+                    return Ok::<_, Status>(None);
+                };
+                let todos = fetch_todos(&data, user.id, &options, &order)
+                    .await
+                    .map_err(database_error)?;
+                let more = todos.len() == PAGE_SIZE;
+                options.page = options.page.map(|page| page + 1);
+                Ok(Some((todos, more.then_some(options))))
+            }
+        });
+        let todos = pages
+            .map_ok(|todos| stream::iter(todos.into_iter().map(|todo| Ok(todo.into()))))
+            .try_flatten();
+        Ok(Response::new(Box::pin(todos)))
+    }
+
+    async fn create_todo(
+        &self,
+        request: Request<proto::CreateTodoRequest>,
+    ) -> Result<Response<proto::Todo>, Status> {
+        let user = self.user(&request, "todos:write").await?;
+        let body = request.into_inner();
+        let todo = CreateToDo {
+            title: body.title,
+            content: body.content,
+            complete: Some(body.complete),
+            due_at: body.due_at.map(|at| date_time("due_at", at)).transpose()?,
+            priority: priority(body.priority)?,
+            rrule: body.rrule,
+            timezone: body.timezone,
+            list_id: optional_uuid("list_id", body.list_id.as_deref())?,
+            tags: Some(uuids("tags", &body.tags)?),
+            parent_id: optional_uuid("parent_id", body.parent_id.as_deref())?,
+        };
+        recurrence::validate(todo.rrule.as_deref(), todo.timezone.as_deref())
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        let actor = creator(&self.data.db, user.id, &todo)
+            .await
+            .map_err(|err| match err {
+                Denied::Forbidden => {
+                    Status::permission_denied("You are not allowed to add ToDos there")
+                }
+                err => denied(err, todo.parent_id.unwrap_or_default()),
+            })?;
+
+        let created = match self.data.db.acquire().await {
+            Ok(mut conn) => store::todo::create(&mut conn, actor, &todo).await,
+            Err(err) => Err(err.into()),
+        };
+        self.written(actor, Uuid::nil(), created).await
+    }
+
+    async fn update_todo(
+        &self,
+        request: Request<proto::UpdateTodoRequest>,
+    ) -> Result<Response<proto::Todo>, Status> {
+        let user = self.user(&request, "todos:write").await?;
+        let body = request.into_inner();
+        let id = uuid("id", &body.id)?;
+        let changes = changes(
+            body.todo.unwrap_or_default(),
+            body.update_mask.unwrap_or_default(),
+        )?;
+        recurrence::validate(
+            changes.rrule.clone().flatten().as_deref(),
+            changes.timezone.as_deref(),
+        )
+        .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        let actor = self.editor(&user, id).await?;
+
+        let updated = match self.data.db.acquire().await {
+            Ok(mut conn) => {
+                store::todo::update(
+                    &mut conn,
+                    actor,
+                    id,
+                    Some(body.version),
+                    &changes,
+                    body.cascade,
+                )
+                .await
+            }
+            Err(err) => Err(err.into()),
+        };
+        self.written(actor, id, updated).await
+    }
+
+    async fn delete_todo(
+        &self,
+        request: Request<proto::DeleteTodoRequest>,
+    ) -> Result<Response<proto::DeleteTodoResponse>, Status> {
+        let user = self.user(&request, "todos:write").await?;
+        let body = request.into_inner();
+        let id = uuid("id", &body.id)?;
+        let actor = self.editor(&user, id).await?;
+
+        let deleted = match self.data.db.acquire().await {
+            Ok(mut conn) => {
+                store::todo::delete(
+                    &mut conn,
+                    actor,
+                    id,
+                    Some(body.version),
+                    !body.keep_subtasks,
+                )
+                .await
+            }
+            Err(err) => Err(err),
+        }
+        .map_err(database_error)?;
+        if !deleted {
+            return Err(write_failed(&self.data, actor.owner_id, id, WriteError::Rejected).await);
+        }
+        Ok(Response::new(proto::DeleteTodoResponse {}))
+    }
+
+    async fn move_todo(
+        &self,
+        request: Request<proto::MoveTodoRequest>,
+    ) -> Result<Response<proto::Todo>, Status> {
+        let user = self.user(&request, "todos:write").await?;
+        let body = request.into_inner();
+        let id = uuid("id", &body.id)?;
+        let after = optional_uuid("after", body.after.as_deref())?;
+        let before = optional_uuid("before", body.before.as_deref())?;
+        if after.is_none() && before.is_none() {
+            return Err(Status::invalid_argument(
+                "Either after or before is required",
+            ));
+        }
+        let actor = self.editor(&user, id).await?;
+
+        let moved = match self.data.db.acquire().await {
+            Ok(mut conn) => {
+                store::todo::reorder(&mut conn, actor, id, Some(body.version), after, before).await
+            }
+            Err(err) => Err(err.into()),
+        };
+        self.written(actor, id, moved).await
+    }
+
+    async fn restore_todo(
+        &self,
+        request: Request<proto::RestoreTodoRequest>,
+    ) -> Result<Response<proto::Todo>, Status> {
+        let user = self.user(&request, "todos:write").await?;
+        let id = uuid("id", &request.get_ref().id)?;
+        let actor = self.editor(&user, id).await?;
+
+        let restored = match self.data.db.acquire().await {
+            Ok(mut conn) => store::todo::restore(&mut conn, actor, id).await,
+            Err(err) => Err(err),
+        };
+        let restored = match restored {
+            Ok(restored) => restored,
+            Err(err) => {
+                return Err(
+                    write_failed(&self.data, actor.owner_id, id, WriteError::Database(err)).await,
+                )
+            }
+        }
+        .ok_or_else(|| Status::not_found(format!("ToDo with ID: {} is not in the trash", id)))?;
+        Ok(Response::new(restored.into()))
+    }
+
+    type WatchTodosStream = ResponseStream<proto::TodoUpdate>;
+
+    async fn watch_todos(
+        &self,
+        request: Request<proto::WatchTodosRequest>,
+    ) -> Result<Response<Self::WatchTodosStream>, Status> {
+        let user = self.user(&request, "todos:read").await?;
+        let body = request.into_inner();
+        let options = StreamOptions {
+            last_event_id: body.last_event_id,
+            list_id: optional_uuid("list_id", body.list_id.as_deref())?,
+            tags: (!body.tags.is_empty())
+                .then(|| uuids("tags", &body.tags))
+                .transpose()?,
+        };
+        let (feed, missed) = Feed::open(&self.data, user.id, options)
+            .await
+            .map_err(database_error)?;
+
+        // The feed is dropped after an error, which ends the stream.
+        let pending: VecDeque<Update> = VecDeque::from(missed);
+        let updates = stream::unfold(
+            (Some(feed), pending, self.data.clone()),
+            |(mut feed, mut pending, data)| async move {
+                loop {
+                    if let Some(update) = pending.pop_front() {
+                        return Some((Ok(update.into()), (feed, pending, data)));
+                    }
+
+                    let following = feed.as_mut()?;
+                    let received = following.recv().await;
+                    match following.handle(&data.db, received).await {
+                        Ok(Some(updates)) => pending.extend(updates),
+                        Ok(None) => return None,
+                        Err(err) => return Some((Err(database_error(err)), (None, pending, data))),
+                    }
+                }
+            },
+        );
+        Ok(Response::new(Box::pin(updates)))
+    }
+}
+
+/// The fields of `todo` named in `mask` as changes. A named field that is
+/// unset clears the todo's field where it can be cleared.
+fn changes(todo: proto::Todo, mask: prost_types::FieldMask) -> Result<UpdateToDo, Status> {
+    if mask.paths.is_empty() {
+        return Err(Status::invalid_argument(
+            "update_mask must name the fields to change",
+        ));
+    }
+
+    let mut changes = UpdateToDo::default();
+    for path in &mask.paths {
+        match path.as_str() {
+            "title" => changes.title = Some(todo.title.clone()),
+            "content" => changes.content = Some(todo.content.clone()),
+            "complete" => changes.complete = Some(todo.complete),
+            "due_at" => {
+                changes.due_at = Some(
+                    todo.due_at
+                        .clone()
+                        .map(|at| date_time("due_at", at))
+                        .transpose()?,
+                )
+            }
+            "priority" => {
+                changes.priority = Some(
+                    priority(todo.priority)?
+                        .ok_or_else(|| Status::invalid_argument("priority cannot be cleared"))?,
+                )
+            }
+            "rrule" => changes.rrule = Some(todo.rrule.clone()),
+            "timezone" => changes.timezone = Some(todo.timezone.clone()),
+            "list_id" => changes.list_id = Some(optional_uuid("list_id", todo.list_id.as_deref())?),
+            "tags" => {
+                let ids: Vec<String> = todo.tags.iter().map(|tag| tag.id.clone()).collect();
+                changes.tags = Some(uuids("tags", &ids)?)
+            }
+            "parent_id" => {
+                changes.parent_id = Some(optional_uuid("parent_id", todo.parent_id.as_deref())?)
+            }
+            path => return Err(Status::invalid_argument(format!("Cannot update {}", path))),
+        }
+    }
+    Ok(changes)
+}
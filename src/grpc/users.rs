@@ -0,0 +1,94 @@
+use super::{authenticate, database_error, proto, session_only};
+use crate::{
+    events::UserChangeKind,
+    handlers::auth::{announce, confirmed, password_hash, PHOTO_READ_ONLY, SIGN_IN_AGAIN},
+    model::UserModel,
+    AppState,
+};
+use proto::users_server::Users;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+
+pub struct UserService {
+    pub data: Arc<AppState>,
+}
+
+#[tonic::async_trait]
+impl Users for UserService {
+    async fn get_me(
+        &self,
+        request: Request<proto::GetMeRequest>,
+    ) -> Result<Response<proto::User>, Status> {
+        let (user, _) = authenticate(&self.data, &request).await?;
+        Ok(Response::new(user.into()))
+    }
+
+    async fn update_me(
+        &self,
+        request: Request<proto::UpdateMeRequest>,
+    ) -> Result<Response<proto::User>, Status> {
+        let (user, credential) = authenticate(&self.data, &request).await?;
+        session_only(&credential)?;
+        let body = request.into_inner();
+        if body
+            .name
+            .as_ref()
+            .is_some_and(|name| name.trim().is_empty())
+        {
+            return Err(Status::invalid_argument("Name must not be empty"));
+        }
+        if body.photo.is_some_and(|photo| photo != user.photo) {
+            return Err(Status::invalid_argument(PHOTO_READ_ONLY));
+        }
+
+        let updated = sqlx::query_as!(
+            UserModel,
+            "UPDATE users SET name = $1, updated_at = $2 WHERE id = $3 RETURNING *",
+            body.name.map_or(user.name, |name| name.trim().to_string()),
+            chrono::Utc::now(),
+            user.id
+        )
+        .fetch_one(&self.data.db)
+        .await
+        .map_err(database_error)?;
+        announce(&self.data, user.id, UserChangeKind::Updated).await;
+
+        Ok(Response::new(updated.into()))
+    }
+
+    /// Accounts created through a login provider may set a first password
+    /// without a current one, right after signing in through it.
+    async fn change_password(
+        &self,
+        request: Request<proto::ChangePasswordRequest>,
+    ) -> Result<Response<proto::ChangePasswordResponse>, Status> {
+        let (user, credential) = authenticate(&self.data, &request).await?;
+        session_only(&credential)?;
+        let body = request.into_inner();
+        if !confirmed(&user, &credential, body.current_password.as_deref()) {
+            return Err(if user.password.is_empty() {
+                Status::permission_denied(SIGN_IN_AGAIN)
+            } else {
+                Status::invalid_argument("Current password is incorrect")
+            });
+        }
+        if body.new_password.is_empty() {
+            return Err(Status::invalid_argument("New password must not be empty"));
+        }
+
+        let hash = password_hash(&body.new_password)
+            .map_err(|err| Status::internal(format!("Error while hashing password: {}", err)))?;
+        sqlx::query!(
+            "UPDATE users SET password = $1, updated_at = $2 WHERE id = $3",
+            hash,
+            chrono::Utc::now(),
+            user.id
+        )
+        .execute(&self.data.db)
+        .await
+        .map_err(database_error)?;
+        announce(&self.data, user.id, UserChangeKind::Updated).await;
+
+        Ok(Response::new(proto::ChangePasswordResponse {}))
+    }
+}
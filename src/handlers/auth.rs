@@ -1,11 +1,17 @@
 use crate::{
+    auth::{hash_token, random_token, Credential},
+    events::{self, UserChangeKind},
+    handlers::avatar,
     model::UserModel,
-    schema::{GenericResponse, Signin, Signup, UserSingleResponse, JWT},
-    AppState,
+    schema::{
+        ChangeMail, ChangePassword, DeleteAccount, GenericResponse, Signin, Signup, UpdateUser,
+        UserSingleResponse, VerifyMail, JWT,
+    },
+    store, webhook, AppState,
 };
 use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use axum::{
-    extract::State,
+    extract::{Query, State},
     http::{header, Response, StatusCode},
     response::IntoResponse,
     Extension, Json,
@@ -16,6 +22,55 @@ use jsonwebtoken::{encode, EncodingKey, Header};
 use rand_core::OsRng;
 use std::sync::Arc;
 
+fn hash_password(password: &str) -> Result<String, (StatusCode, Json<serde_json::Value>)> {
+    password_hash(password).map_err(|err| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Error while hashing password: {}", err),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+    })
+}
+
+/// An Argon2 hash of the password with a fresh salt.
+pub(crate) fn password_hash(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+}
+
+/// Accounts created through a login provider have no password and never verify.
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed_hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// What accounts without a password are told when they did not just sign in
+/// through their login provider.
+pub const SIGN_IN_AGAIN: &str = "Sign in through your login provider again to confirm this";
+
+/// Whether a sensitive change is confirmed: with the password, or for
+/// accounts without one by having just signed in through a login provider.
+pub fn confirmed(user: &UserModel, credential: &Credential, password: Option<&str>) -> bool {
+    if user.password.is_empty() {
+        return credential.recently_signed_in_through_provider();
+    }
+    password.is_some_and(|password| verify_password(&user.password, password))
+}
+
+/// Lets every instance know about a change to a user. The change is made
+/// already, so a failure only costs the notification.
+pub(crate) async fn announce(data: &AppState, id: uuid::Uuid, kind: UserChangeKind) {
+    if let Err(err) = events::notify_user(&data.db, id, kind).await {
+        println!("🔥 Failed to announce change to user {}: {:?}", id, err);
+    }
+}
+
 // ----------------------------------------------------------------- SIGNUP_TODO
 pub async fn signup_handler(
     State(data): State<Arc<AppState>>,
@@ -44,17 +99,7 @@ pub async fn signup_handler(
         }
     }
 
-    let salt = SaltString::generate(&mut OsRng);
-    let hashed_password = Argon2::default()
-        .hash_password(body.password.as_bytes(), &salt)
-        .map_err(|err| {
-            let response = serde_json::json!(GenericResponse {
-                status: "fail".to_string(),
-                message: format!("Error while hashing password: {}", err),
-            });
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
-        })
-        .map(|hash| hash.to_string())?;
+    let hashed_password = hash_password(&body.password)?;
 
     let query = sqlx::query_as!(
         UserModel,
@@ -72,6 +117,14 @@ pub async fn signup_handler(
         });
         (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
     })?;
+    announce(&data, query.id, UserChangeKind::Created).await;
+    let payload = webhook::signed_up(query.id, &query.name, &query.mail);
+    if let Err(err) = store::webhook::enqueue(&data.db, None, "user.signed_up", &payload).await {
+        println!(
+            "🔥 Failed to queue webhooks for user {}: {:?}",
+            query.id, err
+        );
+    }
 
     let response = serde_json::json!(UserSingleResponse {
         status: "success".to_string(),
@@ -108,14 +161,7 @@ pub async fn signin_handler(
         (StatusCode::BAD_REQUEST, Json(response))
     })?;
 
-    let valid = match PasswordHash::new(&query.password) {
-        Ok(parsed_hash) => Argon2::default()
-            .verify_password(body.password.as_bytes(), &parsed_hash)
-            .map_or(false, |_| true),
-        Err(_) => false,
-    };
-
-    if !valid {
+    if !verify_password(&query.password, &body.password) {
         return Err((
             StatusCode::BAD_REQUEST,
             Json(serde_json::json!(GenericResponse {
@@ -125,13 +171,24 @@ pub async fn signin_handler(
         ));
     }
 
+    Ok(session_response(&data, query.id, None))
+}
+
+/// Issues a JWT for the user, both in the body and as the `token` cookie,
+/// noting the login provider signed in through, if any.
+pub fn session_response(
+    data: &AppState,
+    user_id: uuid::Uuid,
+    provider: Option<&str>,
+) -> Response<String> {
     let now = chrono::Utc::now();
     let iat = now.timestamp() as usize;
     let exp = (now + chrono::Duration::minutes(60)).timestamp() as usize;
     let claims: JWT = JWT {
-        sub: query.id.to_string(),
+        sub: user_id.to_string(),
         exp,
         iat,
+        provider: provider.map(str::to_string),
     };
 
     let token = encode(
@@ -154,7 +211,7 @@ pub async fn signin_handler(
         .headers_mut()
         .insert(header::SET_COOKIE, cookie.to_string().parse().unwrap());
 
-    return Ok(response);
+    response
 }
 
 pub async fn logout_handler() -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
@@ -181,3 +238,319 @@ pub async fn get_me_handler(
         data: user
     })))
 }
+
+// ----------------------------------------------------------------- UPDATE_ME
+/// Why `photo` cannot be changed directly. Sending the current value back is
+/// fine.
+pub const PHOTO_READ_ONLY: &str = "The photo can only be changed through /api/users/me/avatar";
+
+pub async fn update_me_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<UpdateUser>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if body
+        .name
+        .as_ref()
+        .is_some_and(|name| name.trim().is_empty())
+    {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "Name must not be empty".to_string(),
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(response)));
+    }
+    if body.photo.is_some_and(|photo| photo != user.photo) {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: PHOTO_READ_ONLY.to_string(),
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(response)));
+    }
+
+    let query = sqlx::query_as!(
+        UserModel,
+        "UPDATE users SET name = $1, updated_at = $2 WHERE id = $3 RETURNING *",
+        body.name.map_or(user.name, |name| name.trim().to_string()),
+        chrono::Utc::now(),
+        user.id
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|e| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Database error: {}", e),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+    })?;
+    announce(&data, user.id, UserChangeKind::Updated).await;
+
+    Ok(Json(serde_json::json!(UserSingleResponse {
+        status: "success".to_string(),
+        data: query
+    })))
+}
+
+// ----------------------------------------------------------------- CHANGE_PASSWORD
+pub async fn change_password_handler(
+    Extension(user): Extension<UserModel>,
+    Extension(credential): Extension<Credential>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<ChangePassword>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    // Accounts created through a login provider may set a first password
+    // right after signing in through it.
+    if !confirmed(&user, &credential, body.current_password.as_deref()) {
+        let (status, message) = if user.password.is_empty() {
+            (StatusCode::FORBIDDEN, SIGN_IN_AGAIN)
+        } else {
+            (StatusCode::BAD_REQUEST, "Current password is incorrect")
+        };
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: message.to_string(),
+        });
+        return Err((status, Json(response)));
+    }
+
+    if body.new_password.is_empty() {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "New password must not be empty".to_string(),
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(response)));
+    }
+
+    sqlx::query!(
+        "UPDATE users SET password = $1, updated_at = $2 WHERE id = $3",
+        hash_password(&body.new_password)?,
+        chrono::Utc::now(),
+        user.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|e| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Database error: {}", e),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+    })?;
+    announce(&data, user.id, UserChangeKind::Updated).await;
+
+    Ok(Json(serde_json::json!(GenericResponse {
+        status: "success".to_string(),
+        message: "Password changed".to_string(),
+    })))
+}
+
+// ----------------------------------------------------------------- CHANGE_MAIL
+pub async fn change_mail_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<ChangeMail>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let database_error = |e: sqlx::Error| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Database error: {}", e),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+    };
+
+    let mail = body.mail.trim().to_ascii_lowercase();
+    if !mail.contains('@') {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "Invalid mail".to_string(),
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(response)));
+    }
+
+    let exists = sqlx::query_scalar!("SELECT EXISTS(SELECT 1 FROM users WHERE mail = $1)", mail)
+        .fetch_one(&data.db)
+        .await
+        .map_err(database_error)?;
+
+    if exists == Some(true) {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "User with that mail already exists".to_string(),
+        });
+        return Err((StatusCode::CONFLICT, Json(response)));
+    }
+
+    let token = random_token();
+    let mut tx = data.db.begin().await.map_err(database_error)?;
+
+    sqlx::query!("DELETE FROM mail_changes WHERE user_id = $1", user.id)
+        .execute(&mut tx)
+        .await
+        .map_err(database_error)?;
+
+    sqlx::query!(
+        "INSERT INTO mail_changes (user_id,mail,token_hash,expires_at) VALUES ($1, $2, $3, $4)",
+        user.id,
+        mail,
+        hash_token(&token),
+        chrono::Utc::now() + chrono::Duration::hours(24),
+    )
+    .execute(&mut tx)
+    .await
+    .map_err(database_error)?;
+
+    sqlx::query!(
+        "UPDATE users SET verify = FALSE, updated_at = $1 WHERE id = $2",
+        chrono::Utc::now(),
+        user.id
+    )
+    .execute(&mut tx)
+    .await
+    .map_err(database_error)?;
+
+    events::notify_user(&mut tx, user.id, UserChangeKind::Updated)
+        .await
+        .map_err(database_error)?;
+
+    tx.commit().await.map_err(database_error)?;
+
+    let link = format!("{}/auth/verify-mail?token={}", data.env.app_url, token);
+    data.mailer
+        .send(
+            &mail,
+            "Confirm your new mail address",
+            &format!(
+                "Open this link within 24 hours to confirm the change:\n{}",
+                link
+            ),
+        )
+        .await
+        .map_err(|e| {
+            let response = serde_json::json!(GenericResponse {
+                status: "error".to_string(),
+                message: format!("Could not send confirmation mail: {}", e),
+            });
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+        })?;
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!(GenericResponse {
+            status: "success".to_string(),
+            message: format!("A confirmation link was sent to {}", mail),
+        })),
+    ))
+}
+
+// ----------------------------------------------------------------- VERIFY_MAIL
+pub async fn verify_mail_handler(
+    Query(query): Query<VerifyMail>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let database_error = |e: sqlx::Error| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Database error: {}", e),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+    };
+
+    let mut tx = data.db.begin().await.map_err(database_error)?;
+
+    let change = sqlx::query!(
+        "DELETE FROM mail_changes WHERE token_hash = $1 RETURNING user_id, mail, expires_at",
+        hash_token(&query.token)
+    )
+    .fetch_optional(&mut tx)
+    .await
+    .map_err(database_error)?
+    .filter(|change| change.expires_at > chrono::Utc::now())
+    .ok_or_else(|| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "Invalid or expired confirmation link".to_string(),
+        });
+        (StatusCode::BAD_REQUEST, Json(response))
+    })?;
+
+    let query = sqlx::query_as!(
+        UserModel,
+        "UPDATE users SET mail = $1, verify = TRUE, updated_at = $2 WHERE id = $3 RETURNING *",
+        change.mail,
+        chrono::Utc::now(),
+        change.user_id
+    )
+    .fetch_one(&mut tx)
+    .await
+    .map_err(|e| {
+        if e.to_string()
+            .contains("duplicate key value violates unique constraint")
+        {
+            let response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: "User with that mail already exists".to_string(),
+            });
+            return (StatusCode::CONFLICT, Json(response));
+        }
+        database_error(e)
+    })?;
+
+    events::notify_user(&mut tx, query.id, UserChangeKind::Updated)
+        .await
+        .map_err(database_error)?;
+
+    tx.commit().await.map_err(database_error)?;
+
+    Ok(Json(serde_json::json!(UserSingleResponse {
+        status: "success".to_string(),
+        data: query
+    })))
+}
+
+// ----------------------------------------------------------------- DELETE_ME
+pub async fn delete_me_handler(
+    Extension(user): Extension<UserModel>,
+    Extension(credential): Extension<Credential>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<DeleteAccount>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if !confirmed(&user, &credential, body.password.as_deref()) {
+        let (status, message) = if user.password.is_empty() {
+            (StatusCode::FORBIDDEN, SIGN_IN_AGAIN)
+        } else {
+            (StatusCode::BAD_REQUEST, "Password is incorrect")
+        };
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: message.to_string(),
+        });
+        return Err((status, Json(response)));
+    }
+
+    // Todos, tokens and linked identities are removed by ON DELETE CASCADE.
+    sqlx::query!("DELETE FROM users WHERE id = $1", user.id)
+        .execute(&data.db)
+        .await
+        .map_err(|e| {
+            let response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: format!("Database error: {}", e),
+            });
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+        })?;
+    announce(&data, user.id, UserChangeKind::Deleted).await;
+    avatar::delete_avatar(&data, &user).await;
+
+    let cookie = Cookie::build("token", "")
+        .path("/")
+        .max_age(time::Duration::hours(-1))
+        .same_site(SameSite::Lax)
+        .http_only(true)
+        .finish();
+
+    Ok((
+        StatusCode::NO_CONTENT,
+        [(header::SET_COOKIE, cookie.to_string())],
+    ))
+}
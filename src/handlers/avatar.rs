@@ -0,0 +1,194 @@
+use crate::{
+    events::UserChangeKind,
+    handlers::auth::announce,
+    model::UserModel,
+    schema::{GenericResponse, UserSingleResponse},
+    AppState,
+};
+use axum::{
+    extract::{Multipart, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use image::{imageops::FilterType, io::Limits, ImageFormat, ImageOutputFormat};
+use std::{io::Cursor, sync::Arc};
+
+/// Square sizes generated for every avatar; the first one is stored in `photo`.
+const THUMBNAIL_SIZES: [u32; 3] = [256, 128, 64];
+
+// ----------------------------------------------------------------- UPLOAD_AVATAR
+pub async fn upload_avatar_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let multipart_error = |e: axum::extract::multipart::MultipartError| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: e.body_text(),
+        });
+        (e.status(), Json(response))
+    };
+
+    let mut upload = None;
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        if field.name() == Some("avatar") {
+            upload = Some(field.bytes().await.map_err(multipart_error)?);
+            break;
+        }
+    }
+
+    let upload = upload.ok_or_else(|| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "Missing multipart field: avatar".to_string(),
+        });
+        (StatusCode::BAD_REQUEST, Json(response))
+    })?;
+
+    let thumbnails = tokio::task::spawn_blocking(move || thumbnails(&upload))
+        .await
+        .map_err(|e| {
+            let response = serde_json::json!(GenericResponse {
+                status: "error".to_string(),
+                message: format!("Could not process image: {}", e),
+            });
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+        })?
+        .map_err(|message| {
+            let response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message,
+            });
+            (StatusCode::UNSUPPORTED_MEDIA_TYPE, Json(response))
+        })?;
+
+    let name = uuid::Uuid::new_v4();
+    for (size, png) in thumbnails {
+        data.storage
+            .put(&avatar_key(user.id, name, size), "image/png", png)
+            .await
+            .map_err(|e| {
+                let response = serde_json::json!(GenericResponse {
+                    status: "error".to_string(),
+                    message: e.to_string(),
+                });
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+            })?;
+    }
+
+    let query = sqlx::query_as!(
+        UserModel,
+        "UPDATE users SET photo = $1, updated_at = $2 WHERE id = $3 RETURNING *",
+        data.storage
+            .url(&avatar_key(user.id, name, THUMBNAIL_SIZES[0])),
+        chrono::Utc::now(),
+        user.id
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|e| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Database error: {}", e),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+    })?;
+    announce(&data, user.id, UserChangeKind::Updated).await;
+
+    // The previous avatar is no longer referenced; failing to remove it only
+    // leaves an orphaned file behind.
+    delete_avatar(&data, &user).await;
+
+    Ok(Json(serde_json::json!(UserSingleResponse {
+        status: "success".to_string(),
+        data: query
+    })))
+}
+
+// ----------------------------------------------------------------- DELETE_AVATAR
+pub async fn delete_avatar_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let query = sqlx::query_as!(
+        UserModel,
+        "UPDATE users SET photo = '', updated_at = $1 WHERE id = $2 RETURNING *",
+        chrono::Utc::now(),
+        user.id
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|e| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Database error: {}", e),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+    })?;
+    announce(&data, user.id, UserChangeKind::Updated).await;
+    delete_avatar(&data, &user).await;
+
+    Ok(Json(serde_json::json!(UserSingleResponse {
+        status: "success".to_string(),
+        data: query
+    })))
+}
+
+fn avatar_key(user_id: uuid::Uuid, name: uuid::Uuid, size: u32) -> String {
+    format!("avatars/{}/{}-{}.png", user_id, name, size)
+}
+
+/// Removes the uploaded files behind the user's `photo`. Anything else, such
+/// as a picture from a login provider, is left alone.
+pub async fn delete_avatar(data: &AppState, user: &UserModel) {
+    let name = data.storage.key(&user.photo).and_then(|key| {
+        key.strip_prefix(&format!("avatars/{}/", user.id))?
+            .strip_suffix(&format!("-{}.png", THUMBNAIL_SIZES[0]))
+            .and_then(|name| uuid::Uuid::parse_str(name).ok())
+    });
+    let Some(name) = name else {
+        return;
+    };
+
+    for size in THUMBNAIL_SIZES {
+        let key = avatar_key(user.id, name, size);
+        if let Err(err) = data.storage.delete(&key).await {
+            println!("🔥 Failed to delete old avatar {}: {}", key, err);
+        }
+    }
+}
+
+/// Sniffs the real format from the bytes, ignoring the declared content
+/// type, and renders square PNG thumbnails.
+fn thumbnails(bytes: &[u8]) -> Result<Vec<(u32, Vec<u8>)>, String> {
+    let format = image::guess_format(bytes).map_err(|_| "Unrecognized image format")?;
+    if !matches!(
+        format,
+        ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP
+    ) {
+        return Err("Only PNG, JPEG, GIF and WebP avatars are supported".to_string());
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(8192);
+    limits.max_image_height = Some(8192);
+    let mut reader = image::io::Reader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let image = reader
+        .decode()
+        .map_err(|e| format!("Could not decode image: {}", e))?;
+
+    THUMBNAIL_SIZES
+        .iter()
+        .map(|&size| {
+            let mut png = Cursor::new(Vec::new());
+            image
+                .resize_to_fill(size, size, FilterType::Lanczos3)
+                .write_to(&mut png, ImageOutputFormat::Png)
+                .map_err(|e| format!("Could not encode thumbnail: {}", e))?;
+            Ok((size, png.into_inner()))
+        })
+        .collect()
+}
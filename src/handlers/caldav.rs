@@ -0,0 +1,1031 @@
+//! CalDAV (RFC 4791) over the user's todos, for native task apps.
+//!
+//! - `/dav/` points clients to the user's principal.
+//! - `/dav/principals/<user id>/` points to the calendar home.
+//! - `/dav/calendars/<user id>/` holds a single calendar, `todos`.
+//! - `/dav/calendars/<user id>/todos/` holds an object per todo outside the
+//!   trash, see `store::dav`.
+//!
+//! ETags are todo versions. Sync tokens and CTags are positions of todo events,
+//! so `sync-collection` reports the todos with events after the token.
+
+use crate::{
+    auth::Credential,
+    ical,
+    model::{Priority, UserModel},
+    recurrence,
+    schema::{CreateToDo, UpdateToDo},
+    store::{
+        self,
+        dav::Object,
+        todo::{Actor, WriteError},
+    },
+    AppState,
+};
+use axum::{
+    body::{Bytes, Full},
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use roxmltree::{Document, Node};
+use sqlx::{Acquire, PgConnection};
+use std::{collections::HashMap, sync::Arc};
+use uuid::Uuid;
+
+const DAV: &str = "DAV:";
+const CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+const CALENDARSERVER: &str = "http://calendarserver.org/ns/";
+
+/// Name of the one calendar in a user's home.
+const CALENDAR: &str = "todos";
+
+const OBJECT_CONTENT_TYPE: &str = "text/calendar; charset=utf-8; component=VTODO";
+
+// ----------------------------------------------------------------- WELL_KNOWN
+pub async fn caldav_redirect_handler() -> impl IntoResponse {
+    (StatusCode::MOVED_PERMANENTLY, [(header::LOCATION, "/dav/")])
+}
+
+// ----------------------------------------------------------------- DAV
+pub async fn dav_root_handler(
+    Extension(user): Extension<UserModel>,
+    Extension(credential): Extension<Credential>,
+    State(data): State<Arc<AppState>>,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let dav = Dav {
+        data: &data,
+        user: &user,
+        credential: &credential,
+    };
+    dav.serve("", method, headers, body).await
+}
+
+pub async fn dav_handler(
+    Path(path): Path<String>,
+    Extension(user): Extension<UserModel>,
+    Extension(credential): Extension<Credential>,
+    State(data): State<Arc<AppState>>,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let dav = Dav {
+        data: &data,
+        user: &user,
+        credential: &credential,
+    };
+    dav.serve(&path, method, headers, body).await
+}
+
+/// What a path under `/dav/` points at.
+enum Target {
+    Root,
+    Principal,
+    Home,
+    Calendar,
+    Object(String),
+    Missing,
+}
+
+/// A resource reported in a multistatus.
+enum Resource {
+    Root,
+    Principal,
+    Home,
+    /// With the current sync token.
+    Calendar(i64),
+    Object(Box<Object>),
+}
+
+/// A property name, its namespace and local name.
+type Name = (String, String);
+
+enum PropRequest {
+    All,
+    Names,
+    Props(Vec<Name>),
+}
+
+/// A `calendar-query` filter, reduced to what task clients ask for.
+#[derive(Default)]
+struct Filter {
+    /// Whether the filter asks for VTODOs at all.
+    todos: bool,
+    /// Only todos that are not completed.
+    open: bool,
+    /// Todos due in `[start, end)`. Todos without a due date always match.
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+}
+
+struct Dav<'a> {
+    data: &'a AppState,
+    user: &'a UserModel,
+    credential: &'a Credential,
+}
+
+impl Dav<'_> {
+    async fn serve(&self, path: &str, method: Method, headers: HeaderMap, body: Bytes) -> Response {
+        let scope = match method.as_str() {
+            "OPTIONS" | "GET" | "HEAD" | "PROPFIND" | "REPORT" => "todos:read",
+            "PUT" | "DELETE" => "todos:write",
+            _ => return StatusCode::METHOD_NOT_ALLOWED.into_response(),
+        };
+        if !self.credential.has_scope(scope) {
+            return (
+                StatusCode::FORBIDDEN,
+                format!("This token is missing the {} scope", scope),
+            )
+                .into_response();
+        }
+
+        let target = self.target(path);
+        if let Target::Missing = target {
+            return StatusCode::NOT_FOUND.into_response();
+        }
+
+        let mut conn = match self.data.db.acquire().await {
+            Ok(conn) => conn,
+            Err(err) => return dav_failed(err),
+        };
+        let result = match method.as_str() {
+            "OPTIONS" => Ok(options()),
+            "PROPFIND" => self.propfind(&mut conn, target, &headers, &body).await,
+            "REPORT" => self.report(&mut conn, target, &body).await,
+            "GET" | "HEAD" => self.get(&mut conn, target, method == Method::HEAD).await,
+            "PUT" => self.put(&mut conn, target, &headers, &body).await,
+            _ => self.delete(&mut conn, target, &headers).await,
+        };
+        result.unwrap_or_else(dav_failed)
+    }
+
+    fn target(&self, path: &str) -> Target {
+        let user_id = self.user.id.to_string();
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        match segments.as_slice() {
+            [] => Target::Root,
+            ["principals", id] if *id == user_id => Target::Principal,
+            ["calendars", id] if *id == user_id => Target::Home,
+            ["calendars", id, CALENDAR] if *id == user_id => Target::Calendar,
+            ["calendars", id, CALENDAR, name] if *id == user_id => Target::Object(name.to_string()),
+            _ => Target::Missing,
+        }
+    }
+
+    fn principal_href(&self) -> String {
+        format!("/dav/principals/{}/", self.user.id)
+    }
+
+    fn home_href(&self) -> String {
+        format!("/dav/calendars/{}/", self.user.id)
+    }
+
+    fn calendar_href(&self) -> String {
+        format!("/dav/calendars/{}/{}/", self.user.id, CALENDAR)
+    }
+
+    fn object_href(&self, name: &str) -> String {
+        format!("{}{}", self.calendar_href(), encode_segment(name))
+    }
+
+    fn sync_token(&self, position: i64) -> String {
+        format!("{}/dav/sync/{}", self.data.env.app_url, position)
+    }
+
+    fn href(&self, resource: &Resource) -> String {
+        match resource {
+            Resource::Root => "/dav/".to_string(),
+            Resource::Principal => self.principal_href(),
+            Resource::Home => self.home_href(),
+            Resource::Calendar(_) => self.calendar_href(),
+            Resource::Object(object) => self.object_href(&object.name),
+        }
+    }
+
+    // ------------------------------------------------------------- PROPFIND
+    async fn propfind(
+        &self,
+        conn: &mut PgConnection,
+        target: Target,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<Response, sqlx::Error> {
+        let request = match parse_xml(body) {
+            Ok(Some(doc)) => match element(doc.root_element(), DAV, "propfind") {
+                Some(propfind) => prop_request(propfind),
+                None => return Ok(StatusCode::BAD_REQUEST.into_response()),
+            },
+            Ok(None) => PropRequest::All,
+            Err(status) => return Ok(status.into_response()),
+        };
+        // Infinite depth counts as 1, the tree is no deeper than that below
+        // the home anyway.
+        let children = headers
+            .get("depth")
+            .and_then(|depth| depth.to_str().ok())
+            .is_none_or(|depth| depth.trim() != "0");
+
+        let mut resources = Vec::new();
+        match target {
+            Target::Root => resources.push(Resource::Root),
+            Target::Principal => resources.push(Resource::Principal),
+            Target::Home => {
+                resources.push(Resource::Home);
+                if children {
+                    let token = store::event::latest_position_of(&mut *conn, self.user.id).await?;
+                    resources.push(Resource::Calendar(token));
+                }
+            }
+            Target::Calendar => {
+                let token = store::event::latest_position_of(&mut *conn, self.user.id).await?;
+                resources.push(Resource::Calendar(token));
+                if children {
+                    let objects = store::dav::objects(conn, self.user.id).await?;
+                    resources.extend(
+                        objects
+                            .into_iter()
+                            .map(|object| Resource::Object(Box::new(object))),
+                    );
+                }
+            }
+            Target::Object(name) => match store::dav::find(conn, self.user.id, &name).await? {
+                Some(object) => resources.push(Resource::Object(Box::new(object))),
+                None => return Ok(StatusCode::NOT_FOUND.into_response()),
+            },
+            Target::Missing => return Ok(StatusCode::NOT_FOUND.into_response()),
+        }
+
+        let mut multistatus = Multistatus::new();
+        for resource in &resources {
+            self.respond(&mut multistatus, resource, &request);
+        }
+        Ok(multistatus.finish(None))
+    }
+
+    /// Adds the requested properties of the resource to the multistatus.
+    fn respond(&self, multistatus: &mut Multistatus, resource: &Resource, request: &PropRequest) {
+        let href = self.href(resource);
+        match request {
+            PropRequest::Names => {
+                let names = default_props(resource)
+                    .into_iter()
+                    .map(|name| (name, String::new()))
+                    .collect();
+                multistatus.response(&href, names, Vec::new());
+            }
+            PropRequest::All => {
+                let found = default_props(resource)
+                    .into_iter()
+                    .filter_map(|name| self.prop(resource, &name).map(|value| (name, value)))
+                    .collect();
+                multistatus.response(&href, found, Vec::new());
+            }
+            PropRequest::Props(names) => {
+                let mut found = Vec::new();
+                let mut missing = Vec::new();
+                for name in names {
+                    match self.prop(resource, name) {
+                        Some(value) => found.push((name.clone(), value)),
+                        None => missing.push(name.clone()),
+                    }
+                }
+                multistatus.response(&href, found, missing);
+            }
+        }
+    }
+
+    /// The value of a property as XML, `None` if the resource does not have
+    /// it.
+    fn prop(&self, resource: &Resource, (ns, name): &Name) -> Option<String> {
+        let href = |href: String| format!("<d:href>{}</d:href>", escape(&href));
+        let value = match (ns.as_str(), name.as_str(), resource) {
+            (DAV, "resourcetype", Resource::Root | Resource::Home) => "<d:collection/>".to_string(),
+            (DAV, "resourcetype", Resource::Principal) => "<d:principal/>".to_string(),
+            (DAV, "resourcetype", Resource::Calendar(_)) => {
+                "<d:collection/><c:calendar/>".to_string()
+            }
+            (DAV, "resourcetype", Resource::Object(_)) => String::new(),
+            (DAV, "displayname", Resource::Principal) => escape(&self.user.name),
+            (DAV, "displayname", Resource::Calendar(_)) => "Todos".to_string(),
+            (DAV, "current-user-principal", _) => href(self.principal_href()),
+            (DAV, "principal-URL", Resource::Principal) => href(self.principal_href()),
+            (DAV, "owner", Resource::Calendar(_) | Resource::Object(_)) => {
+                href(self.principal_href())
+            }
+            (DAV, "current-user-privilege-set", _) => {
+                let mut privileges = vec!["read"];
+                if self.credential.has_scope("todos:write") {
+                    privileges.extend(["write", "write-content", "bind", "unbind"]);
+                }
+                privileges
+                    .iter()
+                    .map(|privilege| format!("<d:privilege><d:{}/></d:privilege>", privilege))
+                    .collect()
+            }
+            (DAV, "supported-report-set", Resource::Calendar(_)) => [
+                "<c:calendar-query/>",
+                "<c:calendar-multiget/>",
+                "<d:sync-collection/>",
+            ]
+            .iter()
+            .map(|report| {
+                format!(
+                    "<d:supported-report><d:report>{}</d:report></d:supported-report>",
+                    report
+                )
+            })
+            .collect(),
+            (DAV, "sync-token", Resource::Calendar(token)) => escape(&self.sync_token(*token)),
+            (CALENDARSERVER, "getctag", Resource::Calendar(token)) => token.to_string(),
+            (CALDAV, "calendar-home-set", Resource::Root | Resource::Principal) => {
+                href(self.home_href())
+            }
+            (CALDAV, "calendar-user-address-set", Resource::Principal) => {
+                href(format!("mailto:{}", self.user.mail))
+            }
+            (CALDAV, "supported-calendar-component-set", Resource::Calendar(_)) => {
+                "<c:comp name=\"VTODO\"/>".to_string()
+            }
+            (DAV, "getetag", Resource::Object(object)) => escape(&etag(object)),
+            (DAV, "getcontenttype", Resource::Object(_)) => OBJECT_CONTENT_TYPE.to_string(),
+            (DAV, "getlastmodified", Resource::Object(object)) => object
+                .todo
+                .updated_at
+                .map(|at| at.format("%a, %d %b %Y %H:%M:%S GMT").to_string())?,
+            (CALDAV, "calendar-data", Resource::Object(object)) => escape(&ical::object(
+                &object.todo,
+                &object.uid,
+                object.parent_uid.as_deref(),
+            )),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    // ------------------------------------------------------------- REPORT
+    async fn report(
+        &self,
+        conn: &mut PgConnection,
+        target: Target,
+        body: &[u8],
+    ) -> Result<Response, sqlx::Error> {
+        if !matches!(target, Target::Calendar) {
+            return Ok(dav_error(StatusCode::FORBIDDEN, "d:supported-report"));
+        }
+        let doc = match parse_xml(body) {
+            Ok(Some(doc)) => doc,
+            Ok(None) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+            Err(status) => return Ok(status.into_response()),
+        };
+        let report = doc.root_element();
+        let request = prop_request(report);
+
+        match (report.tag_name().namespace(), report.tag_name().name()) {
+            (Some(CALDAV), "calendar-query") => {
+                let filter = child(report, CALDAV, "filter")
+                    .map(parse_filter)
+                    .unwrap_or(Filter {
+                        todos: true,
+                        ..Default::default()
+                    });
+                let mut multistatus = Multistatus::new();
+                for object in store::dav::objects(conn, self.user.id).await? {
+                    if filter.matches(&object) {
+                        self.respond(
+                            &mut multistatus,
+                            &Resource::Object(Box::new(object)),
+                            &request,
+                        );
+                    }
+                }
+                Ok(multistatus.finish(None))
+            }
+            (Some(CALDAV), "calendar-multiget") => {
+                let calendar_href = self.calendar_href();
+                let mut multistatus = Multistatus::new();
+                for href in report
+                    .children()
+                    .filter(|node| is(*node, DAV, "href"))
+                    .filter_map(|node| node.text())
+                {
+                    // Clients may send full URLs rather than paths.
+                    let path = href.find("/dav/").map_or(href, |start| &href[start..]);
+                    let name = path
+                        .trim()
+                        .strip_prefix(&calendar_href)
+                        .map(decode_segment)
+                        .filter(|name| !name.is_empty() && !name.contains('/'));
+                    let object = match &name {
+                        Some(name) => store::dav::find(conn, self.user.id, name).await?,
+                        None => None,
+                    };
+                    match object {
+                        Some(object) => self.respond(
+                            &mut multistatus,
+                            &Resource::Object(Box::new(object)),
+                            &request,
+                        ),
+                        None => multistatus.gone(href.trim()),
+                    }
+                }
+                Ok(multistatus.finish(None))
+            }
+            (Some(DAV), "sync-collection") => self.sync(conn, report, &request).await,
+            _ => Ok(dav_error(StatusCode::FORBIDDEN, "d:supported-report")),
+        }
+    }
+
+    async fn sync(
+        &self,
+        conn: &mut PgConnection,
+        report: Node<'_, '_>,
+        request: &PropRequest,
+    ) -> Result<Response, sqlx::Error> {
+        let token = child(report, DAV, "sync-token")
+            .and_then(|node| node.text())
+            .map(str::trim)
+            .filter(|token| !token.is_empty());
+        let since = match token {
+            Some(token) => match token
+                .rsplit('/')
+                .next()
+                .and_then(|id| id.parse::<i64>().ok())
+            {
+                Some(since) => Some(since),
+                None => return Ok(dav_error(StatusCode::FORBIDDEN, "d:valid-sync-token")),
+            },
+            None => None,
+        };
+
+        // Taken first, so changes made while the report is built are
+        // reported again next time rather than missed.
+        let latest = store::event::latest_position_of(&mut *conn, self.user.id).await?;
+        let objects = store::dav::objects(conn, self.user.id).await?;
+
+        let mut multistatus = Multistatus::new();
+        match since {
+            None => {
+                for object in objects {
+                    self.respond(
+                        &mut multistatus,
+                        &Resource::Object(Box::new(object)),
+                        request,
+                    );
+                }
+            }
+            Some(since) => {
+                let changed = store::event::changed_after(&mut *conn, self.user.id, since).await?;
+                let mut objects: HashMap<Uuid, Object> = objects
+                    .into_iter()
+                    .map(|object| (object.todo.id, object))
+                    .collect();
+                let gone: Vec<Uuid> = changed
+                    .iter()
+                    .copied()
+                    .filter(|id| !objects.contains_key(id))
+                    .collect();
+                for id in &changed {
+                    if let Some(object) = objects.remove(id) {
+                        self.respond(
+                            &mut multistatus,
+                            &Resource::Object(Box::new(object)),
+                            request,
+                        );
+                    }
+                }
+                let names = store::dav::names(&mut *conn, &gone).await?;
+                for id in &gone {
+                    multistatus.gone(&self.object_href(&names[id]));
+                }
+            }
+        }
+        Ok(multistatus.finish(Some(self.sync_token(latest))))
+    }
+
+    // ------------------------------------------------------------- GET
+    async fn get(
+        &self,
+        conn: &mut PgConnection,
+        target: Target,
+        head: bool,
+    ) -> Result<Response, sqlx::Error> {
+        let (body, etag) = match target {
+            Target::Object(name) => match store::dav::find(conn, self.user.id, &name).await? {
+                Some(object) => (
+                    ical::object(&object.todo, &object.uid, object.parent_uid.as_deref()),
+                    Some(etag(&object)),
+                ),
+                None => return Ok(StatusCode::NOT_FOUND.into_response()),
+            },
+            Target::Calendar => {
+                let todos = store::todo::calendar(conn, self.user.id, None).await?;
+                (ical::calendar("Todos", &todos), None)
+            }
+            _ => return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response()),
+        };
+
+        let mut response = if head {
+            Response::new(Full::default())
+        } else {
+            Response::new(Full::from(body))
+        };
+        let headers = response.headers_mut();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(OBJECT_CONTENT_TYPE),
+        );
+        if let Some(etag) = etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+            headers.insert(header::ETAG, etag);
+        }
+        Ok(response.into_response())
+    }
+
+    // ------------------------------------------------------------- PUT
+    async fn put(
+        &self,
+        conn: &mut PgConnection,
+        target: Target,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<Response, sqlx::Error> {
+        let Target::Object(name) = target else {
+            return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
+        };
+        let Ok(body) = std::str::from_utf8(body) else {
+            return Ok(dav_error(StatusCode::FORBIDDEN, "c:valid-calendar-data"));
+        };
+        let vtodo = match ical::parse(body) {
+            Ok(vtodo) => vtodo,
+            Err(ical::ParseError::NoTodo) => {
+                return Ok(dav_error(
+                    StatusCode::FORBIDDEN,
+                    "c:supported-calendar-component",
+                ))
+            }
+            Err(_) => return Ok(dav_error(StatusCode::FORBIDDEN, "c:valid-calendar-data")),
+        };
+        if recurrence::validate(vtodo.rrule.as_deref(), vtodo.timezone.as_deref()).is_err() {
+            return Ok(dav_error(StatusCode::FORBIDDEN, "c:valid-calendar-data"));
+        }
+
+        let existing = store::dav::find(conn, self.user.id, &name).await?;
+        if let Some(status) = precondition(headers, existing.as_ref()) {
+            return Ok(status.into_response());
+        }
+
+        let mut tx = conn.begin().await?;
+        let title = match vtodo.summary.trim() {
+            "" => "Untitled",
+            summary => summary,
+        }
+        .to_string();
+        let mut tags = Vec::with_capacity(vtodo.categories.len());
+        for category in &vtodo.categories {
+            tags.push(store::tag::ensure(&mut *tx, self.user.id, category).await?);
+        }
+        let parent_id = match &vtodo.related_to {
+            Some(uid) => match store::dav::find_by_uid(&mut *tx, self.user.id, uid).await? {
+                Some(id) if Some(id) != existing.as_ref().map(|o| o.todo.id) => {
+                    store::todo::find(&mut tx, self.user.id, id)
+                        .await?
+                        .map(|parent| parent.id)
+                }
+                _ => None,
+            },
+            None => None,
+        };
+
+        let actor = Actor::owner(self.user.id);
+        let (written, status) = match &existing {
+            Some(object) => {
+                let changes = UpdateToDo {
+                    title: Some(title),
+                    content: Some(vtodo.description.clone()),
+                    complete: Some(vtodo.complete),
+                    due_at: Some(vtodo.due_at),
+                    priority: Some(vtodo.priority.unwrap_or(Priority::Normal)),
+                    rrule: Some(vtodo.rrule.clone()),
+                    timezone: vtodo.timezone.clone(),
+                    tags: Some(tags),
+                    parent_id: Some(parent_id),
+                    ..Default::default()
+                };
+                let version = if_match(headers).map(|_| object.todo.version);
+                let written =
+                    store::todo::update(&mut tx, actor, object.todo.id, version, &changes, false)
+                        .await;
+                (written, StatusCode::NO_CONTENT)
+            }
+            None => {
+                let todo = CreateToDo {
+                    title,
+                    content: vtodo.description.clone(),
+                    complete: Some(vtodo.complete),
+                    due_at: vtodo.due_at,
+                    priority: vtodo.priority,
+                    rrule: vtodo.rrule.clone(),
+                    timezone: vtodo.timezone.clone(),
+                    list_id: None,
+                    tags: Some(tags),
+                    parent_id,
+                };
+                let written = store::todo::create(&mut tx, actor, &todo).await;
+                (written, StatusCode::CREATED)
+            }
+        };
+
+        let todo = match written {
+            Ok(todo) => todo,
+            Err(WriteError::Rejected) => return Ok(StatusCode::PRECONDITION_FAILED.into_response()),
+            Err(WriteError::Database(err)) if store::is_unique_violation(&err) => {
+                return Ok(StatusCode::CONFLICT.into_response())
+            }
+            Err(WriteError::Database(err)) if !store::is_check_violation(&err) => return Err(err),
+            Err(_) => return Ok(dav_error(StatusCode::FORBIDDEN, "c:valid-calendar-data")),
+        };
+        if existing
+            .as_ref()
+            .is_none_or(|object| object.uid != vtodo.uid)
+        {
+            store::dav::bind(&mut tx, self.user.id, todo.id, &name, &vtodo.uid).await?;
+        }
+        tx.commit().await?;
+
+        // No ETag, as the stored object differs from what was sent. Clients
+        // fetch it again instead.
+        Ok(status.into_response())
+    }
+
+    // ------------------------------------------------------------- DELETE
+    async fn delete(
+        &self,
+        conn: &mut PgConnection,
+        target: Target,
+        headers: &HeaderMap,
+    ) -> Result<Response, sqlx::Error> {
+        let Target::Object(name) = target else {
+            return Ok(StatusCode::FORBIDDEN.into_response());
+        };
+        let Some(object) = store::dav::find(conn, self.user.id, &name).await? else {
+            return Ok(StatusCode::NOT_FOUND.into_response());
+        };
+        if let Some(status) = precondition(headers, Some(&object)) {
+            return Ok(status.into_response());
+        }
+
+        let actor = Actor::owner(self.user.id);
+        let version = if_match(headers).map(|_| object.todo.version);
+        if !store::todo::delete(conn, actor, object.todo.id, version, true).await? {
+            return Ok(StatusCode::PRECONDITION_FAILED.into_response());
+        }
+        Ok(StatusCode::NO_CONTENT.into_response())
+    }
+}
+
+impl Filter {
+    fn matches(&self, object: &Object) -> bool {
+        let todo = &object.todo;
+        if !self.todos || (self.open && todo.complete == Some(true)) {
+            return false;
+        }
+        match todo.due_at {
+            Some(due_at) => {
+                self.start.is_none_or(|start| due_at >= start)
+                    && self.end.is_none_or(|end| due_at < end)
+            }
+            None => true,
+        }
+    }
+}
+
+fn parse_filter(filter: Node) -> Filter {
+    let mut parsed = Filter::default();
+    let Some(calendar) = child(filter, CALDAV, "comp-filter")
+        .filter(|node| node.attribute("name") == Some("VCALENDAR"))
+    else {
+        return parsed;
+    };
+    let components: Vec<Node> = calendar
+        .children()
+        .filter(|node| is(*node, CALDAV, "comp-filter"))
+        .collect();
+    if components.is_empty() {
+        parsed.todos = true;
+        return parsed;
+    }
+    let Some(todo) = components
+        .into_iter()
+        .find(|node| node.attribute("name") == Some("VTODO"))
+    else {
+        return parsed;
+    };
+    parsed.todos = true;
+
+    if let Some(range) = child(todo, CALDAV, "time-range") {
+        parsed.start = range.attribute("start").and_then(parse_utc);
+        parsed.end = range.attribute("end").and_then(parse_utc);
+    }
+    for prop_filter in todo
+        .children()
+        .filter(|node| is(*node, CALDAV, "prop-filter"))
+    {
+        let name = prop_filter.attribute("name").unwrap_or_default();
+        if name.eq_ignore_ascii_case("COMPLETED")
+            && child(prop_filter, CALDAV, "is-not-defined").is_some()
+        {
+            parsed.open = true;
+        }
+        if name.eq_ignore_ascii_case("STATUS") {
+            if let Some(text_match) = child(prop_filter, CALDAV, "text-match") {
+                let negated = text_match.attribute("negate-condition") == Some("yes");
+                let completed = text_match
+                    .text()
+                    .is_some_and(|text| text.trim().eq_ignore_ascii_case("COMPLETED"));
+                if negated && completed {
+                    parsed.open = true;
+                }
+            }
+        }
+    }
+    parsed
+}
+
+fn parse_utc(value: &str) -> Option<DateTime<Utc>> {
+    let naive = NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S").ok()?;
+    Some(Utc.from_utc_datetime(&naive))
+}
+
+/// The properties of a PROPFIND or REPORT request.
+fn prop_request(node: Node) -> PropRequest {
+    if child(node, DAV, "propname").is_some() {
+        return PropRequest::Names;
+    }
+    match child(node, DAV, "prop") {
+        Some(prop) => PropRequest::Props(
+            prop.children()
+                .filter(|node| node.is_element())
+                .map(|node| {
+                    let tag = node.tag_name();
+                    (
+                        tag.namespace().unwrap_or_default().to_string(),
+                        tag.name().to_string(),
+                    )
+                })
+                .collect(),
+        ),
+        None => PropRequest::All,
+    }
+}
+
+/// What `allprop` and `propname` cover.
+fn default_props(resource: &Resource) -> Vec<Name> {
+    let names: &[(&str, &str)] = match resource {
+        Resource::Root | Resource::Home => {
+            &[(DAV, "resourcetype"), (DAV, "current-user-principal")]
+        }
+        Resource::Principal => &[
+            (DAV, "resourcetype"),
+            (DAV, "displayname"),
+            (DAV, "principal-URL"),
+            (CALDAV, "calendar-home-set"),
+        ],
+        Resource::Calendar(_) => &[
+            (DAV, "resourcetype"),
+            (DAV, "displayname"),
+            (DAV, "sync-token"),
+            (CALDAV, "supported-calendar-component-set"),
+            (CALENDARSERVER, "getctag"),
+        ],
+        Resource::Object(_) => &[
+            (DAV, "resourcetype"),
+            (DAV, "getetag"),
+            (DAV, "getcontenttype"),
+            (DAV, "getlastmodified"),
+        ],
+    };
+    names
+        .iter()
+        .map(|(ns, name)| (ns.to_string(), name.to_string()))
+        .collect()
+}
+
+/// The status a conditional request fails with, if it does.
+fn precondition(headers: &HeaderMap, existing: Option<&Object>) -> Option<StatusCode> {
+    let none_match = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok());
+    if none_match.is_some_and(|value| value.trim() == "*") && existing.is_some() {
+        return Some(StatusCode::PRECONDITION_FAILED);
+    }
+    match (if_match(headers), existing) {
+        (Some(_), None) => Some(StatusCode::PRECONDITION_FAILED),
+        (Some(expected), Some(object)) => {
+            let current = etag(object);
+            let matched = expected
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag == current);
+            (!matched).then_some(StatusCode::PRECONDITION_FAILED)
+        }
+        (None, _) => None,
+    }
+}
+
+fn if_match(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::IF_MATCH)
+        .and_then(|value| value.to_str().ok())
+}
+
+fn etag(object: &Object) -> String {
+    format!("\"{}\"", object.todo.version)
+}
+
+fn options() -> Response {
+    (
+        StatusCode::OK,
+        [
+            ("dav", "1, 3, calendar-access"),
+            ("allow", "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, REPORT"),
+        ],
+    )
+        .into_response()
+}
+
+/// A response naming the precondition the request failed.
+fn dav_error(status: StatusCode, condition: &str) -> Response {
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<d:error xmlns:d=\"DAV:\" xmlns:c=\"{}\"><{}/></d:error>",
+        CALDAV, condition
+    );
+    (
+        status,
+        [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+fn dav_failed(err: sqlx::Error) -> Response {
+    println!("🔥 CalDAV request failed: {:?}", err);
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+/// Parses a request body, `None` if it is empty.
+fn parse_xml(body: &[u8]) -> Result<Option<Document<'_>>, StatusCode> {
+    let text = std::str::from_utf8(body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    Document::parse(text)
+        .map(Some)
+        .map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+fn is(node: Node, ns: &str, name: &str) -> bool {
+    node.is_element() && node.tag_name().namespace() == Some(ns) && node.tag_name().name() == name
+}
+
+fn element<'a, 'input>(node: Node<'a, 'input>, ns: &str, name: &str) -> Option<Node<'a, 'input>> {
+    is(node, ns, name).then_some(node)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, ns: &str, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| is(*child, ns, name))
+}
+
+/// Builds a 207 Multi-Status body.
+struct Multistatus {
+    body: String,
+}
+
+impl Multistatus {
+    fn new() -> Self {
+        Multistatus {
+            body: format!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<d:multistatus xmlns:d=\"{}\" xmlns:c=\"{}\" xmlns:cs=\"{}\">",
+                DAV, CALDAV, CALENDARSERVER
+            ),
+        }
+    }
+
+    fn response(&mut self, href: &str, found: Vec<(Name, String)>, missing: Vec<Name>) {
+        self.body
+            .push_str(&format!("<d:response><d:href>{}</d:href>", escape(href)));
+        if !found.is_empty() {
+            self.body.push_str("<d:propstat><d:prop>");
+            for (name, value) in &found {
+                self.body.push_str(&element_xml(name, value));
+            }
+            self.body
+                .push_str("</d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat>");
+        }
+        if !missing.is_empty() {
+            self.body.push_str("<d:propstat><d:prop>");
+            for name in &missing {
+                self.body.push_str(&element_xml(name, ""));
+            }
+            self.body
+                .push_str("</d:prop><d:status>HTTP/1.1 404 Not Found</d:status></d:propstat>");
+        }
+        self.body.push_str("</d:response>");
+    }
+
+    /// A resource that no longer exists.
+    fn gone(&mut self, href: &str) {
+        self.body.push_str(&format!(
+            "<d:response><d:href>{}</d:href><d:status>HTTP/1.1 404 Not Found</d:status></d:response>",
+            escape(href)
+        ));
+    }
+
+    fn finish(mut self, sync_token: Option<String>) -> Response {
+        if let Some(token) = sync_token {
+            self.body
+                .push_str(&format!("<d:sync-token>{}</d:sync-token>", escape(&token)));
+        }
+        self.body.push_str("</d:multistatus>");
+        (
+            StatusCode::MULTI_STATUS,
+            [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+            self.body,
+        )
+            .into_response()
+    }
+}
+
+/// A property element, prefixed if its namespace has one in the multistatus.
+fn element_xml((ns, name): &Name, value: &str) -> String {
+    let prefix = match ns.as_str() {
+        DAV => "d",
+        CALDAV => "c",
+        CALENDARSERVER => "cs",
+        _ => {
+            return match value {
+                "" => format!("<x:{} xmlns:x=\"{}\"/>", name, escape(ns)),
+                value => format!(
+                    "<x:{} xmlns:x=\"{}\">{}</x:{}>",
+                    name,
+                    escape(ns),
+                    value,
+                    name
+                ),
+            }
+        }
+    };
+    match value {
+        "" => format!("<{}:{}/>", prefix, name),
+        value => format!("<{}:{}>{}</{}:{}>", prefix, name, value, prefix, name),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Percent-encodes an object name for use in a path.
+fn encode_segment(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'@' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn decode_segment(segment: &str) -> String {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| segment.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
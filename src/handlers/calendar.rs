@@ -0,0 +1,200 @@
+use crate::{
+    auth::{hash_token, random_token},
+    ical,
+    model::UserModel,
+    schema::{
+        CalendarFeedCreatedResponse, CalendarFeedListResponse, CalendarOptions, CreateCalendarFeed,
+        GenericResponse,
+    },
+    store, AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use sqlx::PgConnection;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// ----------------------------------------------------------------- DOWNLOAD_CALENDAR
+pub async fn download_calendar_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Query(opts): Query<CalendarOptions>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let mut conn = data.db.acquire().await.map_err(database_error)?;
+    let Some(calendar) = render(&mut conn, user.id, opts.list_id)
+        .await
+        .map_err(database_error)?
+    else {
+        return Err(list_not_found(opts.list_id.unwrap_or_default()));
+    };
+
+    let headers = [
+        (header::CONTENT_TYPE, ical::CONTENT_TYPE),
+        (
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"todos.ics\"",
+        ),
+    ];
+    Ok((headers, calendar))
+}
+
+// ----------------------------------------------------------------- CALENDAR_FEED
+/// The subscription URL calendar clients poll. The token is the only
+/// credential, so a wrong one gets a bare 404.
+pub async fn calendar_feed_handler(
+    Path(token): Path<String>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let token = token.strip_suffix(".ics").unwrap_or(&token);
+    let mut conn = data.db.acquire().await.map_err(feed_error)?;
+
+    let feed = store::calendar::poll(&mut *conn, &hash_token(token))
+        .await
+        .map_err(feed_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let calendar = render(&mut conn, feed.user_id, feed.list_id)
+        .await
+        .map_err(feed_error)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(([(header::CONTENT_TYPE, ical::CONTENT_TYPE)], calendar))
+}
+
+/// The user's calendar, or that of one of their lists. `None` if they have
+/// no such list.
+async fn render(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    list_id: Option<Uuid>,
+) -> Result<Option<String>, sqlx::Error> {
+    let name = match list_id {
+        Some(list_id) => match store::list::find(&mut *conn, user_id, list_id).await? {
+            Some(list) => list.name,
+            None => return Ok(None),
+        },
+        None => "Todos".to_string(),
+    };
+    let todos = store::todo::calendar(conn, user_id, list_id).await?;
+
+    Ok(Some(ical::calendar(&name, &todos)))
+}
+
+// ----------------------------------------------------------------- GET_CALENDAR_FEEDS
+pub async fn get_calendar_feeds_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let feeds = store::calendar::list(&data.db, user.id)
+        .await
+        .map_err(database_error)?;
+
+    let json_response = serde_json::json!(CalendarFeedListResponse {
+        status: "success".to_string(),
+        results: feeds.len(),
+        data: feeds,
+    });
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- CREATE_CALENDAR_FEED
+pub async fn create_calendar_feed_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<CreateCalendarFeed>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if let Some(list_id) = body.list_id {
+        let list = store::list::find(&data.db, user.id, list_id)
+            .await
+            .map_err(database_error)?;
+        if list.is_none() {
+            return Err(list_not_found(list_id));
+        }
+    }
+
+    let token = random_token();
+    let feed = store::calendar::create(&data.db, user.id, body.list_id, &hash_token(&token))
+        .await
+        .map_err(database_error)?;
+
+    let json_response = serde_json::json!(CalendarFeedCreatedResponse {
+        status: "success".to_string(),
+        url: feed_url(&data, &token),
+        token,
+        data: feed,
+    });
+    Ok((StatusCode::CREATED, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- ROTATE_CALENDAR_FEED
+pub async fn rotate_calendar_feed_handler(
+    Path(id): Path<Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let token = random_token();
+    let feed = store::calendar::rotate(&data.db, user.id, id, &hash_token(&token))
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| feed_not_found(id))?;
+
+    let json_response = serde_json::json!(CalendarFeedCreatedResponse {
+        status: "success".to_string(),
+        url: feed_url(&data, &token),
+        token,
+        data: feed,
+    });
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- DELETE_CALENDAR_FEED
+pub async fn delete_calendar_feed_handler(
+    Path(id): Path<Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let deleted = store::calendar::delete(&data.db, user.id, id)
+        .await
+        .map_err(database_error)?;
+    if !deleted {
+        return Err(feed_not_found(id));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn feed_error(err: sqlx::Error) -> StatusCode {
+    println!("🔥 Failed to serve a calendar feed: {:?}", err);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+fn feed_url(data: &AppState, token: &str) -> String {
+    format!("{}/api/calendar/{}.ics", data.env.app_url, token)
+}
+
+fn list_not_found(id: Uuid) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "fail".to_string(),
+        message: format!("List with ID: {} not found", id),
+    });
+    (StatusCode::NOT_FOUND, Json(error_response))
+}
+
+fn feed_not_found(id: Uuid) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "fail".to_string(),
+        message: format!("Calendar feed with ID: {} not found", id),
+    });
+    (StatusCode::NOT_FOUND, Json(error_response))
+}
+
+fn database_error(err: sqlx::Error) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "error".to_string(),
+        message: format!("{:?}", err),
+    });
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+}
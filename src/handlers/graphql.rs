@@ -0,0 +1,90 @@
+use crate::{auth::Credential, graphql, model::UserModel, AppState};
+use async_graphql::{
+    http::{WebSocket as GraphQLWebSocket, WebSocketProtocols, WsMessage, ALL_WEBSOCKET_PROTOCOLS},
+    Data, Request, Response,
+};
+use axum::{
+    extract::{
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use futures::{future, SinkExt, StreamExt};
+use std::{str::FromStr, sync::Arc};
+
+// ----------------------------------------------------------------- GRAPHQL
+pub async fn graphql_handler(
+    Extension(user): Extension<UserModel>,
+    Extension(credential): Extension<Credential>,
+    State(data): State<Arc<AppState>>,
+    Json(mut request): Json<Request>,
+) -> Json<Response> {
+    request.data = graphql::context(&data, user, credential);
+
+    Json(data.graphql.execute(request).await)
+}
+
+// ----------------------------------------------------------------- GRAPHQL_SOCKET
+/// Subscriptions over either the `graphql-transport-ws` or the older
+/// `graphql-ws` protocol, whichever the client asks for first.
+pub async fn graphql_socket_handler(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    Extension(user): Extension<UserModel>,
+    Extension(credential): Extension<Credential>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let protocol = headers
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|protocols| {
+            protocols
+                .split(',')
+                .find_map(|protocol| WebSocketProtocols::from_str(protocol.trim()).ok())
+        })
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let context = graphql::context(&data, user, credential);
+    Ok(ws
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |socket| serve(socket, data, context, protocol)))
+}
+
+/// Runs the subscriptions the client starts until either side goes away.
+async fn serve(
+    socket: WebSocket,
+    data: Arc<AppState>,
+    context: Data,
+    protocol: WebSocketProtocols,
+) {
+    let (mut sender, receiver) = socket.split();
+    let incoming = receiver
+        .take_while(|message| {
+            future::ready(matches!(message, Ok(message) if !matches!(message, Message::Close(_))))
+        })
+        .filter_map(|message| {
+            future::ready(match message {
+                Ok(Message::Text(text)) => Some(text.into_bytes()),
+                Ok(Message::Binary(bytes)) => Some(bytes),
+                _ => None,
+            })
+        });
+
+    let mut outgoing =
+        GraphQLWebSocket::new(data.graphql.clone(), incoming, protocol).connection_data(context);
+    while let Some(message) = outgoing.next().await {
+        let message = match message {
+            WsMessage::Text(text) => Message::Text(text),
+            WsMessage::Close(code, reason) => Message::Close(Some(CloseFrame {
+                code,
+                reason: reason.into(),
+            })),
+        };
+        if sender.send(message).await.is_err() {
+            break;
+        }
+    }
+}
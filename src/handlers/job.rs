@@ -0,0 +1,99 @@
+use crate::{
+    schema::{GenericResponse, JobListOptions, JobListResponse, JobSingleResponse},
+    store, AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use std::sync::Arc;
+
+// ----------------------------------------------------------------- GET_JOBS
+pub async fn get_jobs_handler(
+    options: Option<Query<JobListOptions>>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let Query(options) = options.unwrap_or_default();
+
+    let limit = options.limit.unwrap_or(10);
+    let offset = (options.page.unwrap_or(1) - 1) * limit;
+
+    let jobs = store::job::list(
+        &data.db,
+        options.status,
+        options.kind.as_deref(),
+        limit as i64,
+        offset as i64,
+    )
+    .await
+    .map_err(job_failed)?;
+
+    let json_response = serde_json::json!(JobListResponse {
+        status: "success".to_string(),
+        results: jobs.len(),
+        data: jobs,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- GET_JOB
+pub async fn get_job_handler(
+    Path(id): Path<uuid::Uuid>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let job = store::job::find(&data.db, id)
+        .await
+        .map_err(job_failed)?
+        .ok_or_else(|| job_not_found(id))?;
+
+    let json_response = serde_json::json!(JobSingleResponse {
+        status: "success".to_string(),
+        data: job,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- RETRY_JOB
+/// Queues a dead job again with fresh attempts.
+pub async fn retry_job_handler(
+    Path(id): Path<uuid::Uuid>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let job = store::job::retry(&data.db, id)
+        .await
+        .map_err(job_failed)?
+        .ok_or_else(|| {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: format!("No dead job with ID: {}", id),
+            });
+            (StatusCode::NOT_FOUND, Json(error_response))
+        })?;
+
+    let json_response = serde_json::json!(JobSingleResponse {
+        status: "success".to_string(),
+        data: job,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+fn job_not_found(id: uuid::Uuid) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "fail".to_string(),
+        message: format!("Job with ID: {} not found", id)
+    });
+    (StatusCode::NOT_FOUND, Json(error_response))
+}
+
+fn job_failed(err: sqlx::Error) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "error".to_string(),
+        message: format!("{:?}", err),
+    });
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+}
@@ -0,0 +1,186 @@
+use crate::{
+    handlers::todo::list_todos,
+    model::UserModel,
+    schema::{
+        CreateList, FilterOptions, GenericResponse, ListListResponse, ListOptions,
+        ListSingleResponse, UpdateList,
+    },
+    store, AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use std::sync::Arc;
+
+// ----------------------------------------------------------------- CREATE_LIST
+pub async fn create_list_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<CreateList>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if body.name.trim().is_empty() {
+        return Err(empty_name());
+    }
+
+    let query = match data.db.acquire().await {
+        Ok(mut conn) => store::list::create(&mut conn, user.id, &body).await,
+        Err(err) => Err(err),
+    };
+
+    match query {
+        Ok(list) => {
+            let json_response = serde_json::json!(ListSingleResponse {
+                status: "success".to_string(),
+                data: list,
+            });
+            Ok((StatusCode::CREATED, Json(json_response)))
+        }
+        Err(err) => Err(list_failed(err)),
+    }
+}
+
+// ----------------------------------------------------------------- GET_LISTS
+pub async fn get_lists_handler(
+    options: Option<Query<ListOptions>>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let Query(options) = options.unwrap_or_default();
+
+    let lists = store::list::list(&data.db, user.id, options.archived.unwrap_or(false))
+        .await
+        .map_err(list_failed)?;
+
+    let json_response = serde_json::json!(ListListResponse {
+        status: "success".to_string(),
+        results: lists.len(),
+        data: lists,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- GET_LIST
+pub async fn get_list_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let access = store::share::list_access(&data.db, user.id, id)
+        .await
+        .map_err(list_failed)?
+        .ok_or_else(|| list_not_found(id))?;
+    let list = store::list::find(&data.db, access.owner_id, id)
+        .await
+        .map_err(list_failed)?
+        .ok_or_else(|| list_not_found(id))?;
+
+    let json_response = serde_json::json!(ListSingleResponse {
+        status: "success".to_string(),
+        data: list,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- UPDATE_LIST
+pub async fn update_list_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<UpdateList>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if body
+        .name
+        .as_deref()
+        .is_some_and(|name| name.trim().is_empty())
+    {
+        return Err(empty_name());
+    }
+
+    let list = match data.db.acquire().await {
+        Ok(mut conn) => store::list::update(&mut conn, user.id, id, &body).await,
+        Err(err) => Err(err),
+    }
+    .map_err(list_failed)?
+    .ok_or_else(|| list_not_found(id))?;
+
+    let json_response = serde_json::json!(ListSingleResponse {
+        status: "success".to_string(),
+        data: list,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- DELETE_LIST
+pub async fn delete_list_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let deleted = match data.db.acquire().await {
+        Ok(mut conn) => store::list::delete(&mut conn, user.id, id).await,
+        Err(err) => Err(err),
+    }
+    .map_err(list_failed)?;
+
+    if !deleted {
+        return Err(list_not_found(id));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ----------------------------------------------------------------- GET_LIST_TODOS
+pub async fn get_list_todos_handler(
+    Path(id): Path<uuid::Uuid>,
+    options: Option<Query<FilterOptions>>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let Query(mut options) = options.unwrap_or_default();
+    options.list_id = Some(id);
+
+    store::share::list_access(&data.db, user.id, id)
+        .await
+        .map_err(list_failed)?
+        .ok_or_else(|| list_not_found(id))?;
+
+    list_todos(&data, &user, options, "position").await
+}
+
+fn empty_name() -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "fail".to_string(),
+        message: "List name must not be empty".to_string(),
+    });
+    (StatusCode::BAD_REQUEST, Json(error_response))
+}
+
+pub(crate) fn list_not_found(id: uuid::Uuid) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "fail".to_string(),
+        message: format!("List with ID: {} not found", id)
+    });
+    (StatusCode::NOT_FOUND, Json(error_response))
+}
+
+fn list_failed(err: sqlx::Error) -> (StatusCode, Json<serde_json::Value>) {
+    if store::is_unique_violation(&err) {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "List with that name already exists".to_string(),
+        });
+        return (StatusCode::CONFLICT, Json(error_response));
+    }
+
+    let error_response = serde_json::json!(GenericResponse {
+        status: "error".to_string(),
+        message: format!("{:?}", err),
+    });
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+}
@@ -1,3 +1,16 @@
 pub mod auth;
+pub mod avatar;
+pub mod caldav;
+pub mod calendar;
+pub mod graphql;
 pub mod health;
+pub mod job;
+pub mod list;
+pub mod oidc;
+pub mod share;
+pub mod stream;
+pub mod tag;
 pub mod todo;
+pub mod token;
+pub mod transfer;
+pub mod webhook;
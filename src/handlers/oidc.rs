@@ -0,0 +1,270 @@
+use crate::{
+    config::OidcProvider,
+    events::{self, UserChangeKind},
+    handlers::auth::session_response,
+    model::UserModel,
+    oidc::{self, IdClaims, LoginState, OidcError},
+    schema::{GenericResponse, OidcCallback},
+    store, webhook, AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Redirect},
+    Json,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use std::sync::Arc;
+
+const LOGIN_COOKIE: &str = "oidc";
+
+fn provider_error(err: OidcError) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "error".to_string(),
+        message: err.to_string(),
+    });
+    (StatusCode::BAD_GATEWAY, Json(error_response))
+}
+
+fn find_provider<'a>(
+    data: &'a AppState,
+    name: &str,
+) -> Result<&'a OidcProvider, (StatusCode, Json<serde_json::Value>)> {
+    data.env.oidc_provider(name).ok_or_else(|| {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Unknown login provider: {}", name),
+        });
+        (StatusCode::NOT_FOUND, Json(error_response))
+    })
+}
+
+// ----------------------------------------------------------------- OIDC_LOGIN
+pub async fn oidc_login_handler(
+    Path(provider): Path<String>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let provider = find_provider(&data, &provider)?;
+    let discovery = oidc::discover(&data.http, provider)
+        .await
+        .map_err(provider_error)?;
+
+    let login = LoginState {
+        provider: provider.name.clone(),
+        state: oidc::random_string(),
+        nonce: oidc::random_string(),
+        verifier: oidc::random_string(),
+        exp: (chrono::Utc::now() + chrono::Duration::minutes(10)).timestamp() as usize,
+    };
+    let url = oidc::authorization_url(&discovery, provider, &login).map_err(provider_error)?;
+
+    let value = encode(
+        &Header::default(),
+        &login,
+        &EncodingKey::from_secret(data.env.jwt_secret.as_ref()),
+    )
+    .unwrap();
+    let cookie = Cookie::build(LOGIN_COOKIE, value)
+        .path("/auth/oidc")
+        .max_age(time::Duration::minutes(10))
+        .same_site(SameSite::Lax)
+        .http_only(true)
+        .finish();
+
+    Ok((
+        [(header::SET_COOKIE, cookie.to_string())],
+        Redirect::to(&url),
+    ))
+}
+
+// ----------------------------------------------------------------- OIDC_CALLBACK
+pub async fn oidc_callback_handler(
+    Path(provider): Path<String>,
+    Query(query): Query<OidcCallback>,
+    cookie_jar: CookieJar,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if let Some(error) = query.error {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Login was not completed: {}", error),
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    let provider = find_provider(&data, &provider)?;
+
+    let login = cookie_jar
+        .get(LOGIN_COOKIE)
+        .and_then(|cookie| {
+            decode::<LoginState>(
+                cookie.value(),
+                &DecodingKey::from_secret(data.env.jwt_secret.as_ref()),
+                &Validation::default(),
+            )
+            .ok()
+        })
+        .map(|token| token.claims)
+        .filter(|login| {
+            login.provider == provider.name && Some(&login.state) == query.state.as_ref()
+        })
+        .ok_or_else(|| {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: "Login session is missing, expired or does not match".to_string(),
+            });
+            (StatusCode::BAD_REQUEST, Json(error_response))
+        })?;
+
+    let code = query.code.ok_or_else(|| {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "Missing authorization code".to_string(),
+        });
+        (StatusCode::BAD_REQUEST, Json(error_response))
+    })?;
+
+    let discovery = oidc::discover(&data.http, provider)
+        .await
+        .map_err(provider_error)?;
+    let claims = oidc::exchange_code(&data.http, &discovery, provider, &login, &code)
+        .await
+        .map_err(provider_error)?;
+
+    let user_id = find_or_provision_user(&data, provider, &claims).await?;
+
+    let removal = Cookie::build(LOGIN_COOKIE, "")
+        .path("/auth/oidc")
+        .max_age(time::Duration::hours(-1))
+        .finish();
+    let mut response = session_response(&data, user_id, Some(&provider.name));
+    response
+        .headers_mut()
+        .append(header::SET_COOKIE, removal.to_string().parse().unwrap());
+
+    Ok(response)
+}
+
+/// Resolves the external identity to a user, linking a verified mail to an
+/// existing account whose owner verified it too, or creating a new one from
+/// the ID token claims.
+async fn find_or_provision_user(
+    data: &AppState,
+    provider: &OidcProvider,
+    claims: &IdClaims,
+) -> Result<uuid::Uuid, (StatusCode, Json<serde_json::Value>)> {
+    let database_error = |e: sqlx::Error| {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "error".to_string(),
+            message: format!("Database error: {}", e),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+    };
+
+    let linked = sqlx::query_scalar!(
+        "SELECT user_id FROM user_identities WHERE provider = $1 AND subject = $2",
+        provider.name,
+        claims.sub
+    )
+    .fetch_optional(&data.db)
+    .await
+    .map_err(database_error)?;
+
+    if let Some(user_id) = linked {
+        return Ok(user_id);
+    }
+
+    let mail = claims
+        .email
+        .as_ref()
+        .map(|mail| mail.to_ascii_lowercase())
+        .ok_or_else(|| {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: "The provider did not share an email address".to_string(),
+            });
+            (StatusCode::BAD_REQUEST, Json(error_response))
+        })?;
+    let verified = claims.email_verified.unwrap_or(false);
+
+    let mut tx = data.db.begin().await.map_err(database_error)?;
+
+    let existing = sqlx::query_as!(UserModel, "SELECT * FROM users WHERE mail = $1", mail)
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(database_error)?;
+
+    let (user_id, kind) = match existing {
+        Some(_) if !verified => {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: "User with that mail already exists".to_string(),
+            });
+            return Err((StatusCode::CONFLICT, Json(error_response)));
+        }
+        // Anyone can sign up with a mail they do not own, so only an account
+        // whose owner proved the mail is linked. Otherwise the squatter's
+        // password would keep working on the provider user's account.
+        Some(user) if user.verify != Some(true) => {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: "User with that mail already exists and has not verified it".to_string(),
+            });
+            return Err((StatusCode::CONFLICT, Json(error_response)));
+        }
+        Some(user) => {
+            sqlx::query!(
+                "UPDATE users SET photo = $1, updated_at = NOW() WHERE id = $2 AND photo = ''",
+                claims.picture.clone().unwrap_or_default(),
+                user.id
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(database_error)?;
+            (user.id, UserChangeKind::Updated)
+        }
+        None => {
+            let name = claims
+                .name
+                .clone()
+                .unwrap_or_else(|| mail.split('@').next().unwrap_or_default().to_string());
+            // An empty password can never verify, so the account is
+            // federated-only until the user sets one.
+            let id = sqlx::query_scalar!(
+                "INSERT INTO users (name,mail,password,photo,verify) VALUES ($1, $2, '', $3, $4) RETURNING id",
+                name,
+                mail,
+                claims.picture.clone().unwrap_or_default(),
+                verified,
+            )
+            .fetch_one(&mut tx)
+            .await
+            .map_err(database_error)?;
+            let payload = webhook::signed_up(id, &name, &mail);
+            store::webhook::enqueue(&mut tx, None, "user.signed_up", &payload)
+                .await
+                .map_err(database_error)?;
+            (id, UserChangeKind::Created)
+        }
+    };
+
+    sqlx::query!(
+        "INSERT INTO user_identities (user_id,provider,subject,mail) VALUES ($1, $2, $3, $4)",
+        user_id,
+        provider.name,
+        claims.sub,
+        mail,
+    )
+    .execute(&mut tx)
+    .await
+    .map_err(database_error)?;
+
+    events::notify_user(&mut tx, user_id, kind)
+        .await
+        .map_err(database_error)?;
+
+    tx.commit().await.map_err(database_error)?;
+
+    Ok(user_id)
+}
@@ -0,0 +1,305 @@
+use crate::{
+    handlers::{list::list_not_found, todo::authorize},
+    jobs::{self, SendMail},
+    model::{ShareRole, ShareStatus, UserModel},
+    schema::{CreateShare, GenericResponse, ShareListResponse, ShareSingleResponse, UpdateShare},
+    store::{
+        self,
+        share::{Role, Target},
+    },
+    AppState,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use std::sync::Arc;
+
+// ----------------------------------------------------------------- GET_TODO_SHARES
+pub async fn get_todo_shares_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    // Shares name the other recipients' mail addresses, so only the owner,
+    // who made them, gets to see them.
+    authorize(&data.db, user.id, id, Role::Owner)
+        .await
+        .map_err(|denied| denied.response(id))?;
+
+    list_shares(&data, Target::Todo(id)).await
+}
+
+// ----------------------------------------------------------------- SHARE_TODO
+pub async fn share_todo_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<CreateShare>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    authorize(&data.db, user.id, id, Role::Owner)
+        .await
+        .map_err(|denied| denied.response(id))?;
+
+    share(&data, &user, Target::Todo(id), body).await
+}
+
+// ----------------------------------------------------------------- GET_LIST_SHARES
+pub async fn get_list_shares_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let access = store::share::list_access(&data.db, user.id, id)
+        .await
+        .map_err(share_failed)?
+        .ok_or_else(|| list_not_found(id))?;
+    if access.role < Role::Owner {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Only the owner can see the shares of List with ID: {}", id),
+        });
+        return Err((StatusCode::FORBIDDEN, Json(error_response)));
+    }
+
+    list_shares(&data, Target::List(id)).await
+}
+
+// ----------------------------------------------------------------- SHARE_LIST
+pub async fn share_list_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<CreateShare>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let access = store::share::list_access(&data.db, user.id, id)
+        .await
+        .map_err(share_failed)?
+        .ok_or_else(|| list_not_found(id))?;
+    if access.role < Role::Owner {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Only the owner can share List with ID: {}", id),
+        });
+        return Err((StatusCode::FORBIDDEN, Json(error_response)));
+    }
+
+    share(&data, &user, Target::List(id), body).await
+}
+
+// ----------------------------------------------------------------- UPDATE_SHARE
+pub async fn update_share_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<UpdateShare>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let share = match data.db.acquire().await {
+        Ok(mut conn) => store::share::update(&mut conn, user.id, id, body.role).await,
+        Err(err) => Err(err),
+    }
+    .map_err(share_failed)?
+    .ok_or_else(|| share_not_found(id))?;
+
+    let json_response = serde_json::json!(ShareSingleResponse {
+        status: "success".to_string(),
+        data: share,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- DELETE_SHARE
+pub async fn delete_share_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let deleted = store::share::delete(&data.db, user.id, id)
+        .await
+        .map_err(share_failed)?;
+
+    if !deleted {
+        return Err(share_not_found(id));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ----------------------------------------------------------------- GET_SHARED
+pub async fn get_shared_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let shares = store::share::shared_with(&data.db, user.id)
+        .await
+        .map_err(share_failed)?;
+
+    let json_response = serde_json::json!(ShareListResponse {
+        status: "success".to_string(),
+        results: shares.len(),
+        data: shares,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- GET_INVITATIONS
+pub async fn get_invitations_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let invitations = store::share::invitations(&data.db, &user.mail)
+        .await
+        .map_err(share_failed)?;
+
+    let json_response = serde_json::json!(ShareListResponse {
+        status: "success".to_string(),
+        results: invitations.len(),
+        data: invitations,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- ACCEPT_INVITATION
+pub async fn accept_invitation_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    answer(&data, &user, id, ShareStatus::Accepted).await
+}
+
+// ----------------------------------------------------------------- DECLINE_INVITATION
+pub async fn decline_invitation_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    answer(&data, &user, id, ShareStatus::Declined).await
+}
+
+async fn list_shares(
+    data: &AppState,
+    target: Target,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<serde_json::Value>)> {
+    let shares = store::share::list(&data.db, target)
+        .await
+        .map_err(share_failed)?;
+
+    let json_response = serde_json::json!(ShareListResponse {
+        status: "success".to_string(),
+        results: shares.len(),
+        data: shares,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+/// Invites the address in `body` to something of the user's and lets them
+/// know by mail.
+async fn share(
+    data: &AppState,
+    user: &UserModel,
+    target: Target,
+    body: CreateShare,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<serde_json::Value>)> {
+    let mail = body.mail.trim().to_ascii_lowercase();
+    let message = if !mail.contains('@') {
+        Some("Invalid mail")
+    } else if mail == user.mail {
+        Some("You cannot share with yourself")
+    } else {
+        None
+    };
+    if let Some(message) = message {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: message.to_string(),
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    let role = body.role.unwrap_or(ShareRole::Viewer);
+    let share = match data.db.acquire().await {
+        Ok(mut conn) => store::share::create(&mut conn, user.id, target, &mail, role).await,
+        Err(err) => Err(err),
+    }
+    .map_err(share_failed)?;
+
+    // The share stands even if the mail does not go out, the invitation is
+    // listed either way.
+    let link = format!("{}/api/invitations", data.env.app_url);
+    let invitation = SendMail {
+        to: mail.clone(),
+        subject: format!("{} shared \"{}\" with you", user.name, share.subject),
+        body: format!(
+            "{} invited you to \"{}\". Accept or decline the invitation at:\n{}",
+            user.name, share.subject, link
+        ),
+    };
+    if let Err(err) = jobs::enqueue(&data.db, &invitation).await {
+        println!("🔥 Could not queue invitation to {}: {:?}", mail, err);
+    }
+
+    let json_response = serde_json::json!(ShareSingleResponse {
+        status: "success".to_string(),
+        data: share,
+    });
+
+    Ok((StatusCode::CREATED, Json(json_response)))
+}
+
+async fn answer(
+    data: &AppState,
+    user: &UserModel,
+    id: uuid::Uuid,
+    status: ShareStatus,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<serde_json::Value>)> {
+    let share = match data.db.acquire().await {
+        Ok(mut conn) => store::share::answer(&mut conn, user.id, &user.mail, id, status).await,
+        Err(err) => Err(err),
+    }
+    .map_err(share_failed)?
+    .ok_or_else(|| {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("No open invitation with ID: {}", id),
+        });
+        (StatusCode::NOT_FOUND, Json(error_response))
+    })?;
+
+    let json_response = serde_json::json!(ShareSingleResponse {
+        status: "success".to_string(),
+        data: share,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+fn share_not_found(id: uuid::Uuid) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "fail".to_string(),
+        message: format!("Share with ID: {} not found", id)
+    });
+    (StatusCode::NOT_FOUND, Json(error_response))
+}
+
+fn share_failed(err: sqlx::Error) -> (StatusCode, Json<serde_json::Value>) {
+    if store::is_unique_violation(&err) {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "Already shared with that mail address".to_string(),
+        });
+        return (StatusCode::CONFLICT, Json(error_response));
+    }
+
+    let error_response = serde_json::json!(GenericResponse {
+        status: "error".to_string(),
+        message: format!("{:?}", err),
+    });
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+}
@@ -0,0 +1,194 @@
+use crate::{
+    events::{position, Feed, Update},
+    model::UserModel,
+    schema::{GenericResponse, StreamMessage, StreamOptions},
+    AppState,
+};
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Extension, Json,
+};
+use futures::{sink::SinkExt, stream::SplitSink, Stream, StreamExt};
+use std::{collections::VecDeque, convert::Infallible, sync::Arc, time::Duration};
+
+/// How often the server pings. A client that did not answer by the next
+/// ping is disconnected.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+/// How often an idle event stream gets a comment, so proxies keep it open.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// How long a client may take to accept a message before it counts as too
+/// slow and is disconnected. It can resume from its last event id.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+// ----------------------------------------------------------------- TODO_SOCKET
+pub async fn todo_socket_handler(
+    ws: WebSocketUpgrade,
+    options: Option<Query<StreamOptions>>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    let Query(options) = options.unwrap_or_default();
+
+    ws.on_upgrade(move |socket| follow(socket, data, user, options))
+}
+
+// ----------------------------------------------------------------- TODO_EVENTS
+pub async fn todo_events_handler(
+    headers: HeaderMap,
+    options: Option<Query<StreamOptions>>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<serde_json::Value>)>
+{
+    let Query(mut options) = options.unwrap_or_default();
+    // Browsers send the id of the last event they got when they reconnect,
+    // which is its position.
+    if let Some(last_event_id) = headers
+        .get("Last-Event-ID")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+    {
+        options.last_event_id = Some(last_event_id);
+    }
+
+    let (feed, missed) = Feed::open(&data, user.id, options).await.map_err(|err| {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "error".to_string(),
+            message: format!("{:?}", err),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+    })?;
+
+    let pending = VecDeque::from(missed);
+    let stream = futures::stream::unfold(
+        (feed, pending, data),
+        |(mut feed, mut pending, data)| async move {
+            loop {
+                if let Some(update) = pending.pop_front() {
+                    return Some((Ok(event(&update)), (feed, pending, data)));
+                }
+
+                let received = feed.recv().await;
+                match feed.handle(&data.db, received).await {
+                    Ok(Some(updates)) => pending.extend(updates),
+                    Ok(None) => return None,
+                    Err(err) => {
+                        println!("🔥 Failed to follow todo events: {:?}", err);
+                        return None;
+                    }
+                }
+            }
+        },
+    );
+
+    Ok(Sse::new(stream).keep_alive(
+        KeepAlive::new()
+            .interval(KEEP_ALIVE_INTERVAL)
+            .text("keep-alive"),
+    ))
+}
+
+/// An update as server-sent event, named after the kind of change.
+fn event(update: &Update) -> Event {
+    match update {
+        Update::Event(event) => Event::default()
+            .id(position(event).to_string())
+            .event(&event.kind)
+            .json_data(event.as_ref())
+            .unwrap_or_default(),
+        Update::Reset(position) => Event::default()
+            .id(position.to_string())
+            .event("reset")
+            .data(serde_json::json!({ "position": position }).to_string()),
+    }
+}
+
+/// Pushes the user's todo events until either side goes away.
+async fn follow(socket: WebSocket, data: Arc<AppState>, user: UserModel, options: StreamOptions) {
+    let (mut sender, mut receiver) = socket.split();
+
+    let (mut feed, missed) = match Feed::open(&data, user.id, options).await {
+        Ok(opened) => opened,
+        Err(err) => {
+            println!("🔥 Failed to follow todo events: {:?}", err);
+            let close = CloseFrame {
+                code: close_code::ERROR,
+                reason: "Could not load the todo events".into(),
+            };
+            let _ = sender.send(Message::Close(Some(close))).await;
+            return;
+        }
+    };
+    if !push(&mut sender, missed).await {
+        return;
+    }
+
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    heartbeat.tick().await;
+    let mut alive = true;
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => {
+                if !alive {
+                    break;
+                }
+                alive = false;
+                if !send(&mut sender, Message::Ping(Vec::new())).await {
+                    break;
+                }
+            }
+            message = receiver.next() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Pongs and anything else show the client is still there.
+                Some(Ok(_)) => alive = true,
+            },
+            received = feed.recv() => match feed.handle(&data.db, received).await {
+                Ok(Some(updates)) => {
+                    if !push(&mut sender, updates).await {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    println!("🔥 Failed to follow todo events: {:?}", err);
+                    break;
+                }
+            },
+        }
+    }
+}
+
+/// Sends the updates in order. `false` if the client is gone or too slow.
+async fn push(sender: &mut SplitSink<WebSocket, Message>, updates: Vec<Update>) -> bool {
+    for update in updates {
+        let message = match &update {
+            Update::Event(event) => StreamMessage::Event {
+                position: position(event),
+                data: event,
+            },
+            Update::Reset(position) => StreamMessage::Reset {
+                position: *position,
+            },
+        };
+        let text = serde_json::to_string(&message).unwrap_or_default();
+        if !send(sender, Message::Text(text)).await {
+            return false;
+        }
+    }
+    true
+}
+
+async fn send(sender: &mut SplitSink<WebSocket, Message>, message: Message) -> bool {
+    matches!(
+        tokio::time::timeout(SEND_TIMEOUT, sender.send(message)).await,
+        Ok(Ok(()))
+    )
+}
@@ -0,0 +1,162 @@
+use crate::{
+    model::UserModel,
+    schema::{CreateTag, GenericResponse, TagListResponse, TagSingleResponse, UpdateTag},
+    store, AppState,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use std::sync::Arc;
+
+// ----------------------------------------------------------------- CREATE_TAG
+pub async fn create_tag_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<CreateTag>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    validate(Some(&body.name), body.color.as_deref())?;
+
+    let tag = store::tag::create(&data.db, user.id, &body)
+        .await
+        .map_err(tag_failed)?;
+
+    let json_response = serde_json::json!(TagSingleResponse {
+        status: "success".to_string(),
+        data: tag,
+    });
+
+    Ok((StatusCode::CREATED, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- GET_TAGS
+pub async fn get_tags_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let tags = store::tag::list(&data.db, user.id)
+        .await
+        .map_err(tag_failed)?;
+
+    let json_response = serde_json::json!(TagListResponse {
+        status: "success".to_string(),
+        results: tags.len(),
+        data: tags,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- GET_TAG
+pub async fn get_tag_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let tag = store::tag::find(&data.db, user.id, id)
+        .await
+        .map_err(tag_failed)?
+        .ok_or_else(|| tag_not_found(id))?;
+
+    let json_response = serde_json::json!(TagSingleResponse {
+        status: "success".to_string(),
+        data: tag,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- UPDATE_TAG
+pub async fn update_tag_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<UpdateTag>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    validate(
+        body.name.as_deref(),
+        body.color.clone().flatten().as_deref(),
+    )?;
+
+    let tag = store::tag::update(&data.db, user.id, id, &body)
+        .await
+        .map_err(tag_failed)?
+        .ok_or_else(|| tag_not_found(id))?;
+
+    let json_response = serde_json::json!(TagSingleResponse {
+        status: "success".to_string(),
+        data: tag,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- DELETE_TAG
+pub async fn delete_tag_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let deleted = match data.db.acquire().await {
+        Ok(mut conn) => store::tag::delete(&mut conn, user.id, id).await,
+        Err(err) => Err(err),
+    }
+    .map_err(tag_failed)?;
+
+    if !deleted {
+        return Err(tag_not_found(id));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn validate(
+    name: Option<&str>,
+    color: Option<&str>,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    let message = if name.is_some_and(|name| name.trim().is_empty()) {
+        "Tag name must not be empty"
+    } else if name.is_some_and(|name| name.trim().chars().count() > 64) {
+        "Tag name must be at most 64 characters"
+    } else if color.is_some_and(|color| {
+        color.len() != 7
+            || !color.starts_with('#')
+            || !color[1..].chars().all(|c| c.is_ascii_hexdigit())
+    }) {
+        "Tag color must look like #1e90ff"
+    } else {
+        return Ok(());
+    };
+
+    let error_response = serde_json::json!(GenericResponse {
+        status: "fail".to_string(),
+        message: message.to_string(),
+    });
+    Err((StatusCode::BAD_REQUEST, Json(error_response)))
+}
+
+fn tag_not_found(id: uuid::Uuid) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "fail".to_string(),
+        message: format!("Tag with ID: {} not found", id)
+    });
+    (StatusCode::NOT_FOUND, Json(error_response))
+}
+
+fn tag_failed(err: sqlx::Error) -> (StatusCode, Json<serde_json::Value>) {
+    if store::is_unique_violation(&err) {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "Tag with that name already exists".to_string(),
+        });
+        return (StatusCode::CONFLICT, Json(error_response));
+    }
+
+    let error_response = serde_json::json!(GenericResponse {
+        status: "error".to_string(),
+        message: format!("{:?}", err),
+    });
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+}
@@ -1,33 +1,53 @@
 use crate::{
-    model::ToDoModel,
+    model::{ToDoModel, UserModel},
+    recurrence::{self, RecurrenceError},
     schema::{
-        CreateToDo, FilterOptions, GenericResponse, ToDoListResponse, ToDoSingleResponse,
-        UpdateToDo,
+        BatchMode, BatchOperation, BatchRequest, BatchResponse, BatchResult, CreateToDo,
+        DeleteOptions, FilterOptions, GenericResponse, MoveToDo, OccurrenceListResponse,
+        OccurrenceOptions, RevertToDo, ToDoListResponse, ToDoSingleResponse, ToDoTreeResponse,
+        TodoEventListResponse, UpdateOptions, UpdateToDo,
+    },
+    store::{
+        self,
+        share::Role,
+        todo::{Actor, Failure, WriteError},
     },
     AppState,
 };
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
-    Json,
+    Extension, Json,
 };
-use serde_json::json;
+use sqlx::{Acquire, Executor, PgConnection, Postgres};
 use std::sync::Arc;
 
 // ----------------------------------------------------------------- CREATE_TODO
 pub async fn create_todo_handler(
+    Extension(user): Extension<UserModel>,
     State(data): State<Arc<AppState>>,
     Json(body): Json<CreateToDo>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let query = sqlx::query_as!(
-        ToDoModel,
-        "INSERT INTO todos (title,content) VALUES ($1,$2) RETURNING *",
-        body.title.to_string(),
-        body.content.to_string(),
-    )
-    .fetch_one(&data.db)
-    .await;
+    recurrence::validate(body.rrule.as_deref(), body.timezone.as_deref())
+        .map_err(invalid_recurrence)?;
+    let actor = creator(&data.db, user.id, &body)
+        .await
+        .map_err(|denied| match denied {
+            Denied::Forbidden => {
+                let error_response = serde_json::json!(GenericResponse {
+                    status: "fail".to_string(),
+                    message: "You are not allowed to add ToDos there".to_string(),
+                });
+                (StatusCode::FORBIDDEN, Json(error_response))
+            }
+            denied => denied.response(body.parent_id.unwrap_or_default()),
+        })?;
+
+    let query = match data.db.acquire().await {
+        Ok(mut conn) => store::todo::create(&mut conn, actor, &body).await,
+        Err(err) => Err(err.into()),
+    };
 
     match query {
         Ok(todo) => {
@@ -37,16 +57,19 @@ pub async fn create_todo_handler(
             });
             return Ok((StatusCode::CREATED, Json(json_response)));
         }
-        Err(err) => {
-            if err
-                .to_string()
-                .contains("duplicate key value violates unique constraint")
-            {
+        Err(WriteError::Database(err)) => {
+            if store::is_unique_violation(&err) {
                 let error_response = serde_json::json!(GenericResponse {
                     status: "fail".to_string(),
                     message: "ToDo with that title already exists".to_string(),
                 });
-                return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
+                return Err((StatusCode::CONFLICT, Json(error_response)));
+            }
+            if store::is_check_violation(&err) {
+                return Err(missing_due_date());
+            }
+            if store::is_foreign_key_violation(&err) {
+                return Err(unknown_reference(&err));
             }
 
             let error_response = serde_json::json!(GenericResponse {
@@ -55,55 +78,138 @@ pub async fn create_todo_handler(
             });
             return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error_response)));
         }
+        Err(err) => {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: err.to_string(),
+            });
+            Err((StatusCode::UNPROCESSABLE_ENTITY, Json(error_response)))
+        }
     }
 }
 
 // ----------------------------------------------------------------- GET_TODO
 pub async fn get_todo_handler(
     Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
     State(data): State<Arc<AppState>>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let query = sqlx::query_as!(ToDoModel, "SELECT * FROM todos WHERE id = $1", id)
-        .fetch_one(&data.db)
-        .await;
+    let actor = authorize(&data.db, user.id, id, Role::Viewer)
+        .await
+        .map_err(|denied| denied.response(id))?;
+    let query = match data.db.acquire().await {
+        Ok(mut conn) => store::todo::find(&mut conn, actor.owner_id, id).await,
+        Err(err) => Err(err),
+    };
 
     match query {
-        Ok(todo) => {
+        Ok(Some(todo)) => {
+            let tag = etag(&todo);
+            let unchanged = headers
+                .get(header::IF_NONE_MATCH)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| {
+                    value.split(',').map(str::trim).any(|candidate| {
+                        candidate == "*" || candidate.trim_start_matches("W/") == tag
+                    })
+                });
+            if unchanged {
+                return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, tag)]).into_response());
+            }
+
             let json_response = serde_json::json!(ToDoSingleResponse {
                 status: "".to_string(),
                 data: todo,
             });
-            return Ok((StatusCode::CREATED, Json(json_response)));
+            Ok((StatusCode::OK, [(header::ETAG, tag)], Json(json_response)).into_response())
         }
-        Err(_) => {
+        _ => {
             let error_response = serde_json::json!(GenericResponse {
                 status: "".to_string(),
                 message: format!("ToDo with ID: {} not found", id)
             });
 
-            return Err((StatusCode::NOT_FOUND, Json(error_response)));
+            Err((StatusCode::NOT_FOUND, Json(error_response)))
         }
     }
 }
 
+// ----------------------------------------------------------------- GET_SUBTASKS
+pub async fn get_subtasks_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let actor = authorize(&data.db, user.id, id, Role::Viewer)
+        .await
+        .map_err(|denied| denied.response(id))?;
+    let tree = match data.db.acquire().await {
+        Ok(mut conn) => store::todo::tree(&mut conn, actor.owner_id, id).await,
+        Err(err) => Err(err),
+    }
+    .map_err(|err| {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "error".to_string(),
+            message: format!("{:?}", err),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+    })?
+    .ok_or_else(|| {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("ToDo with ID: {} not found", id)
+        });
+        (StatusCode::NOT_FOUND, Json(error_response))
+    })?;
+
+    let json_response = serde_json::json!(ToDoTreeResponse {
+        status: "success".to_string(),
+        data: tree,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
 // ----------------------------------------------------------------- GET_TODOS
 pub async fn get_todos_handler(
     options: Option<Query<FilterOptions>>,
+    Extension(user): Extension<UserModel>,
     State(data): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
     let Query(options) = options.unwrap_or_default();
 
-    let limit = options.limit.unwrap_or(10);
-    let offset = (options.page.unwrap_or(1) - 1) * limit;
+    list_todos(&data, &user, options, "position").await
+}
 
-    let query = sqlx::query_as!(
-        ToDoModel,
-        "SELECT * FROM todos ORDER by id LIMIT $1 OFFSET $2",
-        limit as i32,
-        offset as i32
-    )
-    .fetch_all(&data.db)
-    .await;
+// ----------------------------------------------------------------- GET_OVERDUE_TODOS
+pub async fn get_overdue_todos_handler(
+    options: Option<Query<FilterOptions>>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let Query(mut options) = options.unwrap_or_default();
+    options.overdue = Some(true);
+
+    list_todos(&data, &user, options, "dueAt").await
+}
+
+pub(crate) async fn list_todos(
+    data: &AppState,
+    user: &UserModel,
+    options: FilterOptions,
+    default_sort: &str,
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<serde_json::Value>)> {
+    let sort = options.sort.as_deref().unwrap_or(default_sort);
+    let order = store::todo::order_by(sort).ok_or_else(|| {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Cannot sort by {}", sort),
+        });
+        (StatusCode::BAD_REQUEST, Json(error_response))
+    })?;
+
+    let query = fetch_todos(data, user.id, &options, &order).await;
 
     if query.is_err() {
         let error_response = serde_json::json!(GenericResponse {
@@ -121,82 +227,823 @@ pub async fn get_todos_handler(
         data: todos,
     });
 
-    return Ok((StatusCode::OK, Json(json_response)));
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+/// A page of the todos matching the filter. `order` must come from
+/// `store::todo::order_by`.
+pub(crate) async fn fetch_todos(
+    data: &AppState,
+    user_id: uuid::Uuid,
+    options: &FilterOptions,
+    order: &str,
+) -> Result<Vec<ToDoModel>, sqlx::Error> {
+    let limit = options.limit.unwrap_or(10);
+    let offset = (options.page.unwrap_or(1) - 1) * limit;
+
+    // The todos of a list or todo shared with the user belong to its owner.
+    let shared = match (options.list_id, options.parent_id) {
+        (Some(list_id), _) => store::share::list_access(&data.db, user_id, list_id).await?,
+        (None, Some(parent_id)) => store::share::todo_access(&data.db, user_id, parent_id).await?,
+        (None, None) => None,
+    };
+    let owner_id = shared.map_or(user_id, |access| access.owner_id);
+
+    let mut conn = data.db.acquire().await?;
+    store::todo::list(&mut conn, owner_id, options, order, limit, offset).await
 }
 
 // ----------------------------------------------------------------- UPDATE_TODO
 pub async fn update_todo_handler(
     Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
     State(data): State<Arc<AppState>>,
+    headers: HeaderMap,
+    options: Option<Query<UpdateOptions>>,
     Json(body): Json<UpdateToDo>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let query = sqlx::query_as!(ToDoModel, "SELECT * FROM todos WHERE id = $1", id)
-        .fetch_one(&data.db)
-        .await;
+    let Query(options) = options.unwrap_or_default();
+    let version = expected_version(&headers, body.version)?;
+    recurrence::validate(
+        body.rrule.clone().flatten().as_deref(),
+        body.timezone.as_deref(),
+    )
+    .map_err(invalid_recurrence)?;
+    let actor = authorize(&data.db, user.id, id, Role::Editor)
+        .await
+        .map_err(|denied| denied.response(id))?;
+    let cascade = options.cascade.unwrap_or(false);
+    let query = match data.db.acquire().await {
+        Ok(mut conn) => store::todo::update(&mut conn, actor, id, version, &body, cascade).await,
+        Err(err) => Err(err.into()),
+    };
+
+    written(&data, actor.owner_id, id, query).await
+}
+
+// ----------------------------------------------------------------- DELETE_TODO
+pub async fn delete_todo_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    headers: HeaderMap,
+    options: Option<Query<DeleteOptions>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let Query(options) = options.unwrap_or_default();
+    let version = expected_version(&headers, options.version)?;
+    let actor = authorize(&data.db, user.id, id, Role::Editor)
+        .await
+        .map_err(|denied| denied.response(id))?;
+
+    let deleted = match data.db.acquire().await {
+        Ok(mut conn) => {
+            let cascade = options.cascade.unwrap_or(true);
+            store::todo::delete(&mut conn, actor, id, version, cascade).await
+        }
+        Err(err) => Err(err),
+    }
+    .map_err(|err| {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "error".to_string(),
+            message: format!("{:?}", err),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+    })?;
+
+    if !deleted {
+        return Err(write_rejected(&data, actor.owner_id, id).await);
+    }
+
+    return Ok((StatusCode::NO_CONTENT, Json({})));
+}
+
+/// Strong entity tag of the todo's current revision.
+fn etag(todo: &ToDoModel) -> String {
+    format!("\"{}\"", todo.version)
+}
+
+/// The version a write is conditional on, taken from `If-Match` or else the
+/// `version` sent with the request. `None` stands for `If-Match: *`.
+fn expected_version(
+    headers: &HeaderMap,
+    version: Option<i32>,
+) -> Result<Option<i32>, (StatusCode, Json<serde_json::Value>)> {
+    let if_match = match headers.get(header::IF_MATCH) {
+        Some(value) => value.to_str().unwrap_or_default().trim(),
+        None => {
+            return version.map(Some).ok_or_else(|| {
+                let error_response = serde_json::json!(GenericResponse {
+                    status: "fail".to_string(),
+                    message: "Send the current version as If-Match header or version field"
+                        .to_string(),
+                });
+                (StatusCode::PRECONDITION_REQUIRED, Json(error_response))
+            })
+        }
+    };
+
+    if if_match == "*" {
+        return Ok(None);
+    }
+
+    // Weak tags never match for writes.
+    if_match
+        .split(',')
+        .map(str::trim)
+        .find_map(|tag| tag.strip_prefix('"')?.strip_suffix('"')?.parse().ok())
+        .map(Some)
+        .ok_or_else(|| {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: "If-Match does not match the current version".to_string(),
+            });
+            (StatusCode::PRECONDITION_FAILED, Json(error_response))
+        })
+}
+
+/// Explains why a conditional write touched no row: the todo is gone, or it
+/// changed since the client read it.
+async fn write_rejected(
+    data: &AppState,
+    user_id: uuid::Uuid,
+    id: uuid::Uuid,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let query = match data.db.acquire().await {
+        Ok(mut conn) => store::todo::find(&mut conn, user_id, id).await,
+        Err(err) => Err(err),
+    };
+    match query {
+        Ok(Some(todo)) => {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: format!(
+                    "ToDo with ID: {} was modified, current version is {}",
+                    id, todo.version
+                ),
+            });
+            (StatusCode::PRECONDITION_FAILED, Json(error_response))
+        }
+        _ => {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: format!("ToDo with ID: {} not found", id)
+            });
+            (StatusCode::NOT_FOUND, Json(error_response))
+        }
+    }
+}
+
+/// Why a user may not work with a todo.
+pub(crate) enum Denied {
+    /// The todo does not exist or is not shared with the user.
+    NotFound,
+    /// The todo is shared with the user, but not for this.
+    Forbidden,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for Denied {
+    fn from(err: sqlx::Error) -> Self {
+        Denied::Database(err)
+    }
+}
+
+impl Denied {
+    pub(crate) fn response(self, id: uuid::Uuid) -> (StatusCode, Json<serde_json::Value>) {
+        let (status, message) = match self {
+            Denied::NotFound => (
+                StatusCode::NOT_FOUND,
+                format!("ToDo with ID: {} not found", id),
+            ),
+            Denied::Forbidden => (
+                StatusCode::FORBIDDEN,
+                format!("You are not allowed to do that with ToDo with ID: {}", id),
+            ),
+            Denied::Database(err) => {
+                let error_response = serde_json::json!(GenericResponse {
+                    status: "error".to_string(),
+                    message: format!("{:?}", err),
+                });
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response));
+            }
+        };
 
-    if query.is_err() {
         let error_response = serde_json::json!(GenericResponse {
             status: "fail".to_string(),
-            message: format!("ToDo with ID: {} not found", id)
+            message,
+        });
+        (status, Json(error_response))
+    }
+}
+
+/// Checks that the user may work with the todo as `role`, which needs its
+/// owner or a share, and returns who acts on whose todos.
+pub(crate) async fn authorize<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: uuid::Uuid,
+    id: uuid::Uuid,
+    role: Role,
+) -> Result<Actor, Denied> {
+    match store::share::todo_access(executor, user_id, id).await? {
+        Some(access) if access.role >= role => Ok(Actor {
+            owner_id: access.owner_id,
+            user_id,
+        }),
+        Some(_) => Err(Denied::Forbidden),
+        None => Err(Denied::NotFound),
+    }
+}
+
+/// Whose todo a new one becomes: that of the owner of its parent or list
+/// when they are shared with the user as editor. Parents and lists the user
+/// does not know are left to the store to report.
+pub(crate) async fn creator<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: uuid::Uuid,
+    todo: &CreateToDo,
+) -> Result<Actor, Denied> {
+    let access = match (todo.parent_id, todo.list_id) {
+        (Some(parent_id), _) => store::share::todo_access(executor, user_id, parent_id).await?,
+        (None, Some(list_id)) => store::share::list_access(executor, user_id, list_id).await?,
+        (None, None) => None,
+    };
+
+    match access {
+        Some(access) if access.role < Role::Editor => Err(Denied::Forbidden),
+        Some(access) => Ok(Actor {
+            owner_id: access.owner_id,
+            user_id,
+        }),
+        None => Ok(Actor::owner(user_id)),
+    }
+}
+
+// ----------------------------------------------------------------- GET_TODO_HISTORY
+pub async fn get_todo_history_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let actor = authorize(&data.db, user.id, id, Role::Viewer)
+        .await
+        .map_err(|denied| denied.response(id))?;
+    let events = store::event::history(&data.db, actor.owner_id, id)
+        .await
+        .map_err(|err| {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "error".to_string(),
+                message: format!("{:?}", err),
+            });
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+        })?;
+
+    if events.is_empty() {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("No history for ToDo with ID: {}", id)
         });
         return Err((StatusCode::NOT_FOUND, Json(error_response)));
     }
 
-    let now = chrono::Utc::now();
-    let todo = query.unwrap();
+    let json_response = serde_json::json!(TodoEventListResponse {
+        status: "success".to_string(),
+        results: events.len(),
+        data: events,
+    });
 
-    let query = sqlx::query_as!(
-        ToDoModel,
-        "UPDATE todos SET title = $1, content = $2, complete = $3, updated_at = $4 WHERE id = $5 RETURNING *",
-        body.title.to_owned().unwrap_or(todo.title),
-        body.content.to_owned().unwrap_or(todo.content),
-        body.complete.unwrap_or(todo.complete.unwrap()),
-        now,
-        id
-    )
-    .fetch_one(&data.db)
-    .await;
+    Ok((StatusCode::OK, Json(json_response)))
+}
 
+// ----------------------------------------------------------------- REVERT_TODO
+pub async fn revert_todo_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(body): Json<RevertToDo>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let version = expected_version(&headers, body.version)?;
+    let actor = authorize(&data.db, user.id, id, Role::Editor)
+        .await
+        .map_err(|denied| denied.response(id))?;
+    let query = match data.db.acquire().await {
+        Ok(mut conn) => store::todo::revert(&mut conn, actor, id, version, body.revision).await,
+        Err(err) => Err(err.into()),
+    };
+
+    written(&data, actor.owner_id, id, query).await
+}
+
+// ----------------------------------------------------------------- MOVE_TODO
+pub async fn move_todo_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(body): Json<MoveToDo>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if body.after.is_none() && body.before.is_none() {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "Either after or before is required".to_string(),
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    let version = expected_version(&headers, body.version)?;
+    let actor = authorize(&data.db, user.id, id, Role::Editor)
+        .await
+        .map_err(|denied| denied.response(id))?;
+    let query = match data.db.acquire().await {
+        Ok(mut conn) => {
+            store::todo::reorder(&mut conn, actor, id, version, body.after, body.before).await
+        }
+        Err(err) => Err(err.into()),
+    };
+
+    written(&data, actor.owner_id, id, query).await
+}
+
+// ----------------------------------------------------------------- GET_OCCURRENCES
+pub async fn get_occurrences_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    options: Option<Query<OccurrenceOptions>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let Query(options) = options.unwrap_or_default();
+    let count = options.count.unwrap_or(5).clamp(1, recurrence::MAX_PREVIEW);
+    let actor = authorize(&data.db, user.id, id, Role::Viewer)
+        .await
+        .map_err(|denied| denied.response(id))?;
+
+    let query = match data.db.acquire().await {
+        Ok(mut conn) => store::todo::find(&mut conn, actor.owner_id, id).await,
+        Err(err) => Err(err),
+    };
+    let todo = match query {
+        Ok(Some(todo)) => todo,
+        _ => {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: format!("ToDo with ID: {} not found", id)
+            });
+            return Err((StatusCode::NOT_FOUND, Json(error_response)));
+        }
+    };
+
+    let (rrule, due_at) = match (&todo.rrule, todo.due_at) {
+        (Some(rrule), Some(due_at)) => (rrule, due_at),
+        _ => return Err(write_failed(id, WriteError::NotRecurring)),
+    };
+
+    // The open occurrence comes first.
+    let mut occurrences = vec![due_at];
+    occurrences.extend(
+        recurrence::upcoming(rrule, &todo.timezone, due_at, count - 1)
+            .map_err(invalid_recurrence)?,
+    );
+
+    let json_response = serde_json::json!(OccurrenceListResponse {
+        status: "success".to_string(),
+        results: occurrences.len(),
+        data: occurrences,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- SKIP_OCCURRENCE
+pub async fn skip_occurrence_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    headers: HeaderMap,
+    options: Option<Query<DeleteOptions>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let Query(options) = options.unwrap_or_default();
+    let version = expected_version(&headers, options.version)?;
+    let actor = authorize(&data.db, user.id, id, Role::Editor)
+        .await
+        .map_err(|denied| denied.response(id))?;
+    let query = match data.db.acquire().await {
+        Ok(mut conn) => store::todo::skip(&mut conn, actor, id, version).await,
+        Err(err) => Err(err.into()),
+    };
+
+    written(&data, actor.owner_id, id, query).await
+}
+
+// ----------------------------------------------------------------- END_SERIES
+pub async fn end_series_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    headers: HeaderMap,
+    options: Option<Query<DeleteOptions>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let Query(options) = options.unwrap_or_default();
+    let version = expected_version(&headers, options.version)?;
+    let actor = authorize(&data.db, user.id, id, Role::Editor)
+        .await
+        .map_err(|denied| denied.response(id))?;
+    let query = match data.db.acquire().await {
+        Ok(mut conn) => store::todo::end_series(&mut conn, actor, id, version).await,
+        Err(err) => Err(err.into()),
+    };
+
+    written(&data, actor.owner_id, id, query).await
+}
+
+/// Responds to a write made through one of the store's `WriteError`
+/// operations.
+async fn written(
+    data: &AppState,
+    user_id: uuid::Uuid,
+    id: uuid::Uuid,
+    query: Result<ToDoModel, WriteError>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
     match query {
         Ok(todo) => {
+            let tag = etag(&todo);
             let json_response = serde_json::json!(ToDoSingleResponse {
                 status: "success".to_string(),
                 data: todo
             });
 
-            return Ok((StatusCode::OK, Json(json_response)));
-        }
-        Err(err) => {
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!(GenericResponse {
-                    status: "error".to_string(),
-                    message: format!("{:?}", err)
-                })),
-            ));
+            Ok((StatusCode::OK, [(header::ETAG, tag)], Json(json_response)))
         }
+        Err(WriteError::Rejected) => Err(write_rejected(data, user_id, id).await),
+        Err(err) => Err(write_failed(id, err)),
     }
 }
 
-// ----------------------------------------------------------------- DELETE_TODO
-pub async fn delete_todo_handler(
+fn write_failed(id: uuid::Uuid, err: WriteError) -> (StatusCode, Json<serde_json::Value>) {
+    let (failure, message) = err.classify(id);
+    let (status_code, status) = match failure {
+        Failure::NotFound => (StatusCode::NOT_FOUND, "fail"),
+        Failure::Conflict => (StatusCode::CONFLICT, "fail"),
+        Failure::Invalid => (StatusCode::UNPROCESSABLE_ENTITY, "fail"),
+        Failure::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "error"),
+    };
+
+    let error_response = serde_json::json!(GenericResponse {
+        status: status.to_string(),
+        message,
+    });
+    (status_code, Json(error_response))
+}
+
+fn missing_due_date() -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "fail".to_string(),
+        message: "A repeating ToDo needs a due date".to_string(),
+    });
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(error_response))
+}
+
+fn unknown_reference(err: &sqlx::Error) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "fail".to_string(),
+        message: store::todo::missing_reference(err).to_string(),
+    });
+    (StatusCode::UNPROCESSABLE_ENTITY, Json(error_response))
+}
+
+fn invalid_recurrence(err: RecurrenceError) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "fail".to_string(),
+        message: err.to_string(),
+    });
+    (StatusCode::BAD_REQUEST, Json(error_response))
+}
+
+// ----------------------------------------------------------------- GET_TRASH
+pub async fn get_trash_handler(
+    options: Option<Query<FilterOptions>>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let Query(options) = options.unwrap_or_default();
+
+    let limit = options.limit.unwrap_or(10);
+    let offset = (options.page.unwrap_or(1) - 1) * limit;
+
+    let todos = match data.db.acquire().await {
+        Ok(mut conn) => store::todo::list_trash(&mut conn, user.id, limit, offset).await,
+        Err(err) => Err(err),
+    }
+    .map_err(|err| {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "error".to_string(),
+            message: format!("{:?}", err),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+    })?;
+
+    let json_response = serde_json::json!(ToDoListResponse {
+        status: "success".to_string(),
+        results: todos.len(),
+        data: todos,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- RESTORE_TODO
+pub async fn restore_todo_handler(
     Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
     State(data): State<Arc<AppState>>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let query = sqlx::query!("DELETE FROM todos WHERE id = $1", id)
-        .execute(&data.db)
+    let actor = authorize(&data.db, user.id, id, Role::Editor)
         .await
-        .unwrap()
-        .rows_affected();
-
-    if query == 0 {
+        .map_err(|denied| denied.response(id))?;
+    let todo = match data.db.acquire().await {
+        Ok(mut conn) => store::todo::restore(&mut conn, actor, id).await,
+        Err(err) => Err(err),
+    }
+    .map_err(|err| write_failed(id, WriteError::Database(err)))?
+    .ok_or_else(|| {
         let error_response = serde_json::json!(GenericResponse {
             status: "fail".to_string(),
-            message: format!("ToDo with ID: {} not found", id)
+            message: format!("ToDo with ID: {} is not in the trash", id)
         });
+        (StatusCode::NOT_FOUND, Json(error_response))
+    })?;
+
+    let tag = etag(&todo);
+    let json_response = serde_json::json!(ToDoSingleResponse {
+        status: "success".to_string(),
+        data: todo
+    });
+
+    Ok((StatusCode::OK, [(header::ETAG, tag)], Json(json_response)))
+}
+
+// ----------------------------------------------------------------- PURGE_TODO
+pub async fn purge_todo_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let actor = authorize(&data.db, user.id, id, Role::Owner)
+        .await
+        .map_err(|denied| denied.response(id))?;
+    let purged = store::todo::purge(&data.db, actor.owner_id, id)
+        .await
+        .map_err(|err| {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "error".to_string(),
+                message: format!("{:?}", err),
+            });
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+        })?;
 
+    if !purged {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("ToDo with ID: {} is not in the trash", id)
+        });
         return Err((StatusCode::NOT_FOUND, Json(error_response)));
     }
 
-    return Ok((StatusCode::NO_CONTENT, Json({})));
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ----------------------------------------------------------------- BATCH_TODOS
+const MAX_BATCH_OPERATIONS: usize = 100;
+
+pub async fn batch_todos_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<BatchRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if body.operations.is_empty() || body.operations.len() > MAX_BATCH_OPERATIONS {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!(
+                "A batch must contain between 1 and {} operations",
+                MAX_BATCH_OPERATIONS
+            ),
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    let database_error = |err: sqlx::Error| {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "error".to_string(),
+            message: format!("{:?}", err),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+    };
+
+    let atomic = body.mode == BatchMode::Atomic;
+    let mut tx = data.db.begin().await.map_err(database_error)?;
+    let mut results = Vec::with_capacity(body.operations.len());
+    let mut failed = false;
+
+    for (index, operation) in body.operations.iter().enumerate() {
+        if failed && atomic {
+            results.push(BatchResult {
+                index,
+                status: StatusCode::FAILED_DEPENDENCY.as_u16(),
+                data: None,
+                message: Some("Not applied because another operation failed".to_string()),
+            });
+            continue;
+        }
+
+        // Each operation runs in a savepoint so a failure does not abort the
+        // surrounding transaction.
+        let mut savepoint = tx.begin().await.map_err(database_error)?;
+        let result = apply_batch_operation(&mut savepoint, user.id, index, operation).await;
+        if result.status < 400 {
+            savepoint.commit().await.map_err(database_error)?;
+        } else {
+            savepoint.rollback().await.map_err(database_error)?;
+            failed = true;
+        }
+        results.push(result);
+    }
+
+    if failed && atomic {
+        tx.rollback().await.map_err(database_error)?;
+        for result in results.iter_mut().filter(|result| result.status < 400) {
+            result.status = StatusCode::FAILED_DEPENDENCY.as_u16();
+            result.data = None;
+            result.message = Some("Rolled back because another operation failed".to_string());
+        }
+
+        let json_response = serde_json::json!(BatchResponse {
+            status: "fail".to_string(),
+            results: results.len(),
+            data: results,
+        });
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(json_response)));
+    }
+
+    tx.commit().await.map_err(database_error)?;
+
+    let json_response = serde_json::json!(BatchResponse {
+        status: "success".to_string(),
+        results: results.len(),
+        data: results,
+    });
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+async fn apply_batch_operation(
+    conn: &mut PgConnection,
+    user_id: uuid::Uuid,
+    index: usize,
+    operation: &BatchOperation,
+) -> BatchResult {
+    let invalid = match operation {
+        BatchOperation::Create(todo) => {
+            recurrence::validate(todo.rrule.as_deref(), todo.timezone.as_deref()).err()
+        }
+        BatchOperation::Update { changes, .. } => recurrence::validate(
+            changes.rrule.clone().flatten().as_deref(),
+            changes.timezone.as_deref(),
+        )
+        .err(),
+        BatchOperation::Delete { .. } => None,
+    };
+    if let Some(err) = invalid {
+        return BatchResult {
+            index,
+            status: StatusCode::BAD_REQUEST.as_u16(),
+            data: None,
+            message: Some(err.to_string()),
+        };
+    }
+
+    let actor = match operation {
+        BatchOperation::Create(todo) => creator(&mut *conn, user_id, todo).await,
+        BatchOperation::Update { id, .. } | BatchOperation::Delete { id, .. } => {
+            authorize(&mut *conn, user_id, *id, Role::Editor).await
+        }
+    };
+    let actor = match actor {
+        Ok(actor) => actor,
+        Err(Denied::Database(err)) => {
+            return BatchResult {
+                index,
+                status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                data: None,
+                message: Some(format!("{:?}", err)),
+            }
+        }
+        Err(denied) => {
+            let (status, message) = match denied {
+                Denied::NotFound => (StatusCode::NOT_FOUND, "ToDo not found"),
+                _ => (StatusCode::FORBIDDEN, "Not allowed for this ToDo"),
+            };
+            return BatchResult {
+                index,
+                status: status.as_u16(),
+                data: None,
+                message: Some(message.to_string()),
+            };
+        }
+    };
+
+    let result = match operation {
+        BatchOperation::Create(todo) => store::todo::create(&mut *conn, actor, todo)
+            .await
+            .map(|todo| (StatusCode::CREATED, Some(todo))),
+        BatchOperation::Update {
+            id,
+            cascade,
+            changes,
+        } => match changes.version {
+            Some(version) => {
+                let cascade = cascade.unwrap_or(false);
+                match store::todo::update(&mut *conn, actor, *id, Some(version), changes, cascade)
+                    .await
+                {
+                    Ok(todo) => Ok((StatusCode::OK, Some(todo))),
+                    Err(WriteError::Rejected) => {
+                        missing_or_modified(&mut *conn, actor.owner_id, *id).await
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            None => Ok((StatusCode::PRECONDITION_REQUIRED, None)),
+        },
+        BatchOperation::Delete {
+            id,
+            version,
+            cascade,
+        } => match version {
+            Some(version) => {
+                let cascade = cascade.unwrap_or(true);
+                match store::todo::delete(&mut *conn, actor, *id, Some(*version), cascade).await {
+                    Ok(true) => Ok((StatusCode::NO_CONTENT, None)),
+                    Ok(false) => missing_or_modified(&mut *conn, actor.owner_id, *id).await,
+                    Err(err) => Err(err.into()),
+                }
+            }
+            None => Ok((StatusCode::PRECONDITION_REQUIRED, None)),
+        },
+    };
+
+    match result {
+        Ok((status, data)) => BatchResult {
+            index,
+            status: status.as_u16(),
+            data,
+            message: match status {
+                StatusCode::NOT_FOUND => Some("ToDo not found"),
+                StatusCode::PRECONDITION_FAILED => Some("ToDo was modified since it was read"),
+                StatusCode::PRECONDITION_REQUIRED => {
+                    Some("Updates and deletes require the current version")
+                }
+                _ => None,
+            }
+            .map(str::to_string),
+        },
+        Err(WriteError::Database(err)) if store::is_unique_violation(&err) => BatchResult {
+            index,
+            status: StatusCode::CONFLICT.as_u16(),
+            data: None,
+            message: Some("ToDo with that title already exists".to_string()),
+        },
+        Err(WriteError::Database(err)) if store::is_check_violation(&err) => BatchResult {
+            index,
+            status: StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+            data: None,
+            message: Some("A repeating ToDo needs a due date".to_string()),
+        },
+        Err(WriteError::Database(err)) if store::is_foreign_key_violation(&err) => BatchResult {
+            index,
+            status: StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+            data: None,
+            message: Some(store::todo::missing_reference(&err).to_string()),
+        },
+        Err(WriteError::Database(err)) => BatchResult {
+            index,
+            status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            data: None,
+            message: Some(format!("{:?}", err)),
+        },
+        Err(err) => BatchResult {
+            index,
+            status: StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+            data: None,
+            message: Some(err.to_string()),
+        },
+    }
+}
+
+async fn missing_or_modified(
+    conn: &mut PgConnection,
+    user_id: uuid::Uuid,
+    id: uuid::Uuid,
+) -> Result<(StatusCode, Option<ToDoModel>), WriteError> {
+    Ok(match store::todo::find(conn, user_id, id).await? {
+        Some(_) => (StatusCode::PRECONDITION_FAILED, None),
+        None => (StatusCode::NOT_FOUND, None),
+    })
 }
@@ -0,0 +1,143 @@
+use crate::{
+    auth::{generate_token, hash_token, SCOPES},
+    model::{PersonalTokenModel, UserModel},
+    schema::{CreateToken, GenericResponse, TokenCreatedResponse, TokenListResponse},
+    AppState,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use std::sync::Arc;
+
+// ----------------------------------------------------------------- CREATE_TOKEN
+pub async fn create_token_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<CreateToken>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if body.name.trim().is_empty() {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "Token name must not be empty".to_string(),
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    if body.scopes.is_empty() {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("At least one scope is required: {}", SCOPES.join(", ")),
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    if let Some(scope) = body.scopes.iter().find(|s| !SCOPES.contains(&s.as_str())) {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Unknown scope: {}", scope),
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    if body.expires_at.is_some_and(|at| at <= chrono::Utc::now()) {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "Token expiry must be in the future".to_string(),
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+
+    let token = generate_token();
+
+    let query = sqlx::query_as!(
+        PersonalTokenModel,
+        "INSERT INTO personal_tokens (user_id,name,token_hash,scopes,expires_at) VALUES ($1, $2, $3, $4, $5) RETURNING id, user_id, name, scopes, expires_at, last_used_at, created_at",
+        user.id,
+        body.name.trim().to_string(),
+        hash_token(&token),
+        &body.scopes,
+        body.expires_at,
+    )
+    .fetch_one(&data.db)
+    .await
+    .map_err(|e| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Database error: {}", e),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+    })?;
+
+    let json_response = serde_json::json!(TokenCreatedResponse {
+        status: "success".to_string(),
+        token,
+        data: query,
+    });
+
+    Ok((StatusCode::CREATED, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- GET_TOKENS
+pub async fn get_tokens_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let tokens = sqlx::query_as!(
+        PersonalTokenModel,
+        "SELECT id, user_id, name, scopes, expires_at, last_used_at, created_at FROM personal_tokens WHERE user_id = $1 ORDER BY created_at DESC",
+        user.id
+    )
+    .fetch_all(&data.db)
+    .await
+    .map_err(|e| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Database error: {}", e),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+    })?;
+
+    let json_response = serde_json::json!(TokenListResponse {
+        status: "success".to_string(),
+        results: tokens.len(),
+        data: tokens,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- DELETE_TOKEN
+pub async fn delete_token_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let query = sqlx::query!(
+        "DELETE FROM personal_tokens WHERE id = $1 AND user_id = $2",
+        id,
+        user.id
+    )
+    .execute(&data.db)
+    .await
+    .map_err(|e| {
+        let response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Database error: {}", e),
+        });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(response))
+    })?
+    .rows_affected();
+
+    if query == 0 {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: format!("Token with ID: {} not found", id)
+        });
+        return Err((StatusCode::NOT_FOUND, Json(error_response)));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
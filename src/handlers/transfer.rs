@@ -0,0 +1,354 @@
+use crate::{
+    model::{Priority, UserModel},
+    recurrence,
+    schema::{
+        CreateToDo, DuplicateStrategy, ExportOptions, GenericResponse, ImportAction, ImportOptions,
+        ImportResponse, ImportResult, UpdateToDo,
+    },
+    store::{
+        self,
+        todo::{Actor, WriteError},
+    },
+    transfer::{self, Encoder, Format, TodoRecord},
+    AppState,
+};
+use axum::{
+    body::{Bytes, StreamBody},
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use futures::{stream, StreamExt};
+use sqlx::{Acquire, PgConnection};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+use uuid::Uuid;
+
+// ----------------------------------------------------------------- EXPORT_TODOS
+/// How many todos are read from the database per chunk of the export.
+const EXPORT_BATCH: i64 = 200;
+
+pub async fn export_todos_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Query(opts): Query<ExportOptions>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let format = opts.format.unwrap_or(Format::Json);
+
+    // Read page by page after the last todo sent, so the export never holds
+    // more than a page in memory nor a connection while the client reads.
+    let encoder = Encoder::new(format);
+    let header = stream::once(futures::future::ready(Ok(encoder.header())));
+    let start = (Vec::new(), Uuid::nil());
+    let rows = stream::unfold(Some((start, encoder)), move |state| {
+        let data = data.clone();
+        async move {
+            let ((path, id), mut encoder) = state?;
+            let page = store::todo::export_page(&data.db, user.id, (&path, id), EXPORT_BATCH).await;
+            match page {
+                Ok(rows) if rows.is_empty() => Some((Ok(encoder.footer()), None)),
+                Ok(mut rows) => {
+                    let last = rows
+                        .last_mut()
+                        .map(|row| (std::mem::take(&mut row.path), row.id));
+                    let chunk = encoder.rows(rows);
+                    Some((Ok(chunk), last.map(|last| (last, encoder))))
+                }
+                Err(err) => {
+                    println!("🔥 Failed to export todos: {:?}", err);
+                    Some((Err(err), None))
+                }
+            }
+        }
+    });
+
+    let headers = [
+        (header::CONTENT_TYPE, format.content_type().to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"todos.{}\"", format.extension()),
+        ),
+    ];
+    Ok((headers, StreamBody::new(header.chain(rows))))
+}
+
+// ----------------------------------------------------------------- IMPORT_TODOS
+const MAX_IMPORT_ROWS: usize = 10_000;
+pub const MAX_IMPORT_BYTES: usize = 10 * 1024 * 1024;
+
+pub async fn import_todos_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Query(opts): Query<ImportOptions>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let format = opts.format.or_else(|| {
+        headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Format::from_content_type)
+    });
+    let Some(format) = format else {
+        return Err(invalid_import(
+            "Give the format as json, csv or md, or send a matching Content-Type".to_string(),
+        ));
+    };
+
+    let parsed = transfer::parse(format, &body).map_err(invalid_import)?;
+    if parsed.len() > MAX_IMPORT_ROWS {
+        return Err(invalid_import(format!(
+            "An import can contain at most {} ToDos",
+            MAX_IMPORT_ROWS
+        )));
+    }
+
+    let mut results = Vec::with_capacity(parsed.len());
+    let mut records = Vec::with_capacity(parsed.len());
+    for (row, record) in parsed {
+        match record {
+            Ok(record) => records.push((row, record)),
+            Err(message) => results.push(failed(row, None, message)),
+        }
+    }
+
+    let dry_run = opts.dry_run.unwrap_or(false);
+    let mut tx = data.db.begin().await.map_err(database_error)?;
+    // Todos already imported by their title in the file, to find parents by.
+    let mut imported = HashMap::new();
+
+    for index in parents_first(&records) {
+        let (row, record) = &records[index];
+
+        // Each row runs in a savepoint so a failure does not abort the
+        // surrounding transaction.
+        let mut savepoint = tx.begin().await.map_err(database_error)?;
+        match import_row(
+            &mut savepoint,
+            user.id,
+            record,
+            opts.on_duplicate,
+            &mut imported,
+        )
+        .await
+        {
+            Ok((action, id, message)) => {
+                savepoint.commit().await.map_err(database_error)?;
+                results.push(ImportResult {
+                    row: *row,
+                    title: Some(record.title.clone()),
+                    action,
+                    id: id.filter(|_| !dry_run),
+                    message,
+                });
+            }
+            Err(message) => {
+                savepoint.rollback().await.map_err(database_error)?;
+                results.push(failed(*row, Some(record.title.clone()), message));
+            }
+        }
+    }
+
+    if dry_run {
+        tx.rollback().await.map_err(database_error)?;
+    } else {
+        tx.commit().await.map_err(database_error)?;
+    }
+
+    results.sort_by_key(|result| result.row);
+    let count = |action| {
+        results
+            .iter()
+            .filter(|result| result.action == action)
+            .count()
+    };
+    let json_response = serde_json::json!(ImportResponse {
+        status: "success".to_string(),
+        dry_run,
+        created: count(ImportAction::Created),
+        renamed: count(ImportAction::Renamed),
+        overwritten: count(ImportAction::Overwritten),
+        skipped: count(ImportAction::Skipped),
+        failed: count(ImportAction::Failed),
+        data: results,
+    });
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+/// The order to import the records in, each parent in the file before its
+/// subtasks and otherwise as in the file.
+fn parents_first(records: &[(usize, TodoRecord)]) -> Vec<usize> {
+    let mut by_title = HashMap::new();
+    for (index, (_, record)) in records.iter().enumerate() {
+        by_title.entry(record.title.as_str()).or_insert(index);
+    }
+
+    let mut order = Vec::with_capacity(records.len());
+    let mut seen = HashSet::new();
+    for start in 0..records.len() {
+        // Walk up to the topmost ancestor not yet ordered, stopping at
+        // cycles, then order the chain from the top.
+        let mut chain = Vec::new();
+        let mut next = Some(start);
+        while let Some(index) = next.filter(|index| seen.insert(*index)) {
+            chain.push(index);
+            next = records[index]
+                .1
+                .parent
+                .as_deref()
+                .and_then(|parent| by_title.get(parent).copied());
+        }
+        order.extend(chain.into_iter().rev());
+    }
+    order
+}
+
+/// Imports one record and returns what was done, the todo's id and a note.
+async fn import_row(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    record: &TodoRecord,
+    on_duplicate: DuplicateStrategy,
+    imported: &mut HashMap<String, Uuid>,
+) -> Result<(ImportAction, Option<Uuid>, Option<String>), String> {
+    if record.title.trim().is_empty() {
+        return Err("A ToDo needs a title".to_string());
+    }
+    recurrence::validate(record.rrule.as_deref(), record.timezone.as_deref())
+        .map_err(|err| err.to_string())?;
+
+    let existing = store::todo::find_by_title(&mut *conn, user_id, &record.title)
+        .await
+        .map_err(|err| format!("{:?}", err))?;
+    let (action, title) = match (existing, on_duplicate) {
+        (None, _) => (ImportAction::Created, record.title.clone()),
+        (Some(id), DuplicateStrategy::Skip) => {
+            imported.insert(record.title.clone(), id);
+            let message = "ToDo with that title already exists".to_string();
+            return Ok((ImportAction::Skipped, Some(id), Some(message)));
+        }
+        (Some(_), DuplicateStrategy::Rename) => {
+            let title = store::todo::free_title(&mut *conn, user_id, &record.title)
+                .await
+                .map_err(|err| format!("{:?}", err))?;
+            (ImportAction::Renamed, title)
+        }
+        (Some(_), DuplicateStrategy::Overwrite) => {
+            (ImportAction::Overwritten, record.title.clone())
+        }
+    };
+
+    let parent_id = match &record.parent {
+        Some(parent) => Some(find_parent(&mut *conn, user_id, parent, imported).await?),
+        None => None,
+    };
+    let list_id = match &record.list {
+        Some(list) => Some(
+            store::list::ensure(&mut *conn, user_id, list)
+                .await
+                .map_err(|err| format!("{:?}", err))?,
+        ),
+        None => None,
+    };
+    let mut tags = Vec::with_capacity(record.tags.len());
+    for tag in &record.tags {
+        let tag_id = store::tag::ensure(&mut *conn, user_id, tag)
+            .await
+            .map_err(|err| format!("{:?}", err))?;
+        tags.push(tag_id);
+    }
+
+    let actor = Actor::owner(user_id);
+    let written = match (action, existing) {
+        (ImportAction::Overwritten, Some(id)) => {
+            let changes = UpdateToDo {
+                content: Some(record.content.clone()),
+                complete: Some(record.complete),
+                due_at: Some(record.due_at),
+                priority: Some(record.priority.unwrap_or(Priority::Normal)),
+                rrule: Some(record.rrule.clone()),
+                timezone: Some(record.timezone.clone().unwrap_or("UTC".to_string())),
+                list_id: Some(list_id),
+                tags: Some(tags),
+                parent_id: Some(parent_id),
+                ..Default::default()
+            };
+            store::todo::update(&mut *conn, actor, id, None, &changes, false).await
+        }
+        _ => {
+            let todo = CreateToDo {
+                title: title.clone(),
+                content: record.content.clone(),
+                complete: Some(record.complete),
+                due_at: record.due_at,
+                priority: record.priority,
+                rrule: record.rrule.clone(),
+                timezone: record.timezone.clone(),
+                list_id,
+                tags: Some(tags),
+                parent_id,
+            };
+            store::todo::create(&mut *conn, actor, &todo).await
+        }
+    };
+
+    let todo = written.map_err(|err| match err {
+        WriteError::Database(err) if store::is_unique_violation(&err) => {
+            "ToDo with that title already exists".to_string()
+        }
+        WriteError::Database(err) if store::is_check_violation(&err) => {
+            "A repeating ToDo needs a due date".to_string()
+        }
+        err => err.to_string(),
+    })?;
+    imported.insert(record.title.clone(), todo.id);
+
+    let message = (action == ImportAction::Renamed).then(|| format!("Imported as {}", title));
+    Ok((action, Some(todo.id), message))
+}
+
+/// A parent imported earlier or otherwise one of the user's todos.
+async fn find_parent(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    title: &str,
+    imported: &HashMap<String, Uuid>,
+) -> Result<Uuid, String> {
+    if let Some(id) = imported.get(title) {
+        return Ok(*id);
+    }
+
+    store::todo::find_by_title(conn, user_id, title)
+        .await
+        .map_err(|err| format!("{:?}", err))?
+        .ok_or(format!("Parent ToDo not found: {}", title))
+}
+
+fn failed(row: usize, title: Option<String>, message: String) -> ImportResult {
+    ImportResult {
+        row,
+        title,
+        action: ImportAction::Failed,
+        id: None,
+        message: Some(message),
+    }
+}
+
+fn invalid_import(message: String) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "fail".to_string(),
+        message,
+    });
+    (StatusCode::BAD_REQUEST, Json(error_response))
+}
+
+fn database_error(err: sqlx::Error) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "error".to_string(),
+        message: format!("{:?}", err),
+    });
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+}
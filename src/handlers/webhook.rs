@@ -0,0 +1,277 @@
+use crate::{
+    auth::{random_token, ADMIN_ROLE},
+    model::{UserModel, WebhookModel},
+    schema::{
+        CreateWebhook, GenericResponse, UpdateWebhook, WebhookCreatedResponse,
+        WebhookDeliveryListResponse, WebhookDeliverySingleResponse, WebhookListResponse,
+        WebhookSingleResponse,
+    },
+    store::{self, webhook::EVENTS},
+    webhook, AppState,
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use std::sync::Arc;
+
+/// How many deliveries the log shows.
+const DELIVERY_LOG_LIMIT: i64 = 100;
+
+fn is_admin(user: &UserModel) -> bool {
+    user.role == ADMIN_ROLE
+}
+
+// ----------------------------------------------------------------- GET_WEBHOOKS
+pub async fn get_webhooks_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let webhooks = store::webhook::list(&data.db, user.id, is_admin(&user))
+        .await
+        .map_err(webhook_failed)?;
+
+    let json_response = serde_json::json!(WebhookListResponse {
+        status: "success".to_string(),
+        results: webhooks.len(),
+        data: webhooks,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- CREATE_WEBHOOK
+pub async fn create_webhook_handler(
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<CreateWebhook>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    if body.global && !is_admin(&user) {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message: "Only admins can register global webhooks".to_string(),
+        });
+        return Err((StatusCode::FORBIDDEN, Json(error_response)));
+    }
+    check_url(&data, &body.url).await?;
+    check_events(&body.events, body.global)?;
+
+    let secret = random_token();
+    let owner = (!body.global).then_some(user.id);
+    let webhook = store::webhook::create(&data.db, owner, &body.url, &secret, &body.events)
+        .await
+        .map_err(webhook_failed)?;
+
+    let json_response = serde_json::json!(WebhookCreatedResponse {
+        status: "success".to_string(),
+        secret,
+        data: webhook,
+    });
+
+    Ok((StatusCode::CREATED, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- GET_WEBHOOK
+pub async fn get_webhook_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let webhook = find_webhook(&data, &user, id).await?;
+
+    let json_response = serde_json::json!(WebhookSingleResponse {
+        status: "success".to_string(),
+        data: webhook,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- UPDATE_WEBHOOK
+pub async fn update_webhook_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+    Json(body): Json<UpdateWebhook>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let webhook = find_webhook(&data, &user, id).await?;
+    if let Some(url) = &body.url {
+        check_url(&data, url).await?;
+    }
+    if let Some(events) = &body.events {
+        check_events(events, webhook.user_id.is_none())?;
+    }
+
+    let webhook = store::webhook::update(
+        &data.db,
+        id,
+        body.url.as_deref(),
+        body.events.as_deref(),
+        body.active,
+    )
+    .await
+    .map_err(webhook_failed)?;
+
+    let json_response = serde_json::json!(WebhookSingleResponse {
+        status: "success".to_string(),
+        data: webhook,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- DELETE_WEBHOOK
+pub async fn delete_webhook_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    find_webhook(&data, &user, id).await?;
+    store::webhook::delete(&data.db, id)
+        .await
+        .map_err(webhook_failed)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// ----------------------------------------------------------------- PING_WEBHOOK
+/// Queues a `ping` event, to try out an endpoint.
+pub async fn ping_webhook_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    find_webhook(&data, &user, id).await?;
+
+    let payload = webhook::payload("ping", serde_json::json!({ "webhookId": id }));
+    let delivery = store::webhook::enqueue_for(&data.db, id, "ping", &payload)
+        .await
+        .map_err(webhook_failed)?;
+
+    let json_response = serde_json::json!(WebhookDeliverySingleResponse {
+        status: "success".to_string(),
+        data: delivery,
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- GET_DELIVERIES
+pub async fn get_deliveries_handler(
+    Path(id): Path<uuid::Uuid>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    find_webhook(&data, &user, id).await?;
+
+    let deliveries = store::webhook::deliveries(&data.db, id, DELIVERY_LOG_LIMIT)
+        .await
+        .map_err(webhook_failed)?;
+
+    let json_response = serde_json::json!(WebhookDeliveryListResponse {
+        status: "success".to_string(),
+        results: deliveries.len(),
+        data: deliveries,
+    });
+
+    Ok((StatusCode::OK, Json(json_response)))
+}
+
+// ----------------------------------------------------------------- REDELIVER
+/// Sends the event of a past delivery once more, as a new delivery.
+pub async fn redeliver_handler(
+    Path((id, delivery_id)): Path<(uuid::Uuid, uuid::Uuid)>,
+    Extension(user): Extension<UserModel>,
+    State(data): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    find_webhook(&data, &user, id).await?;
+
+    let delivery = store::webhook::redeliver(&data.db, id, delivery_id)
+        .await
+        .map_err(webhook_failed)?
+        .ok_or_else(|| {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: format!("Delivery with ID: {} not found", delivery_id),
+            });
+            (StatusCode::NOT_FOUND, Json(error_response))
+        })?;
+
+    let json_response = serde_json::json!(WebhookDeliverySingleResponse {
+        status: "success".to_string(),
+        data: delivery,
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(json_response)))
+}
+
+async fn find_webhook(
+    data: &AppState,
+    user: &UserModel,
+    id: uuid::Uuid,
+) -> Result<WebhookModel, (StatusCode, Json<serde_json::Value>)> {
+    store::webhook::find(&data.db, user.id, is_admin(user), id)
+        .await
+        .map_err(webhook_failed)?
+        .ok_or_else(|| {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message: format!("Webhook with ID: {} not found", id),
+            });
+            (StatusCode::NOT_FOUND, Json(error_response))
+        })
+}
+
+async fn check_url(
+    data: &AppState,
+    url: &str,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    webhook::resolve(url, &data.env.webhook_allowed_hosts)
+        .await
+        .map(|_| ())
+        .map_err(|message| {
+            let error_response = serde_json::json!(GenericResponse {
+                status: "fail".to_string(),
+                message,
+            });
+            (StatusCode::BAD_REQUEST, Json(error_response))
+        })
+}
+
+/// Users only hear about their own todos, signups go to global webhooks.
+fn check_events(
+    events: &[String],
+    global: bool,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    let message = if events.is_empty() {
+        Some(format!(
+            "At least one event is required: {}",
+            EVENTS.join(", ")
+        ))
+    } else if let Some(event) = events.iter().find(|e| !EVENTS.contains(&e.as_str())) {
+        Some(format!("Unknown event: {}", event))
+    } else if !global && events.iter().any(|e| e == "user.signed_up") {
+        Some("Only global webhooks can subscribe to user.signed_up".to_string())
+    } else {
+        None
+    };
+
+    if let Some(message) = message {
+        let error_response = serde_json::json!(GenericResponse {
+            status: "fail".to_string(),
+            message,
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(error_response)));
+    }
+    Ok(())
+}
+
+fn webhook_failed(err: sqlx::Error) -> (StatusCode, Json<serde_json::Value>) {
+    let error_response = serde_json::json!(GenericResponse {
+        status: "error".to_string(),
+        message: format!("{:?}", err),
+    });
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+}
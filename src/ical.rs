@@ -0,0 +1,467 @@
+//! Todos as RFC 5545 VTODO components, for calendar apps.
+//!
+//! Times are written in UTC. A repeating todo starts on its due date, so its
+//! RRULE expands from there. RELATED-TO names the parent by its UID.
+
+use crate::model::{Priority, ToDoModel};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::fmt;
+
+pub const CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+const PRODID: &str = "-//Todo API//Todos//EN";
+
+/// Content lines longer than this many octets are folded.
+const LINE_LIMIT: usize = 75;
+
+/// A VCALENDAR holding a VTODO for each todo.
+pub fn calendar(name: &str, todos: &[ToDoModel]) -> String {
+    let mut out = String::new();
+    line(&mut out, "BEGIN", "VCALENDAR");
+    line(&mut out, "VERSION", "2.0");
+    line(&mut out, "PRODID", PRODID);
+    line(&mut out, "CALSCALE", "GREGORIAN");
+    line(&mut out, "X-WR-CALNAME", &text(name));
+    for todo in todos {
+        let parent_uid = todo.parent_id.map(|parent_id| parent_id.to_string());
+        vtodo(&mut out, todo, &todo.id.to_string(), parent_uid.as_deref());
+    }
+    line(&mut out, "END", "VCALENDAR");
+    out
+}
+
+/// A VCALENDAR holding just the todo, as a CalDAV object with that UID
+/// whose parent has `parent_uid`.
+pub fn object(todo: &ToDoModel, uid: &str, parent_uid: Option<&str>) -> String {
+    let mut out = String::new();
+    line(&mut out, "BEGIN", "VCALENDAR");
+    line(&mut out, "VERSION", "2.0");
+    line(&mut out, "PRODID", PRODID);
+    vtodo(&mut out, todo, uid, parent_uid);
+    line(&mut out, "END", "VCALENDAR");
+    out
+}
+
+fn vtodo(out: &mut String, todo: &ToDoModel, uid: &str, parent_uid: Option<&str>) {
+    let complete = todo.complete == Some(true);
+    let modified = todo.updated_at.or(todo.created_at).unwrap_or_else(Utc::now);
+
+    line(out, "BEGIN", "VTODO");
+    line(out, "UID", uid);
+    line(out, "DTSTAMP", &time(modified));
+    if let Some(created_at) = todo.created_at {
+        line(out, "CREATED", &time(created_at));
+    }
+    line(out, "LAST-MODIFIED", &time(modified));
+    line(out, "SEQUENCE", &todo.version.to_string());
+    line(out, "SUMMARY", &text(&todo.title));
+    if !todo.content.is_empty() {
+        line(out, "DESCRIPTION", &text(&todo.content));
+    }
+    line(
+        out,
+        "STATUS",
+        if complete {
+            "COMPLETED"
+        } else {
+            "NEEDS-ACTION"
+        },
+    );
+    if complete {
+        if let Some(completed_at) = todo.completed_at {
+            line(out, "COMPLETED", &time(completed_at));
+        }
+        line(out, "PERCENT-COMPLETE", "100");
+    }
+    line(out, "PRIORITY", priority(todo.priority));
+    if let Some(due_at) = todo.due_at {
+        if todo.rrule.is_some() {
+            line(out, "DTSTART", &time(due_at));
+        }
+        line(out, "DUE", &time(due_at));
+    }
+    if let Some(rrule) = &todo.rrule {
+        line(out, "RRULE", rrule);
+    }
+    if !todo.tags.is_empty() {
+        let categories: Vec<String> = todo.tags.iter().map(|tag| text(&tag.name)).collect();
+        line(out, "CATEGORIES", &categories.join(","));
+    }
+    if let Some(parent_uid) = parent_uid {
+        line(out, "RELATED-TO", parent_uid);
+    }
+    line(out, "END", "VTODO");
+}
+
+/// 1 is the highest and 9 the lowest priority, 5 is medium.
+fn priority(priority: Priority) -> &'static str {
+    match priority {
+        Priority::Urgent => "1",
+        Priority::High => "3",
+        Priority::Normal => "5",
+        Priority::Low => "9",
+    }
+}
+
+fn time(at: DateTime<Utc>) -> String {
+    at.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Escapes a TEXT value.
+fn text(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a content line, folded so no line exceeds `LINE_LIMIT` octets
+/// without splitting a character.
+fn line(out: &mut String, name: &str, value: &str) {
+    let content = format!("{}:{}", name, value);
+    let mut width = 0;
+    for c in content.chars() {
+        if width + c.len_utf8() > LINE_LIMIT {
+            out.push_str("\r\n ");
+            // The leading space counts towards the continuation line.
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// A VTODO as a calendar client sent it.
+#[derive(Debug, Default)]
+pub struct VTodo {
+    pub uid: String,
+    pub summary: String,
+    pub description: String,
+    pub complete: bool,
+    pub due_at: Option<DateTime<Utc>>,
+    /// Zone the due date was given in, if it is an IANA zone.
+    pub timezone: Option<String>,
+    pub priority: Option<Priority>,
+    pub rrule: Option<String>,
+    pub categories: Vec<String>,
+    /// UID of the parent.
+    pub related_to: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    /// The calendar holds no VTODO, e.g. only a VEVENT.
+    NoTodo,
+    Invalid(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::NoTodo => f.write_str("The calendar holds no VTODO"),
+            ParseError::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Reads the first VTODO of a VCALENDAR. Properties of components nested in
+/// it, such as VALARMs, are ignored.
+pub fn parse(body: &str) -> Result<VTodo, ParseError> {
+    let unfolded = body
+        .replace("\r\n ", "")
+        .replace("\r\n\t", "")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+
+    let mut todo = VTodo::default();
+    let mut found = false;
+    // Components open inside the VTODO, 0 for the VTODO itself.
+    let mut depth: Option<usize> = None;
+    let mut status_completed = false;
+    let mut completed_at = false;
+
+    for raw in unfolded.lines() {
+        let raw = raw.trim_end_matches('\r');
+        if raw.is_empty() {
+            continue;
+        }
+        let (name, params, value) = property(raw)
+            .ok_or_else(|| ParseError::Invalid(format!("Invalid content line: {}", raw)))?;
+
+        match (depth, name.as_str()) {
+            (None, "BEGIN") if value.eq_ignore_ascii_case("VTODO") && !found => {
+                depth = Some(0);
+                found = true;
+            }
+            (Some(0), "END") => depth = None,
+            (Some(d), "BEGIN") => depth = Some(d + 1),
+            (Some(d), "END") => depth = Some(d - 1),
+            (Some(0), "UID") => todo.uid = value.to_string(),
+            (Some(0), "SUMMARY") => todo.summary = unescape(value),
+            (Some(0), "DESCRIPTION") => todo.description = unescape(value),
+            (Some(0), "STATUS") => status_completed = value.eq_ignore_ascii_case("COMPLETED"),
+            (Some(0), "COMPLETED") => completed_at = true,
+            (Some(0), "DUE") => {
+                let tzid = param(&params, "TZID");
+                todo.due_at = Some(
+                    parse_time(value, tzid)
+                        .ok_or_else(|| ParseError::Invalid(format!("Invalid DUE: {}", value)))?,
+                );
+                todo.timezone = tzid
+                    .filter(|tzid| tzid.parse::<Tz>().is_ok())
+                    .map(str::to_string);
+            }
+            (Some(0), "PRIORITY") => {
+                todo.priority = match value.trim().parse::<u8>() {
+                    Ok(1) => Some(Priority::Urgent),
+                    Ok(2..=4) => Some(Priority::High),
+                    Ok(0) | Ok(5) => Some(Priority::Normal),
+                    Ok(6..=9) => Some(Priority::Low),
+                    _ => return Err(ParseError::Invalid(format!("Invalid PRIORITY: {}", value))),
+                }
+            }
+            (Some(0), "RRULE") => todo.rrule = Some(value.to_string()),
+            (Some(0), "CATEGORIES") => todo.categories.extend(
+                split_list(value)
+                    .into_iter()
+                    .filter(|category| !category.trim().is_empty()),
+            ),
+            (Some(0), "RELATED-TO") => {
+                let parent = param(&params, "RELTYPE")
+                    .is_none_or(|reltype| reltype.eq_ignore_ascii_case("PARENT"));
+                if parent {
+                    todo.related_to = Some(value.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !found {
+        return Err(ParseError::NoTodo);
+    }
+    if todo.uid.is_empty() {
+        return Err(ParseError::Invalid("The VTODO has no UID".to_string()));
+    }
+    todo.complete = status_completed || completed_at;
+    Ok(todo)
+}
+
+/// Parameters of a property by upper-cased name.
+type Params = Vec<(String, String)>;
+
+/// Splits a content line into its upper-cased name, its parameters and its
+/// value. Quoted parameter values may hold `:` and `;`.
+fn property(line: &str) -> Option<(String, Params, &str)> {
+    let mut quoted = false;
+    let mut colon = None;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ':' if !quoted => {
+                colon = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let colon = colon?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+
+    let mut parts = Vec::new();
+    let mut start = 0;
+    quoted = false;
+    for (i, c) in head.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                parts.push(&head[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&head[start..]);
+
+    let name = parts.first()?.trim().to_ascii_uppercase();
+    let params = parts[1..]
+        .iter()
+        .filter_map(|part| part.split_once('='))
+        .map(|(key, value)| {
+            (
+                key.trim().to_ascii_uppercase(),
+                value.trim_matches('"').to_string(),
+            )
+        })
+        .collect();
+    Some((name, params, value))
+}
+
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Reads a DATE-TIME in UTC, in the zone `tzid` or floating, or a DATE,
+/// which counts from midnight. Floating times and unknown zones count as UTC.
+fn parse_time(value: &str, tzid: Option<&str>) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Some(utc) = value.strip_suffix('Z') {
+        let naive = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some(Utc.from_utc_datetime(&naive));
+    }
+
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y%m%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })?;
+    match tzid.and_then(|tzid| tzid.parse::<Tz>().ok()) {
+        Some(tz) => tz
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|at| at.with_timezone(&Utc)),
+        None => Some(Utc.from_utc_datetime(&naive)),
+    }
+}
+
+/// Undoes `text`.
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(next) => unescaped.push(next),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// Splits a list of TEXT values at the commas that are not escaped.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            ',' if !escaped => {
+                items.push(unescape(&value[start..i]));
+                start = i + 1;
+            }
+            _ => escaped = false,
+        }
+    }
+    items.push(unescape(&value[start..]));
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_text_values() {
+        assert_eq!(text("a,b;c\\d\r\ne"), r"a\,b\;c\\d\ne");
+        assert_eq!(text("Plain: text"), "Plain: text");
+    }
+
+    #[test]
+    fn leaves_lines_up_to_the_limit_alone() {
+        let mut out = String::new();
+        line(
+            &mut out,
+            "SUMMARY",
+            &"a".repeat(LINE_LIMIT - "SUMMARY:".len()),
+        );
+        assert_eq!(out.matches("\r\n").count(), 1);
+        assert_eq!(out.len(), LINE_LIMIT + 2);
+    }
+
+    #[test]
+    fn folds_long_lines_without_splitting_characters() {
+        let value = "ä€😀x".repeat(30);
+        let mut out = String::new();
+        line(&mut out, "SUMMARY", &value);
+
+        let lines: Vec<&str> = out.strip_suffix("\r\n").unwrap().split("\r\n").collect();
+        assert!(lines.len() > 1);
+        assert!(lines.iter().all(|line| line.len() <= LINE_LIMIT));
+        assert!(lines[1..].iter().all(|line| line.starts_with(' ')));
+        assert_eq!(out.replace("\r\n ", ""), format!("SUMMARY:{}\r\n", value));
+    }
+
+    fn todo(title: &str, content: &str, tags: &[&str]) -> ToDoModel {
+        let tags: Vec<_> = tags
+            .iter()
+            .map(|name| {
+                serde_json::json!({
+                    "id": uuid::Uuid::new_v4(),
+                    "userId": uuid::Uuid::nil(),
+                    "name": name,
+                    "createdAt": "2023-06-01T00:00:00Z",
+                })
+            })
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "id": uuid::Uuid::new_v4(),
+            "title": title,
+            "content": content,
+            "complete": false,
+            "version": 1,
+            "priority": "high",
+            "timezone": "UTC",
+            "position": "00000001V",
+            "tags": tags,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let title = format!("Call Bob, Alice; and \\ the rest {}", "–".repeat(40));
+        let todo = todo(&title, "Line one\nLine two", &["work,home", "errands"]);
+
+        let parsed = parse(&object(&todo, "child@app", Some("parent@app"))).unwrap();
+        assert_eq!(parsed.uid, "child@app");
+        assert_eq!(parsed.summary, title);
+        assert_eq!(parsed.description, "Line one\nLine two");
+        assert_eq!(parsed.categories, ["work,home", "errands"]);
+        assert_eq!(parsed.priority, Some(Priority::High));
+        assert_eq!(parsed.related_to.as_deref(), Some("parent@app"));
+        assert!(!parsed.complete);
+    }
+
+    #[test]
+    fn unescapes_text_values() {
+        assert_eq!(unescape(r"a\,b\;c\\d\ne\Nf"), "a,b;c\\d\ne\nf");
+        assert_eq!(split_list(r"work\,home,errands"), ["work,home", "errands"]);
+        assert_eq!(split_list(r"ends in\\,next"), ["ends in\\", "next"]);
+    }
+
+    #[test]
+    fn unfolds_continuation_lines() {
+        let body = "BEGIN:VCALENDAR\r\nBEGIN:VTODO\r\nUID:a\r\nSUMMARY:Long sum\r\n mary\r\n\tand more\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+        assert_eq!(parse(body).unwrap().summary, "Long summaryand more");
+    }
+}
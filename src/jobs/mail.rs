@@ -0,0 +1,21 @@
+use super::Job;
+use crate::AppState;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// Sends a mail, retried if the mailer fails.
+#[derive(Serialize, Deserialize)]
+pub struct SendMail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+impl Job for SendMail {
+    const KIND: &'static str = "send_mail";
+
+    async fn run(self, data: &AppState) -> Result<(), String> {
+        data.mailer.send(&self.to, &self.subject, &self.body).await
+    }
+}
@@ -0,0 +1,99 @@
+use super::Job;
+use crate::{model::UserModel, rank, store, AppState};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// How long succeeded jobs are kept around for inspection.
+const JOB_RETENTION_DAYS: i64 = 7;
+
+/// Deletes todos that have been in the trash for longer than
+/// `TRASH_RETENTION_DAYS`.
+#[derive(Serialize, Deserialize)]
+pub struct PurgeTrash;
+
+#[async_trait]
+impl Job for PurgeTrash {
+    const KIND: &'static str = "purge_trash";
+
+    async fn run(self, data: &AppState) -> Result<(), String> {
+        let before = chrono::Utc::now() - chrono::Duration::days(data.env.trash_retention_days);
+        let purged = store::todo::purge_trashed_before(&data.db, before)
+            .await
+            .map_err(|err| err.to_string())?;
+        if purged > 0 {
+            println!("🗑️ Purged {} todos from the trash", purged);
+        }
+        Ok(())
+    }
+}
+
+/// Notifies owners of open todos that fall due within
+/// `REMINDER_LEAD_MINUTES`. Each due date is reminded at most once, even if
+/// the notification fails.
+#[derive(Serialize, Deserialize)]
+pub struct SendReminders;
+
+#[async_trait]
+impl Job for SendReminders {
+    const KIND: &'static str = "send_reminders";
+
+    async fn run(self, data: &AppState) -> Result<(), String> {
+        let until = chrono::Utc::now() + chrono::Duration::minutes(data.env.reminder_lead_minutes);
+        let todos = store::todo::claim_reminders(&data.db, until)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        for todo in todos {
+            let user =
+                sqlx::query_as!(UserModel, "SELECT * FROM users WHERE id = $1", todo.user_id)
+                    .fetch_one(&data.db)
+                    .await;
+
+            let sent = match user {
+                Ok(user) => data.notifier.remind(&user, &todo).await,
+                Err(err) => Err(err.to_string()),
+            };
+            if let Err(err) = sent {
+                println!("🔥 Failed to send reminder for {}: {}", todo.id, err);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Spaces out the manual order of users whose ranks have grown long from
+/// many moves into the same spot.
+#[derive(Serialize, Deserialize)]
+pub struct RebalancePositions;
+
+#[async_trait]
+impl Job for RebalancePositions {
+    const KIND: &'static str = "rebalance_positions";
+
+    async fn run(self, data: &AppState) -> Result<(), String> {
+        let users = store::todo::rebalance_positions(&data.db, rank::REBALANCE_LENGTH)
+            .await
+            .map_err(|err| err.to_string())?;
+        if users > 0 {
+            println!("📐 Rebalanced the todo order of {} users", users);
+        }
+        Ok(())
+    }
+}
+
+/// Deletes jobs that succeeded more than `JOB_RETENTION_DAYS` ago.
+#[derive(Serialize, Deserialize)]
+pub struct PruneJobs;
+
+#[async_trait]
+impl Job for PruneJobs {
+    const KIND: &'static str = "prune_jobs";
+
+    async fn run(self, data: &AppState) -> Result<(), String> {
+        let before = chrono::Utc::now() - chrono::Duration::days(JOB_RETENTION_DAYS);
+        store::job::prune(&data.db, before)
+            .await
+            .map_err(|err| err.to_string())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+//! A job queue in the `jobs` table. Every instance runs `work`, which claims
+//! due jobs with `FOR UPDATE SKIP LOCKED` so each runs on one instance only,
+//! retries failed ones with exponential backoff and marks them dead once out
+//! of attempts. Jobs are typed: every kind implements `Job` and is registered
+//! at startup, optionally with a cron schedule.
+
+mod mail;
+mod maintenance;
+
+pub use mail::SendMail;
+pub use maintenance::{PruneJobs, PurgeTrash, RebalancePositions, SendReminders};
+
+use crate::{
+    model::JobModel,
+    store::{self, job::NewJob},
+    AppState,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use futures::future::BoxFuture;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use sqlx::{Executor, Postgres};
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How many jobs an instance runs at once.
+const CONCURRENCY: usize = 8;
+/// How long a claimed job is left to its instance. A job still running
+/// after that is picked up again elsewhere.
+const LEASE_SECONDS: i64 = 5 * 60;
+/// Wait after the first failed attempt, doubled after each further one.
+const BACKOFF_BASE_SECONDS: i64 = 10;
+const BACKOFF_MAX_SECONDS: i64 = 60 * 60;
+
+/// A kind of job. The job itself is its payload.
+#[async_trait]
+pub trait Job: Serialize + DeserializeOwned + Send + 'static {
+    /// Stored in `jobs.kind`, unique among the registered jobs.
+    const KIND: &'static str;
+    /// Attempts before the job is dead.
+    const MAX_ATTEMPTS: i32 = 5;
+
+    async fn run(self, data: &AppState) -> Result<(), String>;
+}
+
+/// Queues a job to run as soon as possible.
+pub async fn enqueue<'c, J: Job>(
+    executor: impl Executor<'c, Database = Postgres>,
+    job: &J,
+) -> Result<JobModel, sqlx::Error> {
+    schedule(executor, job, Utc::now()).await
+}
+
+/// Queues a job to run at `run_at` or later.
+pub async fn schedule<'c, J: Job>(
+    executor: impl Executor<'c, Database = Postgres>,
+    job: &J,
+    run_at: DateTime<Utc>,
+) -> Result<JobModel, sqlx::Error> {
+    let job = NewJob {
+        kind: J::KIND,
+        payload: serde_json::to_value(job).unwrap_or_default(),
+        max_attempts: J::MAX_ATTEMPTS,
+        run_at,
+        unique_key: None,
+    };
+    store::job::enqueue(executor, job)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)
+}
+
+type Runner =
+    Box<dyn Fn(Arc<AppState>, Value) -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+/// A job queued on every occurrence of a cron schedule.
+struct Recurring {
+    name: &'static str,
+    schedule: Schedule,
+    kind: &'static str,
+    payload: Value,
+    max_attempts: i32,
+}
+
+/// The jobs an instance knows how to run.
+#[derive(Default)]
+pub struct Registry {
+    runners: HashMap<&'static str, Runner>,
+    recurring: Vec<Recurring>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    pub fn register<J: Job>(mut self) -> Self {
+        let runner: Runner = Box::new(|data, payload| {
+            Box::pin(async move {
+                let job: J = serde_json::from_value(payload)
+                    .map_err(|err| format!("Invalid payload: {}", err))?;
+                job.run(&data).await
+            })
+        });
+        if self.runners.insert(J::KIND, runner).is_some() {
+            panic!("Job kind {} is registered twice", J::KIND);
+        }
+        self
+    }
+
+    /// Registers the job and queues it on every occurrence of `schedule`, a
+    /// cron expression with seconds: `sec min hour day-of-month month
+    /// day-of-week`. Panics on an invalid expression.
+    pub fn every<J: Job>(mut self, name: &'static str, schedule: &str, job: J) -> Self {
+        if !self.runners.contains_key(J::KIND) {
+            self = self.register::<J>();
+        }
+        self.recurring.push(Recurring {
+            name,
+            schedule: Schedule::from_str(schedule)
+                .unwrap_or_else(|err| panic!("Invalid schedule for {}: {}", name, err)),
+            kind: J::KIND,
+            payload: serde_json::to_value(job).unwrap_or_default(),
+            max_attempts: J::MAX_ATTEMPTS,
+        });
+        self
+    }
+}
+
+/// Runs queued jobs and queues recurring ones until the server stops.
+pub async fn work(data: Arc<AppState>, registry: Registry) {
+    let registry = Arc::new(registry);
+    let slots = Arc::new(Semaphore::new(CONCURRENCY));
+    // Occurrences up to here are queued already, by this or another instance.
+    let mut scheduled_until = Utc::now();
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let now = Utc::now();
+        queue_recurring(&data, &registry, scheduled_until, now).await;
+        scheduled_until = now;
+
+        let free = slots.available_permits();
+        if free == 0 {
+            continue;
+        }
+        let lease = chrono::Duration::seconds(LEASE_SECONDS);
+        let jobs = match store::job::claim(&data.db, free as i64, lease).await {
+            Ok(jobs) => jobs,
+            Err(err) => {
+                println!("🔥 Failed to claim jobs: {:?}", err);
+                continue;
+            }
+        };
+
+        for job in jobs {
+            let Ok(slot) = slots.clone().acquire_owned().await else {
+                return;
+            };
+            let data = data.clone();
+            let registry = registry.clone();
+            tokio::spawn(async move {
+                run(data, &registry, job).await;
+                drop(slot);
+            });
+        }
+    }
+}
+
+/// Queues the occurrences of the recurring jobs in `(after, until]`. The
+/// unique key of an occurrence keeps other instances from queueing it again.
+async fn queue_recurring(
+    data: &AppState,
+    registry: &Registry,
+    after: DateTime<Utc>,
+    until: DateTime<Utc>,
+) {
+    for recurring in &registry.recurring {
+        for at in recurring
+            .schedule
+            .after(&after)
+            .take_while(|at| *at <= until)
+        {
+            let job = NewJob {
+                kind: recurring.kind,
+                payload: recurring.payload.clone(),
+                max_attempts: recurring.max_attempts,
+                run_at: at,
+                unique_key: Some(format!("{}@{}", recurring.name, at.timestamp())),
+            };
+            if let Err(err) = store::job::enqueue(&data.db, job).await {
+                println!("🔥 Failed to queue {}: {:?}", recurring.name, err);
+            }
+        }
+    }
+}
+
+async fn run(data: Arc<AppState>, registry: &Registry, job: JobModel) {
+    let outcome = match registry.runners.get(job.kind.as_str()) {
+        // A panicking job fails like any other.
+        Some(runner) => tokio::spawn(runner(data.clone(), job.payload.clone()))
+            .await
+            .unwrap_or_else(|err| Err(format!("Panicked: {}", err))),
+        None => Err(format!("No handler for job kind {}", job.kind)),
+    };
+
+    // Claims always set a token.
+    let token = job.lock_token.unwrap_or_default();
+    let recorded = match outcome {
+        Ok(()) => store::job::succeed(&data.db, job.id, token).await,
+        Err(error) => {
+            let retry_at = (job.attempts < job.max_attempts).then(|| retry_at(job.attempts));
+            if retry_at.is_none() {
+                println!("💀 Job {} ({}) is dead: {}", job.id, job.kind, error);
+            }
+            store::job::fail(&data.db, job.id, token, &error, retry_at).await
+        }
+    };
+    match recorded {
+        Ok(true) => {}
+        Ok(false) => println!(
+            "🔥 Job {} ({}) outlived its lock, its outcome is left to the next claim",
+            job.id, job.kind
+        ),
+        Err(err) => println!("🔥 Failed to record job {}: {:?}", job.id, err),
+    }
+}
+
+/// When to try again after `attempts` failed attempts.
+fn retry_at(attempts: i32) -> DateTime<Utc> {
+    let seconds = BACKOFF_BASE_SECONDS
+        .saturating_mul(1 << (attempts - 1).clamp(0, 20))
+        .min(BACKOFF_MAX_SECONDS);
+    Utc::now() + chrono::Duration::seconds(seconds)
+}
@@ -0,0 +1,18 @@
+use async_trait::async_trait;
+
+/// Delivers transactional mail such as address confirmations.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), String>;
+}
+
+/// Prints mail to stdout, for development without a mail server.
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> Result<(), String> {
+        println!("📧 Mail to {}: {}\n{}", to, subject, body);
+        Ok(())
+    }
+}
@@ -2,23 +2,48 @@ use axum::http::{
     header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
     HeaderValue, Method,
 };
-use config::Config;
+use config::{Config, NotifierConfig, StorageConfig};
 use dotenv::dotenv;
+use events::EventHub;
+use mailer::{LogMailer, Mailer};
+use notifier::{LogNotifier, MailNotifier, Notifier};
 use route::router;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 use std::sync::Arc;
+use storage::{LocalStorage, S3Storage, Storage};
 use tower_http::cors::CorsLayer;
 
 mod auth;
 mod config;
+mod events;
+mod graphql;
+mod grpc;
 mod handlers;
+mod ical;
+mod jobs;
+mod mailer;
 mod model;
+mod notifier;
+mod oidc;
+mod rank;
+mod recurrence;
 mod route;
 mod schema;
+mod storage;
+mod store;
+mod tasks;
+mod transfer;
+mod webhook;
 
 pub struct AppState {
     db: Pool<Postgres>,
     env: Config,
+    events: EventHub,
+    graphql: graphql::TodoSchema,
+    http: reqwest::Client,
+    mailer: Arc<dyn Mailer>,
+    notifier: Arc<dyn Notifier>,
+    storage: Arc<dyn Storage>,
 }
 
 #[tokio::main]
@@ -49,11 +74,67 @@ async fn main() {
         .allow_credentials(true)
         .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE]);
 
-    let app = router(Arc::new(AppState {
+    let http = reqwest::Client::new();
+
+    let storage: Arc<dyn Storage> = match config.storage.clone() {
+        StorageConfig::Local { dir } => Arc::new(LocalStorage {
+            root: dir.into(),
+            base_url: config.app_url.clone(),
+        }),
+        StorageConfig::S3 {
+            endpoint,
+            bucket,
+            region,
+            access_key,
+            secret_key,
+            public_url,
+        } => Arc::new(S3Storage {
+            http: http.clone(),
+            endpoint,
+            bucket,
+            region,
+            access_key,
+            secret_key,
+            public_url,
+        }),
+    };
+
+    let mailer: Arc<dyn Mailer> = Arc::new(LogMailer);
+    let notifier: Arc<dyn Notifier> = match config.notifier {
+        NotifierConfig::Log => Arc::new(LogNotifier),
+        NotifierConfig::Mail => Arc::new(MailNotifier {
+            mailer: mailer.clone(),
+            app_url: config.app_url.clone(),
+        }),
+    };
+
+    let app_state = Arc::new(AppState {
         db: pool.clone(),
         env: config.clone(),
-    }))
-    .layer(cors);
+        events: EventHub::new(),
+        graphql: graphql::schema(&config),
+        http,
+        mailer,
+        notifier,
+        storage,
+    });
+
+    let registry = jobs::Registry::new()
+        .register::<jobs::SendMail>()
+        .every("send_reminders", "0 * * * * *", jobs::SendReminders)
+        .every("purge_trash", "0 0 * * * *", jobs::PurgeTrash)
+        .every(
+            "rebalance_positions",
+            "0 30 * * * *",
+            jobs::RebalancePositions,
+        )
+        .every("prune_jobs", "0 15 3 * * *", jobs::PruneJobs);
+    tokio::spawn(jobs::work(app_state.clone(), registry));
+    tokio::spawn(tasks::listen_events(app_state.clone()));
+    tokio::spawn(tasks::deliver_webhooks(app_state.clone()));
+    tokio::spawn(grpc::serve(app_state.clone()));
+
+    let app = router(app_state).layer(cors);
 
     println!("🚀 Server started successfully");
     axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
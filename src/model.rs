@@ -1,10 +1,10 @@
 use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{types::Json, FromRow};
 use uuid::Uuid;
 
 #[allow(non_snake_case)]
-#[derive(Debug, FromRow, Deserialize, Serialize)]
+#[derive(Debug, FromRow, Deserialize, Serialize, Clone)]
 pub struct ToDoModel {
     pub id: Uuid,
     pub title: String,
@@ -14,6 +14,119 @@ pub struct ToDoModel {
     pub created_at: Option<DateTime<Utc>>,
     #[serde(rename = "updatedAt")]
     pub updated_at: Option<DateTime<Utc>>,
+    #[serde(rename = "userId")]
+    pub user_id: Option<Uuid>,
+    pub version: i32,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(rename = "dueAt")]
+    pub due_at: Option<DateTime<Utc>>,
+    pub priority: Priority,
+    #[serde(rename = "completedAt")]
+    pub completed_at: Option<DateTime<Utc>>,
+    pub rrule: Option<String>,
+    pub timezone: String,
+    #[serde(rename = "listId")]
+    pub list_id: Option<Uuid>,
+    #[serde(rename = "parentId")]
+    pub parent_id: Option<Uuid>,
+    /// Rank in the user's manual order, see `rank`.
+    pub position: String,
+    /// Not a column, filled in by `store::tag::attach`. `Json` only lets the
+    /// derived `FromRow` default it, and `query_as!` reads it as `'[]'`.
+    #[sqlx(default)]
+    #[serde(default)]
+    pub tags: Json<Vec<TagModel>>,
+}
+
+/// Declared from lowest to highest, which is also how Postgres sorts it.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[sqlx(type_name = "todo_priority", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+    Urgent,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize, Clone)]
+pub struct TagModel {
+    pub id: Uuid,
+    #[serde(rename = "userId")]
+    pub user_id: Uuid,
+    pub name: String,
+    pub color: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// A list with the counts of its todos outside the trash.
+#[derive(Debug, FromRow, Deserialize, Serialize, Clone)]
+pub struct ListModel {
+    pub id: Uuid,
+    #[serde(rename = "userId")]
+    pub user_id: Uuid,
+    pub name: String,
+    #[serde(rename = "archivedAt")]
+    pub archived_at: Option<DateTime<Utc>>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(rename = "todoCount")]
+    pub todo_count: i64,
+    #[serde(rename = "completedCount")]
+    pub completed_count: i64,
+    /// Completed todos in percent, 0 for an empty list.
+    pub completion: f64,
+}
+
+/// Declared from least to most allowed, so roles compare by what they allow.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, sqlx::Type,
+)]
+#[sqlx(type_name = "share_role", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ShareRole {
+    Viewer,
+    Editor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[sqlx(type_name = "share_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ShareStatus {
+    Pending,
+    Accepted,
+    Declined,
+}
+
+/// A todo or list shared with a mail address, together with what it is and
+/// who shared it.
+#[derive(Debug, FromRow, Deserialize, Serialize, Clone)]
+pub struct ShareModel {
+    pub id: Uuid,
+    #[serde(rename = "ownerId")]
+    pub owner_id: Uuid,
+    #[serde(rename = "ownerName")]
+    pub owner_name: String,
+    #[serde(rename = "todoId")]
+    pub todo_id: Option<Uuid>,
+    #[serde(rename = "listId")]
+    pub list_id: Option<Uuid>,
+    /// Title of the todo or name of the list.
+    pub subject: String,
+    pub mail: String,
+    /// The recipient, once they answered.
+    #[serde(rename = "userId")]
+    pub user_id: Option<Uuid>,
+    pub role: ShareRole,
+    pub status: ShareStatus,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
 }
 
 #[allow(non_snake_case)]
@@ -25,9 +138,150 @@ pub struct UserModel {
     pub password: String,
     pub role: String,
     pub photo: String,
-    pub verify: bool,
+    pub verify: Option<bool>,
     #[serde(rename = "createdAt")]
     pub created_at: Option<DateTime<Utc>>,
     #[serde(rename = "updatedAt")]
     pub updated_at: Option<DateTime<Utc>>,
 }
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize, FromRow, Serialize, Clone)]
+pub struct PersonalTokenModel {
+    pub id: Uuid,
+    #[serde(rename = "userId")]
+    pub user_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(rename = "createdAt")]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize, Clone)]
+pub struct TodoEventModel {
+    pub id: i64,
+    #[serde(rename = "todoId")]
+    pub todo_id: Uuid,
+    #[serde(rename = "userId")]
+    pub user_id: Uuid,
+    #[serde(rename = "actorId")]
+    pub actor_id: Option<Uuid>,
+    pub kind: String,
+    pub version: i32,
+    pub changes: serde_json::Value,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    /// Place in the order the writes committed in, which followers resume
+    /// after. Only known once the write that recorded the event committed.
+    pub position: Option<i64>,
+}
+
+/// An endpoint told about events. Without a user it belongs to the admins
+/// and hears about everyone.
+#[derive(Debug, FromRow, Deserialize, Serialize, Clone)]
+pub struct WebhookModel {
+    pub id: Uuid,
+    #[serde(rename = "userId")]
+    pub user_id: Option<Uuid>,
+    pub url: String,
+    pub events: Vec<String>,
+    pub active: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[sqlx(type_name = "webhook_delivery_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+/// An event sent or to be sent to a webhook, with the outcome of the last
+/// attempt.
+#[derive(Debug, FromRow, Deserialize, Serialize, Clone)]
+pub struct WebhookDeliveryModel {
+    pub id: Uuid,
+    #[serde(rename = "webhookId")]
+    pub webhook_id: Uuid,
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    #[serde(rename = "nextAttemptAt")]
+    pub next_attempt_at: DateTime<Utc>,
+    #[serde(rename = "responseStatus")]
+    pub response_status: Option<i32>,
+    #[serde(rename = "responseBody")]
+    pub response_body: Option<String>,
+    pub error: Option<String>,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: Option<i32>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "deliveredAt")]
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, sqlx::Type)]
+#[sqlx(type_name = "job_status", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Dead,
+}
+
+#[derive(Debug, FromRow, Deserialize, Serialize, Clone)]
+pub struct JobModel {
+    pub id: Uuid,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub status: JobStatus,
+    pub attempts: i32,
+    #[serde(rename = "maxAttempts")]
+    pub max_attempts: i32,
+    #[serde(rename = "runAt")]
+    pub run_at: DateTime<Utc>,
+    #[serde(rename = "lockedUntil")]
+    pub locked_until: Option<DateTime<Utc>>,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(rename = "uniqueKey")]
+    pub unique_key: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(rename = "finishedAt")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Proves the lock of the instance that claimed the job last.
+    #[serde(skip)]
+    pub lock_token: Option<Uuid>,
+}
+
+/// A private iCalendar subscription. Without a list it covers all of the
+/// user's todos.
+#[derive(Debug, FromRow, Deserialize, Serialize, Clone)]
+pub struct CalendarFeedModel {
+    pub id: Uuid,
+    #[serde(rename = "userId")]
+    pub user_id: Uuid,
+    #[serde(rename = "listId")]
+    pub list_id: Option<Uuid>,
+    #[serde(rename = "lastPolledAt")]
+    pub last_polled_at: Option<DateTime<Utc>>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: DateTime<Utc>,
+}
@@ -0,0 +1,51 @@
+use crate::{
+    mailer::Mailer,
+    model::{ToDoModel, UserModel},
+};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Tells a user that one of their todos falls due soon.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn remind(&self, user: &UserModel, todo: &ToDoModel) -> Result<(), String>;
+}
+
+/// Prints reminders to stdout, for development.
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn remind(&self, user: &UserModel, todo: &ToDoModel) -> Result<(), String> {
+        println!(
+            "⏰ Reminder for {}: {} is due at {}",
+            user.mail,
+            todo.title,
+            todo.due_at.unwrap_or_default()
+        );
+        Ok(())
+    }
+}
+
+/// Sends reminders as mail.
+pub struct MailNotifier {
+    pub mailer: Arc<dyn Mailer>,
+    pub app_url: String,
+}
+
+#[async_trait]
+impl Notifier for MailNotifier {
+    async fn remind(&self, user: &UserModel, todo: &ToDoModel) -> Result<(), String> {
+        let body = format!(
+            "Hi {},\n\n\"{}\" is due at {}.\n\n{}/api/todos/{}",
+            user.name,
+            todo.title,
+            todo.due_at.unwrap_or_default().to_rfc2822(),
+            self.app_url,
+            todo.id
+        );
+        self.mailer
+            .send(&user.mail, &format!("Reminder: {}", todo.title), &body)
+            .await
+    }
+}
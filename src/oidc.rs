@@ -0,0 +1,211 @@
+use crate::config::OidcProvider;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+#[derive(Debug)]
+pub struct OidcError(pub String);
+
+impl fmt::Display for OidcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(err: reqwest::Error) -> Self {
+        OidcError(format!("Provider request failed: {}", err))
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for OidcError {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        OidcError(format!("Invalid ID token: {}", err))
+    }
+}
+
+/// The subset of `/.well-known/openid-configuration` this client needs.
+#[derive(Debug, Deserialize)]
+pub struct Discovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+/// State kept in a signed cookie between the redirect and the callback.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginState {
+    pub provider: String,
+    pub state: String,
+    pub nonce: String,
+    pub verifier: String,
+    pub exp: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub name: Option<String>,
+    pub picture: Option<String>,
+}
+
+pub fn random_string() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// S256 code challenge for a PKCE verifier.
+pub fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+pub async fn discover(
+    http: &reqwest::Client,
+    provider: &OidcProvider,
+) -> Result<Discovery, OidcError> {
+    let discovery: Discovery = http
+        .get(format!(
+            "{}/.well-known/openid-configuration",
+            provider.issuer
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    if discovery.issuer.trim_end_matches('/') != provider.issuer {
+        return Err(OidcError(format!(
+            "Discovery issuer {} does not match {}",
+            discovery.issuer, provider.issuer
+        )));
+    }
+
+    Ok(discovery)
+}
+
+pub fn authorization_url(
+    discovery: &Discovery,
+    provider: &OidcProvider,
+    login: &LoginState,
+) -> Result<String, OidcError> {
+    let url = reqwest::Url::parse_with_params(
+        &discovery.authorization_endpoint,
+        &[
+            ("response_type", "code"),
+            ("client_id", provider.client_id.as_str()),
+            ("redirect_uri", provider.redirect_url.as_str()),
+            ("scope", provider.scopes.as_str()),
+            ("state", login.state.as_str()),
+            ("nonce", login.nonce.as_str()),
+            ("code_challenge", code_challenge(&login.verifier).as_str()),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .map_err(|e| OidcError(format!("Invalid authorization endpoint: {}", e)))?;
+
+    Ok(url.to_string())
+}
+
+/// The algorithms a token signed with the key may name: those of its key
+/// type, narrowed to the one the key declares, if any. Never a symmetric
+/// one, the token header alone does not get to choose.
+fn key_algorithms(jwk: &Jwk) -> Vec<Algorithm> {
+    let mut algorithms = match &jwk.algorithm {
+        AlgorithmParameters::RSA(_) => vec![
+            Algorithm::RS256,
+            Algorithm::RS384,
+            Algorithm::RS512,
+            Algorithm::PS256,
+            Algorithm::PS384,
+            Algorithm::PS512,
+        ],
+        AlgorithmParameters::EllipticCurve(params) => match params.curve {
+            EllipticCurve::P256 => vec![Algorithm::ES256],
+            EllipticCurve::P384 => vec![Algorithm::ES384],
+            _ => Vec::new(),
+        },
+        AlgorithmParameters::OctetKeyPair(_) => vec![Algorithm::EdDSA],
+        AlgorithmParameters::OctetKey(_) => Vec::new(),
+    };
+    if let Some(declared) = jwk.common.algorithm {
+        algorithms.retain(|algorithm| *algorithm == declared);
+    }
+    algorithms
+}
+
+/// Redeems the authorization code and returns the verified ID token claims.
+pub async fn exchange_code(
+    http: &reqwest::Client,
+    discovery: &Discovery,
+    provider: &OidcProvider,
+    login: &LoginState,
+    code: &str,
+) -> Result<IdClaims, OidcError> {
+    let tokens: TokenResponse = http
+        .post(&discovery.token_endpoint)
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", provider.redirect_url.as_str()),
+            ("client_id", provider.client_id.as_str()),
+            ("client_secret", provider.client_secret.as_str()),
+            ("code_verifier", login.verifier.as_str()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let header = decode_header(&tokens.id_token)?;
+    let jwks: JwkSet = http
+        .get(&discovery.jwks_uri)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None => jwks.keys.first(),
+    }
+    .ok_or_else(|| OidcError("No matching signing key for ID token".to_string()))?;
+
+    let algorithms = key_algorithms(jwk);
+    if !algorithms.contains(&header.alg) {
+        return Err(OidcError(format!(
+            "ID token algorithm {:?} does not match the signing key",
+            header.alg
+        )));
+    }
+    let mut validation = Validation::new(header.alg);
+    validation.algorithms = algorithms;
+    validation.set_issuer(&[&discovery.issuer]);
+    validation.set_audience(&[&provider.client_id]);
+    let claims =
+        decode::<IdClaims>(&tokens.id_token, &DecodingKey::from_jwk(jwk)?, &validation)?.claims;
+
+    if claims.nonce.as_deref() != Some(login.nonce.as_str()) {
+        return Err(OidcError("ID token nonce does not match".to_string()));
+    }
+
+    Ok(claims)
+}
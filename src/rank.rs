@@ -0,0 +1,113 @@
+//! Lexicographic ranks for manually ordered todos. Ranks compare by their
+//! bytes, so there is always room for a new one between two others and a
+//! move only rewrites the rank of the todo being moved.
+
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Ranks longer than this are worth rebalancing.
+pub const REBALANCE_LENGTH: i32 = 24;
+
+fn digit(byte: u8) -> usize {
+    DIGITS.iter().position(|d| *d == byte).unwrap_or(0)
+}
+
+/// A rank sorting after `after` and before `before`, where `None` stands
+/// for the start or end. `None` if `after` does not sort before `before`.
+///
+/// Ranks never end in the lowest digit, which keeps room below each of them.
+pub fn between(after: Option<&str>, before: Option<&str>) -> Option<String> {
+    let after = after.unwrap_or_default().as_bytes();
+    let mut before = before.map(str::as_bytes);
+    if before.is_some_and(|before| before <= after) {
+        return None;
+    }
+
+    let mut rank = Vec::new();
+    for i in 0.. {
+        let low = after.get(i).map_or(0, |byte| digit(*byte));
+        let high = match before.map(|before| before.get(i)) {
+            Some(Some(byte)) => digit(*byte),
+            // Only `before` padded with the lowest digit is left in between,
+            // which happens when it ends in that digit.
+            Some(None) => return None,
+            None => DIGITS.len(),
+        };
+
+        if high > low + 1 {
+            rank.push(DIGITS[(low + high) / 2]);
+            break;
+        }
+        rank.push(DIGITS[low]);
+        // Below a digit of `before`, everything that follows fits.
+        if high > low {
+            before = None;
+        }
+    }
+
+    String::from_utf8(rank).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_between(after: Option<&str>, before: Option<&str>) -> String {
+        let rank = between(after, before).expect("there is room");
+        assert!(
+            after.is_none_or(|after| after < rank.as_str()),
+            "{:?} < {}",
+            after,
+            rank
+        );
+        assert!(
+            before.is_none_or(|before| rank.as_str() < before),
+            "{} < {:?}",
+            rank,
+            before
+        );
+        assert!(!rank.ends_with('0'), "{}", rank);
+        rank
+    }
+
+    #[test]
+    fn ranks_without_bounds() {
+        let first = assert_between(None, None);
+        assert_between(None, Some(&first));
+        assert_between(Some(&first), None);
+    }
+
+    #[test]
+    fn ranks_between_adjacent_ranks() {
+        assert_eq!(between(Some("A"), Some("B")), Some("AV".to_string()));
+        assert_between(Some("00000001V"), Some("00000002V"));
+        assert_between(Some("z"), None);
+        assert_between(None, Some("1"));
+    }
+
+    #[test]
+    fn keeps_finding_room_in_the_same_gap() {
+        let (mut after, mut before) = ("A".to_string(), "B".to_string());
+        for i in 0..200 {
+            let rank = assert_between(Some(&after), Some(&before));
+            if i % 2 == 0 {
+                after = rank;
+            } else {
+                before = rank;
+            }
+        }
+    }
+
+    #[test]
+    fn has_no_room_above_a_rank_padded_with_the_lowest_digit() {
+        assert_eq!(between(Some("A"), Some("A0")), None);
+        assert_eq!(between(None, Some("0")), None);
+        assert_between(Some("A0"), Some("A1"));
+        assert_between(Some("A"), Some("A01"));
+    }
+
+    #[test]
+    fn rejects_bounds_out_of_order() {
+        assert_eq!(between(Some("B"), Some("A")), None);
+        assert_eq!(between(Some("A"), Some("A")), None);
+    }
+}
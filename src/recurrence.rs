@@ -0,0 +1,111 @@
+//! Recurring todos carry an iCalendar RRULE. Occurrences are expanded from
+//! the open occurrence's due date as DTSTART, in the todo's time zone, so a
+//! weekly 09:00 stays at 09:00 local time across DST changes.
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use rrule::RRuleSet;
+use std::fmt;
+
+/// Upper bound for previews.
+pub const MAX_PREVIEW: usize = 50;
+
+#[derive(Debug)]
+pub struct RecurrenceError(pub String);
+
+impl fmt::Display for RecurrenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<rrule::RRuleError> for RecurrenceError {
+    fn from(err: rrule::RRuleError) -> Self {
+        RecurrenceError(format!("Invalid RRULE: {}", err))
+    }
+}
+
+/// The rule without an optional `RRULE:` prefix.
+fn rule_part(rrule: &str) -> &str {
+    let rrule = rrule.trim();
+    rrule.strip_prefix("RRULE:").unwrap_or(rrule)
+}
+
+fn time_zone(timezone: &str) -> Result<Tz, RecurrenceError> {
+    timezone
+        .parse()
+        .map_err(|_| RecurrenceError(format!("Unknown time zone: {}", timezone)))
+}
+
+fn rule_set(
+    rrule: &str,
+    timezone: &str,
+    start: DateTime<Utc>,
+) -> Result<RRuleSet, RecurrenceError> {
+    if rrule.contains(['\r', '\n']) {
+        return Err(RecurrenceError(
+            "An RRULE must be a single line".to_string(),
+        ));
+    }
+
+    let tz = time_zone(timezone)?;
+    let ical = format!(
+        "DTSTART;TZID={}:{}\nRRULE:{}",
+        tz.name(),
+        start.with_timezone(&tz).format("%Y%m%dT%H%M%S"),
+        rule_part(rrule)
+    );
+
+    Ok(ical.parse()?)
+}
+
+/// Checks a rule and time zone as given by a client.
+pub fn validate(rrule: Option<&str>, timezone: Option<&str>) -> Result<(), RecurrenceError> {
+    if let Some(timezone) = timezone {
+        time_zone(timezone)?;
+    }
+    match rrule {
+        Some(rrule) => rule_set(rrule, timezone.unwrap_or("UTC"), Utc::now()).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+/// Up to `count` occurrences strictly after `due_at`.
+pub fn upcoming(
+    rrule: &str,
+    timezone: &str,
+    due_at: DateTime<Utc>,
+    count: usize,
+) -> Result<Vec<DateTime<Utc>>, RecurrenceError> {
+    let occurrences = rule_set(rrule, timezone, due_at)?
+        .into_iter()
+        .map(|occurrence| occurrence.with_timezone(&Utc))
+        .skip_while(|occurrence| *occurrence <= due_at)
+        .take(count)
+        .collect();
+
+    Ok(occurrences)
+}
+
+/// The occurrence after `due_at` together with the rule it should carry, or
+/// `None` once the series is over. The rule is re-anchored on every
+/// occurrence, so a `COUNT` is counted down instead.
+pub fn next(
+    rrule: &str,
+    timezone: &str,
+    due_at: DateTime<Utc>,
+) -> Result<Option<(DateTime<Utc>, String)>, RecurrenceError> {
+    let mut parts: Vec<String> = rule_part(rrule).split(';').map(str::to_string).collect();
+    for part in parts.iter_mut() {
+        if let Some(count) = part.strip_prefix("COUNT=") {
+            match count.parse::<u32>() {
+                Ok(count) if count > 1 => *part = format!("COUNT={}", count - 1),
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    Ok(upcoming(rrule, timezone, due_at, 1)?
+        .pop()
+        .map(|occurrence| (occurrence, parts.join(";"))))
+}
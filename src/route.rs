@@ -1,37 +1,269 @@
 use crate::{
-    auth::auth,
+    auth::{admin_only, auth, basic_auth, session_only, todo_scope},
+    config::StorageConfig,
     handlers::{
-        auth::{get_me_handler, logout_handler, signin_handler, signup_handler},
+        auth::{
+            change_mail_handler, change_password_handler, delete_me_handler, get_me_handler,
+            logout_handler, signin_handler, signup_handler, update_me_handler, verify_mail_handler,
+        },
+        avatar::{delete_avatar_handler, upload_avatar_handler},
+        caldav::{caldav_redirect_handler, dav_handler, dav_root_handler},
+        calendar::{
+            calendar_feed_handler, create_calendar_feed_handler, delete_calendar_feed_handler,
+            download_calendar_handler, get_calendar_feeds_handler, rotate_calendar_feed_handler,
+        },
+        graphql::{graphql_handler, graphql_socket_handler},
         health::health_handler,
+        job::{get_job_handler, get_jobs_handler, retry_job_handler},
+        list::{
+            create_list_handler, delete_list_handler, get_list_handler, get_list_todos_handler,
+            get_lists_handler, update_list_handler,
+        },
+        oidc::{oidc_callback_handler, oidc_login_handler},
+        share::{
+            accept_invitation_handler, decline_invitation_handler, delete_share_handler,
+            get_invitations_handler, get_list_shares_handler, get_shared_handler,
+            get_todo_shares_handler, share_list_handler, share_todo_handler, update_share_handler,
+        },
+        stream::{todo_events_handler, todo_socket_handler},
+        tag::{
+            create_tag_handler, delete_tag_handler, get_tag_handler, get_tags_handler,
+            update_tag_handler,
+        },
         todo::{
-            create_todo_handler, delete_todo_handler, get_todo_handler, get_todos_handler,
-            update_todo_handler,
+            batch_todos_handler, create_todo_handler, delete_todo_handler, end_series_handler,
+            get_occurrences_handler, get_overdue_todos_handler, get_subtasks_handler,
+            get_todo_handler, get_todo_history_handler, get_todos_handler, get_trash_handler,
+            move_todo_handler, purge_todo_handler, restore_todo_handler, revert_todo_handler,
+            skip_occurrence_handler, update_todo_handler,
+        },
+        token::{create_token_handler, delete_token_handler, get_tokens_handler},
+        transfer::{export_todos_handler, import_todos_handler, MAX_IMPORT_BYTES},
+        webhook::{
+            create_webhook_handler, delete_webhook_handler, get_deliveries_handler,
+            get_webhook_handler, get_webhooks_handler, ping_webhook_handler, redeliver_handler,
+            update_webhook_handler,
         },
     },
     AppState,
 };
 use axum::{
+    extract::DefaultBodyLimit,
+    http::{header, HeaderValue},
     middleware,
-    routing::{get, post},
+    routing::{any, delete, get, get_service, patch, post},
     Router,
 };
 use std::sync::Arc;
+use tower_http::{services::ServeDir, set_header::SetResponseHeaderLayer};
 
 pub fn router(app_state: Arc<AppState>) -> Router {
-    return Router::new()
+    let mut router = Router::new()
         .route("/api/health", get(health_handler))
         .route(
             "/api/todos",
-            get(get_todos_handler).post(create_todo_handler),
+            get(get_todos_handler)
+                .post(create_todo_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/batch",
+            post(batch_todos_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/export",
+            get(export_todos_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/ics",
+            get(download_calendar_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/import",
+            post(import_todos_handler)
+                .layer(DefaultBodyLimit::max(MAX_IMPORT_BYTES))
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/events",
+            get(todo_events_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/ws",
+            get(todo_socket_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/overdue",
+            get(get_overdue_todos_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/trash",
+            get(get_trash_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/trash/:id",
+            delete(purge_todo_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/trash/:id/restore",
+            post(restore_todo_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
         )
         .route(
             "/api/todos/:id",
             get(get_todo_handler)
                 .delete(delete_todo_handler)
-                .patch(update_todo_handler),
+                .patch(update_todo_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/:id/subtasks",
+            get(get_subtasks_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/:id/history",
+            get(get_todo_history_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/:id/revert",
+            post(revert_todo_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/:id/move",
+            post(move_todo_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/:id/shares",
+            get(get_todo_shares_handler)
+                .post(share_todo_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/:id/occurrences",
+            get(get_occurrences_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/:id/skip",
+            post(skip_occurrence_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/todos/:id/end-series",
+            post(end_series_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/lists",
+            get(get_lists_handler)
+                .post(create_list_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/lists/:id",
+            get(get_list_handler)
+                .delete(delete_list_handler)
+                .patch(update_list_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/lists/:id/todos",
+            get(get_list_todos_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/lists/:id/shares",
+            get(get_list_shares_handler)
+                .post(share_list_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/shares/:id",
+            delete(delete_share_handler)
+                .patch(update_share_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/shared",
+            get(get_shared_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/invitations",
+            get(get_invitations_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/invitations/:id/accept",
+            post(accept_invitation_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/invitations/:id/decline",
+            post(decline_invitation_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/tags",
+            get(get_tags_handler)
+                .post(create_tag_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/tags/:id",
+            get(get_tag_handler)
+                .delete(delete_tag_handler)
+                .patch(update_tag_handler)
+                .route_layer(middleware::from_fn(todo_scope))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
         )
         .route("/auth/signin", post(signin_handler))
         .route("/auth/signup", post(signup_handler))
+        .route("/auth/verify-mail", get(verify_mail_handler))
+        .route("/auth/oidc/:provider", get(oidc_login_handler))
+        .route("/auth/oidc/:provider/callback", get(oidc_callback_handler))
         .route(
             "/api/auth/logout",
             get(logout_handler)
@@ -40,7 +272,163 @@ pub fn router(app_state: Arc<AppState>) -> Router {
         .route(
             "/api/users/me",
             get(get_me_handler)
+                .merge(
+                    patch(update_me_handler)
+                        .delete(delete_me_handler)
+                        .route_layer(middleware::from_fn(session_only)),
+                )
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/users/me/password",
+            post(change_password_handler)
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/users/me/mail",
+            post(change_mail_handler)
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/users/me/avatar",
+            post(upload_avatar_handler)
+                .delete(delete_avatar_handler)
+                .layer(DefaultBodyLimit::max(app_state.env.avatar_max_bytes))
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/users/me/tokens",
+            get(get_tokens_handler)
+                .post(create_token_handler)
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/users/me/tokens/:id",
+            delete(delete_token_handler)
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/users/me/calendar-feeds",
+            get(get_calendar_feeds_handler)
+                .post(create_calendar_feed_handler)
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/users/me/calendar-feeds/:id",
+            delete(delete_calendar_feed_handler)
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/users/me/calendar-feeds/:id/token",
+            post(rotate_calendar_feed_handler)
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route("/api/calendar/:token", get(calendar_feed_handler))
+        .route("/.well-known/caldav", any(caldav_redirect_handler))
+        .route(
+            "/dav",
+            any(dav_root_handler).layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                basic_auth,
+            )),
+        )
+        .route(
+            "/dav/",
+            any(dav_root_handler).layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                basic_auth,
+            )),
+        )
+        .route(
+            "/dav/*path",
+            any(dav_handler).layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                basic_auth,
+            )),
+        )
+        .route(
+            "/graphql",
+            post(graphql_handler)
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/graphql/ws",
+            get(graphql_socket_handler)
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/webhooks",
+            get(get_webhooks_handler)
+                .post(create_webhook_handler)
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/webhooks/:id",
+            get(get_webhook_handler)
+                .patch(update_webhook_handler)
+                .delete(delete_webhook_handler)
+                .route_layer(middleware::from_fn(session_only))
                 .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
         )
-        .with_state(app_state);
+        .route(
+            "/api/webhooks/:id/ping",
+            post(ping_webhook_handler)
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/webhooks/:id/deliveries",
+            get(get_deliveries_handler)
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/webhooks/:id/deliveries/:delivery_id/redeliver",
+            post(redeliver_handler)
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/admin/jobs",
+            get(get_jobs_handler)
+                .route_layer(middleware::from_fn(admin_only))
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/admin/jobs/:id",
+            get(get_job_handler)
+                .route_layer(middleware::from_fn(admin_only))
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        )
+        .route(
+            "/api/admin/jobs/:id/retry",
+            post(retry_job_handler)
+                .route_layer(middleware::from_fn(admin_only))
+                .route_layer(middleware::from_fn(session_only))
+                .route_layer(middleware::from_fn_with_state(app_state.clone(), auth)),
+        );
+
+    // Uploaded file names are never reused, so they can be cached forever.
+    if let StorageConfig::Local { dir } = &app_state.env.storage {
+        router = router.nest_service(
+            "/uploads",
+            get_service(ServeDir::new(dir)).layer(SetResponseHeaderLayer::overriding(
+                header::CACHE_CONTROL,
+                HeaderValue::from_static("public, max-age=31536000, immutable"),
+            )),
+        );
+    }
+
+    router.with_state(app_state)
 }
@@ -1,10 +1,60 @@
-use crate::model::{ToDoModel, UserModel};
-use serde::{Deserialize, Serialize};
+use crate::model::{
+    CalendarFeedModel, JobModel, JobStatus, ListModel, PersonalTokenModel, Priority, ShareModel,
+    ShareRole, TagModel, ToDoModel, TodoEventModel, UserModel, WebhookDeliveryModel, WebhookModel,
+};
+use crate::transfer::Format;
+use chrono::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Deserialize, Debug, Default)]
 pub struct FilterOptions {
     pub page: Option<usize>,
     pub limit: Option<usize>,
+    pub complete: Option<bool>,
+    pub priority: Option<Priority>,
+    #[serde(rename = "dueBefore")]
+    pub due_before: Option<DateTime<Utc>>,
+    #[serde(rename = "dueAfter")]
+    pub due_after: Option<DateTime<Utc>>,
+    /// Only open todos whose due date has passed.
+    pub overdue: Option<bool>,
+    #[serde(rename = "listId")]
+    pub list_id: Option<uuid::Uuid>,
+    /// Direct subtasks of this todo.
+    #[serde(rename = "parentId")]
+    pub parent_id: Option<uuid::Uuid>,
+    /// Comma separated tag ids.
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub tags: Option<Vec<uuid::Uuid>>,
+    /// Whether todos need any or all of `tags`.
+    #[serde(rename = "tagMode")]
+    pub tag_mode: Option<TagMode>,
+    /// A field such as `dueAt`, prefixed with `-` for descending order.
+    /// `position` is the manual order.
+    pub sort: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMode {
+    #[default]
+    Any,
+    All,
+}
+
+fn comma_separated<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    String::deserialize(deserializer)?
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse().map_err(serde::de::Error::custom))
+        .collect::<Result<_, _>>()
+        .map(Some)
 }
 
 #[derive(Deserialize, Debug)]
@@ -18,13 +68,99 @@ pub struct CreateToDo {
     pub content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub complete: Option<bool>,
+    #[serde(rename = "dueAt")]
+    pub due_at: Option<DateTime<Utc>>,
+    pub priority: Option<Priority>,
+    /// iCalendar RRULE, e.g. `FREQ=WEEKLY;BYDAY=MO`. Needs a due date.
+    pub rrule: Option<String>,
+    /// IANA time zone the rule is expanded in, `UTC` by default.
+    pub timezone: Option<String>,
+    #[serde(rename = "listId")]
+    pub list_id: Option<uuid::Uuid>,
+    /// Tag ids.
+    pub tags: Option<Vec<uuid::Uuid>>,
+    /// Makes the todo a subtask.
+    #[serde(rename = "parentId")]
+    pub parent_id: Option<uuid::Uuid>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct UpdateToDo {
     pub title: Option<String>,
     pub content: Option<String>,
     pub complete: Option<bool>,
+    /// `Some(None)` clears the due date.
+    #[serde(default, rename = "dueAt", deserialize_with = "nullable")]
+    pub due_at: Option<Option<DateTime<Utc>>>,
+    pub priority: Option<Priority>,
+    /// `Some(None)` ends the series.
+    #[serde(default, deserialize_with = "nullable")]
+    pub rrule: Option<Option<String>>,
+    pub timezone: Option<String>,
+    /// Moves the todo to another list, `Some(None)` takes it out of its list.
+    #[serde(default, rename = "listId", deserialize_with = "nullable")]
+    pub list_id: Option<Option<uuid::Uuid>>,
+    /// Tag ids replacing the current tags.
+    pub tags: Option<Vec<uuid::Uuid>>,
+    /// Moves the todo below another one, `Some(None)` makes it a top level
+    /// todo.
+    #[serde(default, rename = "parentId", deserialize_with = "nullable")]
+    pub parent_id: Option<Option<uuid::Uuid>>,
+    /// Expected current version, an alternative to `If-Match`.
+    pub version: Option<i32>,
+}
+
+/// Tells an explicit `null` (`Some(None)`) apart from a missing field (`None`).
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Where to put a todo in the manual order. Without `before` it goes right
+/// after `after`, without `after` right before `before`.
+#[derive(Deserialize, Debug)]
+pub struct MoveToDo {
+    pub after: Option<uuid::Uuid>,
+    pub before: Option<uuid::Uuid>,
+    /// Expected current version, an alternative to `If-Match`.
+    pub version: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RevertToDo {
+    /// Version whose fields to restore.
+    pub revision: i32,
+    /// Expected current version, an alternative to `If-Match`.
+    pub version: Option<i32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct OccurrenceOptions {
+    pub count: Option<usize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OccurrenceListResponse {
+    pub status: String,
+    pub results: usize,
+    pub data: Vec<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct DeleteOptions {
+    pub version: Option<i32>,
+    /// Trash the subtasks along with the todo, the default. Otherwise they
+    /// move up to the todo's parent.
+    pub cascade: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct UpdateOptions {
+    /// Completing a todo also completes its open subtasks.
+    pub cascade: Option<bool>,
 }
 
 #[derive(Serialize)]
@@ -39,6 +175,27 @@ pub struct ToDoSingleResponse {
     pub data: ToDoModel,
 }
 
+/// A todo with its subtasks and how many of those, at any depth, are done.
+#[derive(Serialize, Debug)]
+pub struct ToDoTree {
+    #[serde(flatten)]
+    pub todo: ToDoModel,
+    #[serde(rename = "subtaskCount")]
+    pub subtask_count: usize,
+    #[serde(rename = "completedSubtaskCount")]
+    pub completed_subtask_count: usize,
+    /// Completed subtasks in percent. Without subtasks it is 0 or 100
+    /// depending on the todo itself.
+    pub progress: f64,
+    pub subtasks: Vec<ToDoTree>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ToDoTreeResponse {
+    pub status: String,
+    pub data: ToDoTree,
+}
+
 #[derive(Serialize, Debug)]
 pub struct ToDoListResponse {
     pub status: String,
@@ -51,6 +208,9 @@ pub struct JWT {
     pub sub: String,
     pub iat: usize,
     pub exp: usize,
+    /// The login provider signed in through, none for a password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -78,3 +238,395 @@ pub struct UserListResponse {
     pub results: usize,
     pub data: Vec<UserModel>,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct CreateToken {
+    pub name: String,
+    pub scopes: Vec<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TokenCreatedResponse {
+    pub status: String,
+    pub token: String,
+    pub data: PersonalTokenModel,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TokenListResponse {
+    pub status: String,
+    pub results: usize,
+    pub data: Vec<PersonalTokenModel>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUser {
+    pub name: Option<String>,
+    pub photo: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePassword {
+    #[serde(rename = "currentPassword")]
+    pub current_password: Option<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangeMail {
+    pub mail: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyMail {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccount {
+    pub password: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TodoEventListResponse {
+    pub status: String,
+    pub results: usize,
+    pub data: Vec<TodoEventModel>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateList {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateList {
+    pub name: Option<String>,
+    pub archived: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ListOptions {
+    /// Archived lists instead of the active ones.
+    pub archived: Option<bool>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ListSingleResponse {
+    pub status: String,
+    pub data: ListModel,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ListListResponse {
+    pub status: String,
+    pub results: usize,
+    pub data: Vec<ListModel>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTag {
+    pub name: String,
+    /// `#rrggbb`
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateTag {
+    pub name: Option<String>,
+    /// `Some(None)` removes the color.
+    #[serde(default, deserialize_with = "nullable")]
+    pub color: Option<Option<String>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TagSingleResponse {
+    pub status: String,
+    pub data: TagModel,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TagListResponse {
+    pub status: String,
+    pub results: usize,
+    pub data: Vec<TagModel>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateShare {
+    pub mail: String,
+    /// `viewer` unless given.
+    pub role: Option<ShareRole>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateShare {
+    pub role: ShareRole,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ShareSingleResponse {
+    pub status: String,
+    pub data: ShareModel,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ShareListResponse {
+    pub status: String,
+    pub results: usize,
+    pub data: Vec<ShareModel>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct StreamOptions {
+    /// Position of the last event the client received, to resume after it.
+    #[serde(rename = "lastEventId")]
+    pub last_event_id: Option<i64>,
+    /// Only events about todos in this list.
+    #[serde(rename = "listId")]
+    pub list_id: Option<uuid::Uuid>,
+    /// Only events about todos with one of these comma separated tag ids.
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub tags: Option<Vec<uuid::Uuid>>,
+}
+
+/// What the server pushes to clients following todo changes.
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum StreamMessage<'a> {
+    Event {
+        position: i64,
+        data: &'a TodoEventModel,
+    },
+    /// More happened than can be replayed. Clients refetch what they show
+    /// and resume after `position`.
+    Reset { position: i64 },
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BatchOperation {
+    Create(CreateToDo),
+    Update {
+        id: uuid::Uuid,
+        /// As in `UpdateOptions`.
+        cascade: Option<bool>,
+        #[serde(flatten)]
+        changes: UpdateToDo,
+    },
+    Delete {
+        id: uuid::Uuid,
+        version: Option<i32>,
+        /// As in `DeleteOptions`.
+        cascade: Option<bool>,
+    },
+}
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+    /// Roll everything back if any operation fails.
+    #[default]
+    Atomic,
+    /// Keep the operations that succeeded.
+    BestEffort,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BatchRequest {
+    #[serde(default)]
+    pub mode: BatchMode,
+    pub operations: Vec<BatchOperation>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BatchResult {
+    pub index: usize,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<ToDoModel>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BatchResponse {
+    pub status: String,
+    pub results: usize,
+    pub data: Vec<BatchResult>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ExportOptions {
+    /// `json` by default.
+    pub format: Option<Format>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ImportOptions {
+    /// Taken from the `Content-Type` if missing.
+    pub format: Option<Format>,
+    /// Reports what would happen without keeping anything.
+    #[serde(rename = "dryRun")]
+    pub dry_run: Option<bool>,
+    #[serde(default, rename = "onDuplicate")]
+    pub on_duplicate: DuplicateStrategy,
+}
+
+/// What to do with a todo whose title one of the user's todos has already.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateStrategy {
+    /// Leave the existing todo alone.
+    #[default]
+    Skip,
+    /// Import under the first free title of `Title (2)`, `Title (3)`, …
+    Rename,
+    /// Replace the fields of the existing todo.
+    Overwrite,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportAction {
+    Created,
+    Renamed,
+    Overwritten,
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportResult {
+    pub row: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub action: ImportAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<uuid::Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportResponse {
+    pub status: String,
+    #[serde(rename = "dryRun")]
+    pub dry_run: bool,
+    pub created: usize,
+    pub renamed: usize,
+    pub overwritten: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub data: Vec<ImportResult>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhook {
+    pub url: String,
+    pub events: Vec<String>,
+    /// Hear about all users instead of just the caller, admins only.
+    #[serde(default)]
+    pub global: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhook {
+    pub url: Option<String>,
+    pub events: Option<Vec<String>>,
+    pub active: Option<bool>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WebhookCreatedResponse {
+    pub status: String,
+    pub secret: String,
+    pub data: WebhookModel,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WebhookSingleResponse {
+    pub status: String,
+    pub data: WebhookModel,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WebhookListResponse {
+    pub status: String,
+    pub results: usize,
+    pub data: Vec<WebhookModel>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WebhookDeliverySingleResponse {
+    pub status: String,
+    pub data: WebhookDeliveryModel,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WebhookDeliveryListResponse {
+    pub status: String,
+    pub results: usize,
+    pub data: Vec<WebhookDeliveryModel>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct JobListOptions {
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+    pub status: Option<JobStatus>,
+    pub kind: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct JobSingleResponse {
+    pub status: String,
+    pub data: JobModel,
+}
+
+#[derive(Serialize, Debug)]
+pub struct JobListResponse {
+    pub status: String,
+    pub results: usize,
+    pub data: Vec<JobModel>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct CalendarOptions {
+    /// Only the todos in this list.
+    #[serde(rename = "listId")]
+    pub list_id: Option<uuid::Uuid>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct CreateCalendarFeed {
+    /// Only the todos in this list, all of the user's todos without.
+    #[serde(rename = "listId")]
+    pub list_id: Option<uuid::Uuid>,
+}
+
+/// The token and URL are only ever shown here.
+#[derive(Serialize, Debug)]
+pub struct CalendarFeedCreatedResponse {
+    pub status: String,
+    pub token: String,
+    pub url: String,
+    pub data: CalendarFeedModel,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CalendarFeedListResponse {
+    pub status: String,
+    pub results: usize,
+    pub data: Vec<CalendarFeedModel>,
+}
@@ -0,0 +1,82 @@
+use crate::model::CalendarFeedModel;
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+pub async fn list<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+) -> Result<Vec<CalendarFeedModel>, sqlx::Error> {
+    sqlx::query_as!(
+        CalendarFeedModel,
+        "SELECT id, user_id, list_id, last_polled_at, created_at, updated_at FROM calendar_feeds WHERE user_id = $1 ORDER BY created_at DESC",
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn create<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    list_id: Option<Uuid>,
+    token_hash: &str,
+) -> Result<CalendarFeedModel, sqlx::Error> {
+    sqlx::query_as!(
+        CalendarFeedModel,
+        "INSERT INTO calendar_feeds (user_id,list_id,token_hash) VALUES ($1, $2, $3) RETURNING id, user_id, list_id, last_polled_at, created_at, updated_at",
+        user_id,
+        list_id,
+        token_hash
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Replaces the feed's token, so the old URL stops working. Returns `None` if
+/// the user has no feed with that id.
+pub async fn rotate<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    id: Uuid,
+    token_hash: &str,
+) -> Result<Option<CalendarFeedModel>, sqlx::Error> {
+    sqlx::query_as!(
+        CalendarFeedModel,
+        "UPDATE calendar_feeds SET token_hash = $1, last_polled_at = NULL, updated_at = NOW() WHERE id = $2 AND user_id = $3 RETURNING id, user_id, list_id, last_polled_at, created_at, updated_at",
+        token_hash,
+        id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+pub async fn delete<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let deleted = sqlx::query!(
+        "DELETE FROM calendar_feeds WHERE id = $1 AND user_id = $2",
+        id,
+        user_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(deleted.rows_affected() > 0)
+}
+
+/// The feed with that token, noting that it was polled.
+pub async fn poll<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    token_hash: &str,
+) -> Result<Option<CalendarFeedModel>, sqlx::Error> {
+    sqlx::query_as!(
+        CalendarFeedModel,
+        "UPDATE calendar_feeds SET last_polled_at = NOW() WHERE token_hash = $1 RETURNING id, user_id, list_id, last_polled_at, created_at, updated_at",
+        token_hash
+    )
+    .fetch_optional(executor)
+    .await
+}
@@ -0,0 +1,181 @@
+//! Todos as CalDAV objects. A todo created over CalDAV keeps the name and
+//! UID its client chose, any other todo is `<id>.ics` with its id as UID.
+
+use crate::model::ToDoModel;
+use sqlx::{Executor, PgConnection, Postgres};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub struct Object {
+    pub todo: ToDoModel,
+    pub name: String,
+    pub uid: String,
+    /// The UID of the todo's parent, for RELATED-TO.
+    pub parent_uid: Option<String>,
+}
+
+/// How a todo is named and identified, given its row in `dav_objects`.
+fn label(todo_id: Uuid, row: Option<(String, String)>) -> (String, String) {
+    row.unwrap_or_else(|| (format!("{}.ics", todo_id), todo_id.to_string()))
+}
+
+/// All of the user's todos outside the trash.
+pub async fn objects(conn: &mut PgConnection, user_id: Uuid) -> Result<Vec<Object>, sqlx::Error> {
+    let rows: HashMap<Uuid, (String, String)> = sqlx::query!(
+        "SELECT todo_id, name, uid FROM dav_objects WHERE user_id = $1",
+        user_id
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|row| (row.todo_id, (row.name, row.uid)))
+    .collect();
+
+    let todos = super::todo::calendar(conn, user_id, None).await?;
+    Ok(todos
+        .into_iter()
+        .map(|todo| {
+            let (name, uid) = label(todo.id, rows.get(&todo.id).cloned());
+            let parent_uid = todo
+                .parent_id
+                .map(|parent_id| label(parent_id, rows.get(&parent_id).cloned()).1);
+            Object {
+                todo,
+                name,
+                uid,
+                parent_uid,
+            }
+        })
+        .collect())
+}
+
+/// The todo a name stands for, whether or not it still exists.
+pub async fn resolve<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    name: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let todo_id = sqlx::query_scalar!(
+        "SELECT todo_id FROM dav_objects WHERE user_id = $1 AND name = $2",
+        user_id,
+        name
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(todo_id.or_else(|| {
+        name.strip_suffix(".ics")
+            .and_then(|id| Uuid::parse_str(id).ok())
+    }))
+}
+
+/// The object with that name, `None` if its todo is gone or in the trash.
+pub async fn find(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    name: &str,
+) -> Result<Option<Object>, sqlx::Error> {
+    let Some(todo_id) = resolve(&mut *conn, user_id, name).await? else {
+        return Ok(None);
+    };
+    let Some(todo) = super::todo::find(&mut *conn, user_id, todo_id).await? else {
+        return Ok(None);
+    };
+    let row = sqlx::query!(
+        "SELECT name, uid FROM dav_objects WHERE todo_id = $1",
+        todo_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .map(|row| (row.name, row.uid));
+
+    let parent_uid = match todo.parent_id {
+        Some(parent_id) => {
+            let uid =
+                sqlx::query_scalar!("SELECT uid FROM dav_objects WHERE todo_id = $1", parent_id)
+                    .fetch_optional(&mut *conn)
+                    .await?;
+            Some(uid.unwrap_or_else(|| label(parent_id, None).1))
+        }
+        None => None,
+    };
+
+    let (name, uid) = label(todo_id, row);
+    Ok(Some(Object {
+        todo,
+        name,
+        uid,
+        parent_uid,
+    }))
+}
+
+/// Names of the todos, alive or not.
+pub async fn names<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    todo_ids: &[Uuid],
+) -> Result<HashMap<Uuid, String>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT todo_id, name FROM dav_objects WHERE todo_id = ANY($1)",
+        todo_ids
+    )
+    .fetch_all(executor)
+    .await?;
+
+    let mut names: HashMap<Uuid, String> = rows
+        .into_iter()
+        .map(|row| (row.todo_id, row.name))
+        .collect();
+    for todo_id in todo_ids {
+        names
+            .entry(*todo_id)
+            .or_insert_with(|| label(*todo_id, None).0);
+    }
+    Ok(names)
+}
+
+/// The user's todo with that UID, whether or not it still exists.
+pub async fn find_by_uid<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    uid: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let todo_id = sqlx::query_scalar!(
+        "SELECT todo_id FROM dav_objects WHERE user_id = $1 AND uid = $2",
+        user_id,
+        uid
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(todo_id.or_else(|| Uuid::parse_str(uid).ok()))
+}
+
+/// Gives the todo the name and UID a client chose, taking the name over
+/// from any todo that had it before.
+pub async fn bind(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    todo_id: Uuid,
+    name: &str,
+    uid: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM dav_objects WHERE user_id = $1 AND name = $2 AND todo_id <> $3",
+        user_id,
+        name,
+        todo_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO dav_objects (todo_id,user_id,name,uid) VALUES ($1, $2, $3, $4) ON CONFLICT (todo_id) DO UPDATE SET name = EXCLUDED.name, uid = EXCLUDED.uid",
+        todo_id,
+        user_id,
+        name,
+        uid
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
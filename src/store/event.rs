@@ -0,0 +1,213 @@
+use super::webhook;
+use crate::{
+    events::TODO_CHANNEL,
+    model::{ToDoModel, TodoEventModel},
+};
+use serde_json::{json, Map, Value};
+use sqlx::{Executor, PgConnection, Postgres};
+use uuid::Uuid;
+
+/// Fields covered by the field-level diff of an event.
+const TRACKED_FIELDS: [&str; 10] = [
+    "title", "content", "complete", "dueAt", "priority", "rrule", "timezone", "listId", "tags",
+    "parentId",
+];
+
+fn tracked_fields(todo: &ToDoModel) -> [Value; 10] {
+    [
+        json!(todo.title),
+        json!(todo.content),
+        json!(todo.complete.unwrap_or_default()),
+        json!(todo.due_at),
+        json!(todo.priority),
+        json!(todo.rrule),
+        json!(todo.timezone),
+        json!(todo.list_id),
+        json!(super::tag::ids(todo)),
+        json!(todo.parent_id),
+    ]
+}
+
+/// `{"field": {"from": .., "to": ..}}` for every tracked field that differs.
+/// Without `before` every field counts as changed from `null`.
+pub fn diff(before: Option<&ToDoModel>, after: &ToDoModel) -> Map<String, Value> {
+    let from = before.map(tracked_fields);
+    let to = tracked_fields(after);
+
+    TRACKED_FIELDS
+        .iter()
+        .enumerate()
+        .filter_map(|(i, field)| {
+            let from = from.as_ref().map_or(Value::Null, |from| from[i].clone());
+            (from != to[i]).then(|| (field.to_string(), json!({ "from": from, "to": to[i] })))
+        })
+        .collect()
+}
+
+/// Appends an event for the revision of `todo` that a write just produced,
+/// queues it for the webhooks subscribed to it and announces it on
+/// `events::TODO_CHANNEL` for when the write commits. The event gets its
+/// position when the write commits.
+pub async fn record(
+    conn: &mut PgConnection,
+    actor_id: Uuid,
+    kind: &str,
+    todo: &ToDoModel,
+    changes: Map<String, Value>,
+) -> Result<(), sqlx::Error> {
+    let event = sqlx::query_as!(
+        TodoEventModel,
+        "INSERT INTO todo_events (todo_id,user_id,actor_id,kind,version,changes) VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+        todo.id,
+        todo.user_id,
+        actor_id,
+        kind,
+        todo.version,
+        Value::Object(changes),
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let webhook_event = webhook::todo_event(kind);
+    let payload = crate::webhook::payload(webhook_event, json!({ "event": event, "todo": todo }));
+    webhook::enqueue(&mut *conn, todo.user_id, webhook_event, &payload).await?;
+
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(TODO_CHANNEL)
+        .bind(event.id.to_string())
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn find<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    id: i64,
+) -> Result<Option<TodoEventModel>, sqlx::Error> {
+    sqlx::query_as!(
+        TodoEventModel,
+        "SELECT * FROM todo_events WHERE id = $1",
+        id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+pub async fn history<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    todo_id: Uuid,
+) -> Result<Vec<TodoEventModel>, sqlx::Error> {
+    sqlx::query_as!(
+        TodoEventModel,
+        "SELECT * FROM todo_events WHERE todo_id = $1 AND user_id = $2 ORDER BY version",
+        todo_id,
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// The `history` of each of the todos, one todo after the other.
+pub async fn histories<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    todo_ids: &[Uuid],
+) -> Result<Vec<TodoEventModel>, sqlx::Error> {
+    sqlx::query_as!(
+        TodoEventModel,
+        "SELECT * FROM todo_events WHERE todo_id = ANY($1) AND (todo_id, user_id) IN (SELECT id, user_id FROM todos WHERE id = ANY($1)) ORDER BY todo_id, version",
+        todo_ids
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Events that produced revisions after `version`, newest first.
+pub async fn since<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    todo_id: Uuid,
+    version: i32,
+) -> Result<Vec<TodoEventModel>, sqlx::Error> {
+    sqlx::query_as!(
+        TodoEventModel,
+        "SELECT * FROM todo_events WHERE todo_id = $1 AND version > $2 ORDER BY version DESC",
+        todo_id,
+        version
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Position of the newest event, 0 without any.
+pub async fn latest_position<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!("SELECT COALESCE(MAX(position), 0) AS \"position!\" FROM todo_events")
+        .fetch_one(executor)
+        .await
+}
+
+/// Position of the newest event about the user's own todos, 0 without any.
+pub async fn latest_position_of<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT COALESCE(MAX(position), 0) AS \"position!\" FROM todo_events WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// The user's own todos with events after position `after`.
+pub async fn changed_after<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    after: i64,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT DISTINCT todo_id FROM todo_events WHERE user_id = $1 AND position > $2",
+        user_id,
+        after
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Events of all users after position `after`, oldest first.
+pub async fn after<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    after: i64,
+    limit: i64,
+) -> Result<Vec<TodoEventModel>, sqlx::Error> {
+    sqlx::query_as!(
+        TodoEventModel,
+        "SELECT * FROM todo_events WHERE position > $1 ORDER BY position LIMIT $2",
+        after,
+        limit
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Events after position `after` about todos the user can see, their own
+/// and those shared with them directly, through a list or through a todo
+/// they are a subtask of. Oldest first.
+pub async fn feed<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    after: i64,
+    limit: i64,
+) -> Result<Vec<TodoEventModel>, sqlx::Error> {
+    sqlx::query_as!(
+        TodoEventModel,
+        "WITH RECURSIVE shared AS (SELECT todos.id FROM todos JOIN shares ON shares.todo_id = todos.id OR shares.list_id = todos.list_id WHERE shares.user_id = $1 AND shares.status = 'accepted' UNION SELECT todos.id FROM todos JOIN shared ON todos.parent_id = shared.id) SELECT * FROM todo_events WHERE position > $2 AND (user_id = $1 OR todo_id IN (SELECT id FROM shared)) ORDER BY position LIMIT $3",
+        user_id,
+        after,
+        limit
+    )
+    .fetch_all(executor)
+    .await
+}
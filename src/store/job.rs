@@ -0,0 +1,156 @@
+use crate::model::{JobModel, JobStatus};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+/// A job to queue.
+pub struct NewJob<'a> {
+    pub kind: &'a str,
+    pub payload: Value,
+    pub max_attempts: i32,
+    pub run_at: DateTime<Utc>,
+    /// Queues nothing if a job with the same key exists already.
+    pub unique_key: Option<String>,
+}
+
+/// Queues a job. `None` if its unique key is taken.
+pub async fn enqueue<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    job: NewJob<'_>,
+) -> Result<Option<JobModel>, sqlx::Error> {
+    sqlx::query_as!(
+        JobModel,
+        r#"INSERT INTO jobs (kind,payload,max_attempts,run_at,unique_key) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (unique_key) DO NOTHING RETURNING id, kind, payload, status AS "status: _", attempts, max_attempts, run_at, locked_until, last_error, unique_key, created_at, updated_at, finished_at, lock_token"#,
+        job.kind,
+        job.payload,
+        job.max_attempts,
+        job.run_at,
+        job.unique_key
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Takes up to `limit` jobs that are due, or were running on an instance
+/// that let their lock expire, and locks them for `lease` under a new
+/// `lock_token`. Counts as an attempt. Jobs whose lock expired on their last
+/// attempt are marked dead instead.
+pub async fn claim<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    limit: i64,
+    lease: chrono::Duration,
+) -> Result<Vec<JobModel>, sqlx::Error> {
+    sqlx::query_as!(
+        JobModel,
+        r#"WITH expired AS (UPDATE jobs SET status = 'dead', locked_until = NULL, lock_token = NULL, last_error = 'The lock expired on the last attempt', updated_at = NOW(), finished_at = NOW() WHERE id IN (SELECT id FROM jobs WHERE status = 'running' AND locked_until < NOW() AND attempts >= max_attempts FOR UPDATE SKIP LOCKED)) UPDATE jobs SET status = 'running', attempts = attempts + 1, locked_until = NOW() + $2 * INTERVAL '1 second', lock_token = uuid_generate_v4(), updated_at = NOW() WHERE id IN (SELECT id FROM jobs WHERE (status = 'queued' AND run_at <= NOW()) OR (status = 'running' AND locked_until < NOW() AND attempts < max_attempts) ORDER BY run_at LIMIT $1 FOR UPDATE SKIP LOCKED) RETURNING id, kind, payload, status AS "status: _", attempts, max_attempts, run_at, locked_until, last_error, unique_key, created_at, updated_at, finished_at, lock_token"#,
+        limit,
+        lease.num_seconds() as f64
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Marks a job claimed under `lock_token` as succeeded. `false` if the lock
+/// was lost to another claim in the meantime.
+pub async fn succeed<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    id: Uuid,
+    lock_token: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE jobs SET status = 'succeeded', locked_until = NULL, lock_token = NULL, last_error = NULL, updated_at = NOW(), finished_at = NOW() WHERE id = $1 AND lock_token = $2 AND status = 'running'",
+        id,
+        lock_token
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Queues a failed job claimed under `lock_token` again for `retry_at`, or
+/// marks it dead without. `false` if the lock was lost to another claim in
+/// the meantime.
+pub async fn fail<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    id: Uuid,
+    lock_token: Uuid,
+    error: &str,
+    retry_at: Option<DateTime<Utc>>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE jobs SET status = CASE WHEN $4::TIMESTAMPTZ IS NULL THEN 'dead' ELSE 'queued' END::job_status, run_at = COALESCE($4, run_at), locked_until = NULL, lock_token = NULL, last_error = $3, updated_at = NOW(), finished_at = CASE WHEN $4::TIMESTAMPTZ IS NULL THEN NOW() END WHERE id = $1 AND lock_token = $2 AND status = 'running'",
+        id,
+        lock_token,
+        error,
+        retry_at
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Jobs, most recently changed first.
+pub async fn list<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    status: Option<JobStatus>,
+    kind: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<JobModel>, sqlx::Error> {
+    sqlx::query_as!(
+        JobModel,
+        r#"SELECT id, kind, payload, status AS "status: _", attempts, max_attempts, run_at, locked_until, last_error, unique_key, created_at, updated_at, finished_at, lock_token FROM jobs WHERE ($1::job_status IS NULL OR status = $1) AND ($2::VARCHAR IS NULL OR kind = $2) ORDER BY updated_at DESC LIMIT $3 OFFSET $4"#,
+        status as Option<JobStatus>,
+        kind,
+        limit,
+        offset
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn find<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    id: Uuid,
+) -> Result<Option<JobModel>, sqlx::Error> {
+    sqlx::query_as!(
+        JobModel,
+        r#"SELECT id, kind, payload, status AS "status: _", attempts, max_attempts, run_at, locked_until, last_error, unique_key, created_at, updated_at, finished_at, lock_token FROM jobs WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Queues a dead job again with fresh attempts. `None` if there is no dead
+/// job with that id.
+pub async fn retry<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    id: Uuid,
+) -> Result<Option<JobModel>, sqlx::Error> {
+    sqlx::query_as!(
+        JobModel,
+        r#"UPDATE jobs SET status = 'queued', attempts = 0, run_at = NOW(), updated_at = NOW(), finished_at = NULL WHERE id = $1 AND status = 'dead' RETURNING id, kind, payload, status AS "status: _", attempts, max_attempts, run_at, locked_until, last_error, unique_key, created_at, updated_at, finished_at, lock_token"#,
+        id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Deletes jobs that succeeded before `before`.
+pub async fn prune<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    before: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM jobs WHERE status = 'succeeded' AND finished_at < $1",
+        before
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,142 @@
+use super::event;
+use crate::{
+    model::{ListModel, Priority, TagModel, ToDoModel},
+    schema::{CreateList, UpdateList},
+};
+use serde_json::{json, Map};
+use sqlx::{types::Json, Connection, Executor, PgConnection, Postgres};
+use uuid::Uuid;
+
+/// The user's active lists, or the archived ones, by name. Lists come with
+/// the counts of their todos, leaving out todos in the trash.
+pub async fn list<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    archived: bool,
+) -> Result<Vec<ListModel>, sqlx::Error> {
+    sqlx::query_as!(
+        ListModel,
+        r#"SELECT lists.id, lists.user_id, lists.name, lists.archived_at, lists.created_at, lists.updated_at, COUNT(todos.id) AS "todo_count!", COUNT(todos.id) FILTER (WHERE todos.complete) AS "completed_count!", COALESCE(ROUND(100.0 * COUNT(todos.id) FILTER (WHERE todos.complete) / NULLIF(COUNT(todos.id), 0), 1), 0)::FLOAT8 AS "completion!" FROM lists LEFT JOIN todos ON todos.list_id = lists.id AND todos.deleted_at IS NULL WHERE lists.user_id = $1 AND (lists.archived_at IS NOT NULL) = $2 GROUP BY lists.id ORDER BY lists.name"#,
+        user_id,
+        archived
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn find<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<Option<ListModel>, sqlx::Error> {
+    sqlx::query_as!(
+        ListModel,
+        r#"SELECT lists.id, lists.user_id, lists.name, lists.archived_at, lists.created_at, lists.updated_at, COUNT(todos.id) AS "todo_count!", COUNT(todos.id) FILTER (WHERE todos.complete) AS "completed_count!", COALESCE(ROUND(100.0 * COUNT(todos.id) FILTER (WHERE todos.complete) / NULLIF(COUNT(todos.id), 0), 1), 0)::FLOAT8 AS "completion!" FROM lists LEFT JOIN todos ON todos.list_id = lists.id AND todos.deleted_at IS NULL WHERE lists.id = $1 AND lists.user_id = $2 GROUP BY lists.id"#,
+        id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Lists by id, whoever owns them.
+pub async fn find_many<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    ids: &[Uuid],
+) -> Result<Vec<ListModel>, sqlx::Error> {
+    sqlx::query_as!(
+        ListModel,
+        r#"SELECT lists.id, lists.user_id, lists.name, lists.archived_at, lists.created_at, lists.updated_at, COUNT(todos.id) AS "todo_count!", COUNT(todos.id) FILTER (WHERE todos.complete) AS "completed_count!", COALESCE(ROUND(100.0 * COUNT(todos.id) FILTER (WHERE todos.complete) / NULLIF(COUNT(todos.id), 0), 1), 0)::FLOAT8 AS "completion!" FROM lists LEFT JOIN todos ON todos.list_id = lists.id AND todos.deleted_at IS NULL WHERE lists.id = ANY($1) GROUP BY lists.id"#,
+        ids
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn create(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    list: &CreateList,
+) -> Result<ListModel, sqlx::Error> {
+    let id = sqlx::query_scalar!(
+        "INSERT INTO lists (user_id,name) VALUES ($1, $2) RETURNING id",
+        user_id,
+        list.name.trim()
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    find(conn, user_id, id)
+        .await?
+        .ok_or(sqlx::Error::RowNotFound)
+}
+
+/// Renames, archives or unarchives a list. Returns `None` if the user has no
+/// list with that id.
+pub async fn update(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    id: Uuid,
+    changes: &UpdateList,
+) -> Result<Option<ListModel>, sqlx::Error> {
+    let updated = sqlx::query!(
+        "UPDATE lists SET name = COALESCE($1, name), archived_at = CASE WHEN $2::BOOLEAN IS NULL THEN archived_at WHEN $2 THEN COALESCE(archived_at, NOW()) END, updated_at = NOW() WHERE id = $3 AND user_id = $4",
+        changes.name.as_deref().map(str::trim),
+        changes.archived,
+        id,
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+    find(conn, user_id, id).await
+}
+
+/// Deletes a list and returns whether there was one. Its todos, including
+/// those in the trash, are kept without a list.
+pub async fn delete(conn: &mut PgConnection, user_id: Uuid, id: Uuid) -> Result<bool, sqlx::Error> {
+    let mut tx = conn.begin().await?;
+
+    let todos = sqlx::query_as!(
+        ToDoModel,
+        r#"UPDATE todos SET list_id = NULL, updated_at = NOW(), version = version + 1 WHERE list_id = $1 AND user_id = $2 RETURNING id, title, content, complete, created_at, updated_at, user_id, version, deleted_at, due_at, priority AS "priority: Priority", completed_at, rrule, timezone, list_id, parent_id, position, '[]'::JSONB AS "tags!: Json<Vec<TagModel>>""#,
+        id,
+        user_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    for todo in &todos {
+        let mut changes = Map::new();
+        changes.insert("listId".to_string(), json!({ "from": id, "to": null }));
+        event::record(&mut tx, user_id, "moved", todo, changes).await?;
+    }
+
+    let deleted = sqlx::query!(
+        "DELETE FROM lists WHERE id = $1 AND user_id = $2",
+        id,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(deleted.rows_affected() > 0)
+}
+
+/// The id of the user's list with that name, created if there is none.
+pub async fn ensure<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    name: &str,
+) -> Result<Uuid, sqlx::Error> {
+    sqlx::query_scalar!(
+        "INSERT INTO lists (user_id,name) VALUES ($1, $2) ON CONFLICT (user_id, name) DO UPDATE SET name = EXCLUDED.name RETURNING id",
+        user_id,
+        name.trim()
+    )
+    .fetch_one(executor)
+    .await
+}
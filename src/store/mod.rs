@@ -0,0 +1,34 @@
+//! Data access shared by the REST handlers and batch operations.
+
+pub mod calendar;
+pub mod dav;
+pub mod event;
+pub mod job;
+pub mod list;
+pub mod share;
+pub mod tag;
+pub mod todo;
+pub mod webhook;
+
+/// Whether the error is a violated UNIQUE constraint, e.g. a duplicate title.
+pub fn is_unique_violation(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .and_then(|e| e.code())
+        .is_some_and(|code| code == "23505")
+}
+
+/// Whether the error is a violated CHECK constraint, e.g. a recurring todo
+/// without a due date.
+pub fn is_check_violation(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .and_then(|e| e.code())
+        .is_some_and(|code| code == "23514")
+}
+
+/// Whether the error is a violated FOREIGN KEY constraint, e.g. a todo put
+/// in a list that does not exist or belongs to someone else.
+pub fn is_foreign_key_violation(err: &sqlx::Error) -> bool {
+    err.as_database_error()
+        .and_then(|e| e.code())
+        .is_some_and(|code| code == "23503")
+}
@@ -0,0 +1,316 @@
+use crate::model::{ShareModel, ShareRole, ShareStatus};
+use sqlx::{Executor, PgConnection, Postgres};
+use uuid::Uuid;
+
+/// What a user may do with a todo or list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl From<ShareRole> for Role {
+    fn from(role: ShareRole) -> Self {
+        match role {
+            ShareRole::Viewer => Role::Viewer,
+            ShareRole::Editor => Role::Editor,
+        }
+    }
+}
+
+/// A user's access to a todo or list and whose it is.
+#[derive(Debug, Clone, Copy)]
+pub struct Access {
+    pub owner_id: Uuid,
+    pub role: Role,
+}
+
+/// What can be shared.
+#[derive(Debug, Clone, Copy)]
+pub enum Target {
+    Todo(Uuid),
+    List(Uuid),
+}
+
+struct Grant {
+    owner_id: Uuid,
+    /// `None` without an accepted share.
+    editor: Option<bool>,
+}
+
+impl Grant {
+    fn access(self, user_id: Uuid) -> Option<Access> {
+        let role = if self.owner_id == user_id {
+            Role::Owner
+        } else {
+            match self.editor? {
+                true => Role::Editor,
+                false => Role::Viewer,
+            }
+        };
+        Some(Access {
+            owner_id: self.owner_id,
+            role,
+        })
+    }
+}
+
+/// The user's access to a todo, which shares of its list or of the todos it
+/// is a subtask of grant as well. `None` if the user may not see it at all.
+/// Todos in the trash count.
+pub async fn todo_access<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    todo_id: Uuid,
+) -> Result<Option<Access>, sqlx::Error> {
+    let grant = sqlx::query_as!(
+        Grant,
+        "WITH RECURSIVE ancestors AS (SELECT id, parent_id, list_id FROM todos WHERE id = $1 UNION ALL SELECT todos.id, todos.parent_id, todos.list_id FROM todos JOIN ancestors ON todos.id = ancestors.parent_id) SELECT user_id AS \"owner_id!\", (SELECT BOOL_OR(role = 'editor') FROM shares WHERE user_id = $2 AND status = 'accepted' AND (todo_id IN (SELECT id FROM ancestors) OR list_id IN (SELECT list_id FROM ancestors))) AS editor FROM todos WHERE id = $1",
+        todo_id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(grant.and_then(|grant| grant.access(user_id)))
+}
+
+/// The user's access to a list. `None` if the user may not see it at all.
+pub async fn list_access<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    list_id: Uuid,
+) -> Result<Option<Access>, sqlx::Error> {
+    let grant = sqlx::query_as!(
+        Grant,
+        "SELECT user_id AS owner_id, (SELECT BOOL_OR(role = 'editor') FROM shares WHERE list_id = $1 AND user_id = $2 AND status = 'accepted') AS editor FROM lists WHERE id = $1",
+        list_id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(grant.and_then(|grant| grant.access(user_id)))
+}
+
+/// The user's access to each of the todos they may see, worked out like
+/// `todo_access` does for one.
+pub async fn todos_access<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    todo_ids: &[Uuid],
+) -> Result<Vec<(Uuid, Access)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "WITH RECURSIVE ancestors AS (SELECT id AS of, id, parent_id, list_id FROM todos WHERE id = ANY($1) UNION ALL SELECT ancestors.of, todos.id, todos.parent_id, todos.list_id FROM todos JOIN ancestors ON todos.id = ancestors.parent_id) SELECT id, user_id AS \"owner_id!\", (SELECT BOOL_OR(role = 'editor') FROM shares WHERE user_id = $2 AND status = 'accepted' AND (todo_id IN (SELECT id FROM ancestors WHERE of = todos.id) OR list_id IN (SELECT list_id FROM ancestors WHERE of = todos.id))) AS editor FROM todos WHERE id = ANY($1)",
+        todo_ids,
+        user_id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let grant = Grant {
+                owner_id: row.owner_id,
+                editor: row.editor,
+            };
+            Some((row.id, grant.access(user_id)?))
+        })
+        .collect())
+}
+
+/// The user's access to each of the lists they may see.
+pub async fn lists_access<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<Vec<(Uuid, Access)>, sqlx::Error> {
+    let rows = sqlx::query!(
+        "SELECT id, user_id AS owner_id, (SELECT BOOL_OR(role = 'editor') FROM shares WHERE list_id = lists.id AND user_id = $2 AND status = 'accepted') AS editor FROM lists WHERE id = ANY($1)",
+        list_ids,
+        user_id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            let grant = Grant {
+                owner_id: row.owner_id,
+                editor: row.editor,
+            };
+            Some((row.id, grant.access(user_id)?))
+        })
+        .collect())
+}
+
+/// Everyone a todo or list is shared with, including open and declined
+/// invitations. Shares come with the name of their owner and the title or
+/// name of what they share.
+pub async fn list<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    target: Target,
+) -> Result<Vec<ShareModel>, sqlx::Error> {
+    let (todo_id, list_id) = match target {
+        Target::Todo(id) => (Some(id), None),
+        Target::List(id) => (None, Some(id)),
+    };
+
+    sqlx::query_as!(
+        ShareModel,
+        r#"SELECT shares.id, shares.owner_id, users.name AS owner_name, shares.todo_id, shares.list_id, COALESCE(todos.title, lists.name) AS "subject!", shares.mail, shares.user_id, shares.role AS "role: _", shares.status AS "status: _", shares.created_at, shares.updated_at FROM shares JOIN users ON users.id = shares.owner_id LEFT JOIN todos ON todos.id = shares.todo_id LEFT JOIN lists ON lists.id = shares.list_id WHERE shares.todo_id = $1 OR shares.list_id = $2 ORDER BY shares.created_at"#,
+        todo_id,
+        list_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn find<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    id: Uuid,
+) -> Result<Option<ShareModel>, sqlx::Error> {
+    sqlx::query_as!(ShareModel, r#"SELECT shares.id, shares.owner_id, users.name AS owner_name, shares.todo_id, shares.list_id, COALESCE(todos.title, lists.name) AS "subject!", shares.mail, shares.user_id, shares.role AS "role: _", shares.status AS "status: _", shares.created_at, shares.updated_at FROM shares JOIN users ON users.id = shares.owner_id LEFT JOIN todos ON todos.id = shares.todo_id LEFT JOIN lists ON lists.id = shares.list_id WHERE shares.id = $1"#, id)
+        .fetch_optional(executor)
+        .await
+}
+
+/// Invites a mail address to a todo or list of `owner_id`. Sharing what
+/// belongs to someone else violates `shares_todo_id_fkey` or
+/// `shares_list_id_fkey`, sharing twice with the same address a unique
+/// constraint.
+pub async fn create(
+    conn: &mut PgConnection,
+    owner_id: Uuid,
+    target: Target,
+    mail: &str,
+    role: ShareRole,
+) -> Result<ShareModel, sqlx::Error> {
+    let (todo_id, list_id) = match target {
+        Target::Todo(id) => (Some(id), None),
+        Target::List(id) => (None, Some(id)),
+    };
+
+    let id = sqlx::query_scalar!(
+        "INSERT INTO shares (owner_id,todo_id,list_id,mail,role) VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        owner_id,
+        todo_id,
+        list_id,
+        mail,
+        role as ShareRole
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    find(conn, id).await?.ok_or(sqlx::Error::RowNotFound)
+}
+
+/// Changes what the recipient may do. Returns `None` if the owner has no
+/// share with that id.
+pub async fn update(
+    conn: &mut PgConnection,
+    owner_id: Uuid,
+    id: Uuid,
+    role: ShareRole,
+) -> Result<Option<ShareModel>, sqlx::Error> {
+    let updated = sqlx::query!(
+        "UPDATE shares SET role = $1, updated_at = NOW() WHERE id = $2 AND owner_id = $3",
+        role as ShareRole,
+        id,
+        owner_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+    find(conn, id).await
+}
+
+/// Revokes a share, or leaves it when `user_id` is the recipient. Returns
+/// whether there was one.
+pub async fn delete<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let deleted = sqlx::query!(
+        "DELETE FROM shares WHERE id = $1 AND (owner_id = $2 OR user_id = $2)",
+        id,
+        user_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(deleted.rows_affected() > 0)
+}
+
+/// Accepted shares of the user, leaving out todos in the trash.
+pub async fn shared_with<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+) -> Result<Vec<ShareModel>, sqlx::Error> {
+    sqlx::query_as!(
+        ShareModel,
+        r#"SELECT shares.id, shares.owner_id, users.name AS owner_name, shares.todo_id, shares.list_id, COALESCE(todos.title, lists.name) AS "subject!", shares.mail, shares.user_id, shares.role AS "role: _", shares.status AS "status: _", shares.created_at, shares.updated_at FROM shares JOIN users ON users.id = shares.owner_id LEFT JOIN todos ON todos.id = shares.todo_id LEFT JOIN lists ON lists.id = shares.list_id WHERE shares.user_id = $1 AND shares.status = 'accepted' AND todos.deleted_at IS NULL ORDER BY COALESCE(todos.title, lists.name)"#,
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Open invitations to a mail address.
+pub async fn invitations<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    mail: &str,
+) -> Result<Vec<ShareModel>, sqlx::Error> {
+    sqlx::query_as!(
+        ShareModel,
+        r#"SELECT shares.id, shares.owner_id, users.name AS owner_name, shares.todo_id, shares.list_id, COALESCE(todos.title, lists.name) AS "subject!", shares.mail, shares.user_id, shares.role AS "role: _", shares.status AS "status: _", shares.created_at, shares.updated_at FROM shares JOIN users ON users.id = shares.owner_id LEFT JOIN todos ON todos.id = shares.todo_id LEFT JOIN lists ON lists.id = shares.list_id WHERE shares.mail = $1 AND shares.status = 'pending' ORDER BY shares.created_at DESC"#,
+        mail
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Accepts or declines an open invitation to the user's mail address.
+/// Returns `None` if there is no such invitation.
+pub async fn answer(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    mail: &str,
+    id: Uuid,
+    status: ShareStatus,
+) -> Result<Option<ShareModel>, sqlx::Error> {
+    let answered = sqlx::query!(
+        "UPDATE shares SET status = $1, user_id = $2, updated_at = NOW() WHERE id = $3 AND mail = $4 AND status = 'pending'",
+        status as ShareStatus,
+        user_id,
+        id,
+        mail
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if answered.rows_affected() == 0 {
+        return Ok(None);
+    }
+    find(conn, id).await
+}
+
+/// Users who share anything with the user.
+pub async fn sharers<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT DISTINCT owner_id FROM shares WHERE user_id = $1 AND status = 'accepted'",
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+}
@@ -0,0 +1,192 @@
+use super::event;
+use crate::{
+    model::{TagModel, ToDoModel},
+    schema::{CreateTag, UpdateTag},
+};
+use serde_json::{json, Map};
+use sqlx::{types::Json, Connection, Executor, FromRow, PgConnection, Postgres};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(FromRow)]
+struct TodoTag {
+    todo_id: Uuid,
+    #[sqlx(flatten)]
+    tag: TagModel,
+}
+
+/// Sorted ids of the todo's tags, as recorded in its history.
+pub fn ids(todo: &ToDoModel) -> Vec<Uuid> {
+    let mut ids: Vec<Uuid> = todo.tags.iter().map(|tag| tag.id).collect();
+    ids.sort();
+    ids
+}
+
+/// Fills in the tags of all `todos` with a single query.
+pub async fn attach<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    todos: &mut [ToDoModel],
+) -> Result<(), sqlx::Error> {
+    if todos.is_empty() {
+        return Ok(());
+    }
+
+    let todo_ids: Vec<Uuid> = todos.iter().map(|todo| todo.id).collect();
+    let rows = sqlx::query_as::<_, TodoTag>(
+        "SELECT todo_tags.todo_id, tags.* FROM todo_tags JOIN tags ON tags.id = todo_tags.tag_id WHERE todo_tags.todo_id = ANY($1) ORDER BY tags.name",
+    )
+    .bind(&todo_ids)
+    .fetch_all(executor)
+    .await?;
+
+    let mut tags: HashMap<Uuid, Vec<TagModel>> = HashMap::new();
+    for row in rows {
+        tags.entry(row.todo_id).or_default().push(row.tag);
+    }
+    for todo in todos {
+        todo.tags = Json(tags.remove(&todo.id).unwrap_or_default());
+    }
+
+    Ok(())
+}
+
+/// Replaces the tags of a todo. Tags that do not exist or belong to someone
+/// else violate `todo_tags_tag_id_fkey`.
+pub async fn set(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    todo_id: Uuid,
+    tag_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM todo_tags WHERE todo_id = $1", todo_id)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query!(
+        "INSERT INTO todo_tags (todo_id,tag_id,user_id) SELECT $1, UNNEST($2::UUID[]), $3 ON CONFLICT DO NOTHING",
+        todo_id,
+        tag_ids,
+        user_id
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn list<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+) -> Result<Vec<TagModel>, sqlx::Error> {
+    sqlx::query_as!(
+        TagModel,
+        "SELECT * FROM tags WHERE user_id = $1 ORDER BY name",
+        user_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn find<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<Option<TagModel>, sqlx::Error> {
+    sqlx::query_as!(
+        TagModel,
+        "SELECT * FROM tags WHERE id = $1 AND user_id = $2",
+        id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+pub async fn create<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    tag: &CreateTag,
+) -> Result<TagModel, sqlx::Error> {
+    sqlx::query_as!(
+        TagModel,
+        "INSERT INTO tags (user_id,name,color) VALUES ($1, $2, $3) RETURNING *",
+        user_id,
+        tag.name.trim(),
+        tag.color
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Renames or recolors a tag. Returns `None` if the user has no tag with
+/// that id.
+pub async fn update<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    id: Uuid,
+    changes: &UpdateTag,
+) -> Result<Option<TagModel>, sqlx::Error> {
+    sqlx::query_as!(
+        TagModel,
+        "UPDATE tags SET name = COALESCE($1, name), color = CASE WHEN $2 THEN $3 ELSE color END WHERE id = $4 AND user_id = $5 RETURNING *",
+        changes.name.as_deref().map(str::trim),
+        changes.color.is_some(),
+        changes.color.clone().flatten(),
+        id,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Deletes a tag and returns whether there was one. The todos it is taken
+/// off get a new revision.
+pub async fn delete(conn: &mut PgConnection, user_id: Uuid, id: Uuid) -> Result<bool, sqlx::Error> {
+    let mut tx = conn.begin().await?;
+
+    let mut todos = sqlx::query_as::<_, ToDoModel>(
+        "UPDATE todos SET updated_at = NOW(), version = version + 1 WHERE id IN (SELECT todo_id FROM todo_tags WHERE tag_id = $1 AND user_id = $2) RETURNING *",
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    attach(&mut *tx, &mut todos).await?;
+    for todo in &todos {
+        let from = ids(todo);
+        let to: Vec<Uuid> = from
+            .iter()
+            .copied()
+            .filter(|tag_id| *tag_id != id)
+            .collect();
+        let mut changes = Map::new();
+        changes.insert("tags".to_string(), json!({ "from": from, "to": to }));
+        event::record(&mut tx, user_id, "updated", todo, changes).await?;
+    }
+
+    let deleted = sqlx::query!(
+        "DELETE FROM tags WHERE id = $1 AND user_id = $2",
+        id,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(deleted.rows_affected() > 0)
+}
+
+/// The id of the user's tag with that name, created without a color if there
+/// is none.
+pub async fn ensure<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    name: &str,
+) -> Result<Uuid, sqlx::Error> {
+    sqlx::query_scalar!(
+        "INSERT INTO tags (user_id,name) VALUES ($1, $2) ON CONFLICT (user_id, name) DO UPDATE SET name = EXCLUDED.name RETURNING id",
+        user_id,
+        name.trim()
+    )
+    .fetch_one(executor)
+    .await
+}
@@ -0,0 +1,1147 @@
+//! Queries of a fixed shape are checked at compile time: `calendar`,
+//! `export_page`, the title lookups and the purges. The rest read whole
+//! rows into `ToDoModel` and are checked at runtime. `list` builds its
+//! filters with a `QueryBuilder`, and with the macros every `SELECT *` and
+//! `RETURNING *` would have to spell out all columns, with overrides for the
+//! priority enum and for the tags that are attached afterwards.
+
+use super::{event, tag};
+use crate::{
+    model::{Priority, TagModel, ToDoModel},
+    rank, recurrence,
+    schema::{CreateToDo, FilterOptions, TagMode, ToDoTree, UpdateToDo},
+    transfer::ExportRow,
+};
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map};
+use sqlx::{types::Json, Connection, Executor, PgConnection, Postgres, QueryBuilder};
+use std::{collections::HashMap, fmt};
+use uuid::Uuid;
+
+/// Who writes and whose todos are written. The two differ when someone
+/// works on a todo shared with them.
+#[derive(Debug, Clone, Copy)]
+pub struct Actor {
+    pub owner_id: Uuid,
+    pub user_id: Uuid,
+}
+
+impl Actor {
+    /// A user working on their own todos.
+    pub fn owner(user_id: Uuid) -> Self {
+        Actor {
+            owner_id: user_id,
+            user_id,
+        }
+    }
+}
+
+/// How many levels of todos there can be, top level todos included.
+pub const MAX_DEPTH: i32 = 5;
+
+/// Why a write did not produce a new revision.
+pub enum WriteError {
+    /// No such todo, or it does not have the expected version.
+    Rejected,
+    /// The revision is not the todo's past or its history is incomplete.
+    UnknownRevision,
+    /// The todo has no RRULE.
+    NotRecurring,
+    /// The RRULE has no occurrence after the current one.
+    SeriesEnded,
+    /// The parent does not exist or is in the trash.
+    ParentNotFound,
+    /// The parent is the todo itself or one of its subtasks.
+    Cycle,
+    /// The subtasks would be nested deeper than `MAX_DEPTH`.
+    TooDeep,
+    /// A todo to move next to does not exist or is in the trash.
+    NeighborNotFound,
+    /// The todos to move between are not in that order.
+    InvalidNeighbors,
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::Rejected => f.write_str("ToDo not found"),
+            WriteError::UnknownRevision => f.write_str("That revision is not available"),
+            WriteError::NotRecurring => f.write_str("ToDo does not repeat"),
+            WriteError::SeriesEnded => f.write_str("ToDo has no further occurrences"),
+            WriteError::ParentNotFound => f.write_str("Parent ToDo not found"),
+            WriteError::Cycle => f.write_str("A ToDo cannot become a subtask of itself"),
+            WriteError::TooDeep => write!(f, "Subtasks can be nested {} levels deep", MAX_DEPTH),
+            WriteError::NeighborNotFound => f.write_str("ToDo to move next to not found"),
+            WriteError::InvalidNeighbors => f.write_str("ToDo cannot be moved between those ToDos"),
+            WriteError::Database(err) => write!(f, "{:?}", err),
+        }
+    }
+}
+
+impl From<sqlx::Error> for WriteError {
+    fn from(err: sqlx::Error) -> Self {
+        WriteError::Database(err)
+    }
+}
+
+/// What a failed write comes down to for a client, whichever API it uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    NotFound,
+    /// Another of the user's todos has the title.
+    Conflict,
+    /// The write cannot be made as asked.
+    Invalid,
+    Internal,
+}
+
+impl WriteError {
+    /// What the failed write to the todo `id` comes down to, and the message
+    /// explaining it. A rejected write counts as a missing todo, telling it
+    /// from a stale version takes a look at the todo.
+    pub fn classify(&self, id: Uuid) -> (Failure, String) {
+        let message = match self {
+            WriteError::Database(err) if super::is_unique_violation(err) => {
+                return (
+                    Failure::Conflict,
+                    "ToDo with that title already exists".to_string(),
+                );
+            }
+            WriteError::Database(err) if super::is_check_violation(err) => {
+                "A repeating ToDo needs a due date".to_string()
+            }
+            WriteError::Database(err) if super::is_foreign_key_violation(err) => {
+                missing_reference(err).to_string()
+            }
+            WriteError::Database(err) => return (Failure::Internal, format!("{:?}", err)),
+            WriteError::Rejected => {
+                return (Failure::NotFound, format!("ToDo with ID: {} not found", id));
+            }
+            WriteError::UnknownRevision => {
+                format!("That revision of ToDo with ID: {} is not available", id)
+            }
+            WriteError::NotRecurring => format!("ToDo with ID: {} does not repeat", id),
+            WriteError::SeriesEnded => {
+                format!("ToDo with ID: {} has no further occurrences", id)
+            }
+            WriteError::Cycle => {
+                format!("ToDo with ID: {} cannot become a subtask of itself", id)
+            }
+            err => err.to_string(),
+        };
+        (Failure::Invalid, message)
+    }
+}
+
+/// The list or tag a foreign key violation is about.
+pub fn missing_reference(err: &sqlx::Error) -> &'static str {
+    match err.as_database_error().and_then(|e| e.constraint()) {
+        Some("todo_tags_tag_id_fkey") => "Tag not found",
+        _ => "List not found",
+    }
+}
+
+/// SQL ordering for a `sort` value such as `dueAt` or `-priority`.
+pub fn order_by(sort: &str) -> Option<String> {
+    let (field, direction) = match sort.strip_prefix('-') {
+        Some(field) => (field, "DESC"),
+        None => (sort, "ASC"),
+    };
+    let column = match field {
+        "title" => "title",
+        "priority" => "priority",
+        "dueAt" => "due_at",
+        "completedAt" => "completed_at",
+        "createdAt" => "created_at",
+        "updatedAt" => "updated_at",
+        "position" => "position",
+        _ => return None,
+    };
+
+    Some(format!("{} {} NULLS LAST", column, direction))
+}
+
+pub async fn find(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<Option<ToDoModel>, sqlx::Error> {
+    let mut todo = sqlx::query_as::<_, ToDoModel>(
+        "SELECT * FROM todos WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    if let Some(todo) = &mut todo {
+        tag::attach(conn, std::slice::from_mut(todo)).await?;
+    }
+
+    Ok(todo)
+}
+
+/// `order` must come from `order_by`.
+pub async fn list(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    filter: &FilterOptions,
+    order: &str,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<ToDoModel>, sqlx::Error> {
+    let mut query =
+        QueryBuilder::new("SELECT * FROM todos WHERE deleted_at IS NULL AND user_id = ");
+    query.push_bind(user_id);
+
+    if let Some(complete) = filter.complete {
+        query.push(" AND complete = ").push_bind(complete);
+    }
+    if let Some(priority) = filter.priority {
+        query.push(" AND priority = ").push_bind(priority);
+    }
+    if let Some(due_before) = filter.due_before {
+        query.push(" AND due_at < ").push_bind(due_before);
+    }
+    if let Some(due_after) = filter.due_after {
+        query.push(" AND due_at >= ").push_bind(due_after);
+    }
+    if filter.overdue == Some(true) {
+        query.push(" AND due_at < NOW() AND complete IS NOT TRUE");
+    }
+    if let Some(list_id) = filter.list_id {
+        query.push(" AND list_id = ").push_bind(list_id);
+    }
+    if let Some(parent_id) = filter.parent_id {
+        query.push(" AND parent_id = ").push_bind(parent_id);
+    }
+    if let Some(tags) = &filter.tags {
+        let mut tags = tags.clone();
+        tags.sort();
+        tags.dedup();
+        query
+            .push(" AND id IN (SELECT todo_id FROM todo_tags WHERE tag_id = ANY(")
+            .push_bind(tags.clone())
+            .push(")");
+        if filter.tag_mode.unwrap_or_default() == TagMode::All {
+            query
+                .push(" GROUP BY todo_id HAVING COUNT(*) = ")
+                .push_bind(tags.len() as i64);
+        }
+        query.push(")");
+    }
+
+    query
+        .push(" ORDER BY ")
+        .push(order)
+        .push(", id LIMIT ")
+        .push_bind(limit as i64)
+        .push(" OFFSET ")
+        .push_bind(offset as i64);
+
+    let mut todos = query
+        .build_query_as::<ToDoModel>()
+        .fetch_all(&mut *conn)
+        .await?;
+    tag::attach(conn, &mut todos).await?;
+
+    Ok(todos)
+}
+
+/// Ids of the todos below `id` at any depth, either those in the trash since
+/// `deleted_at` or, for `None`, those outside of it.
+async fn subtasks(
+    conn: &mut PgConnection,
+    id: Uuid,
+    deleted_at: Option<DateTime<Utc>>,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar(
+        "WITH RECURSIVE subtasks AS (SELECT id FROM todos WHERE parent_id = $1 AND deleted_at IS NOT DISTINCT FROM $2 UNION ALL SELECT todos.id FROM todos JOIN subtasks ON todos.parent_id = subtasks.id WHERE todos.deleted_at IS NOT DISTINCT FROM $2) SELECT id FROM subtasks",
+    )
+    .bind(id)
+    .bind(deleted_at)
+    .fetch_all(conn)
+    .await
+}
+
+/// Checks that the todo `id` and its subtasks can go below `parent_id`. For
+/// a todo yet to be created `id` is `None`.
+async fn check_parent(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    id: Option<Uuid>,
+    parent_id: Uuid,
+) -> Result<(), WriteError> {
+    // Two moves that are fine on their own can still form a cycle together,
+    // so a user's moves take turns.
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1::TEXT, 0))")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    let ancestors: Vec<Uuid> = sqlx::query_scalar(
+        "WITH RECURSIVE ancestors AS (SELECT id, parent_id, 1 AS depth FROM todos WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL UNION ALL SELECT todos.id, todos.parent_id, ancestors.depth + 1 FROM todos JOIN ancestors ON todos.id = ancestors.parent_id WHERE ancestors.depth <= $3) SELECT id FROM ancestors",
+    )
+    .bind(parent_id)
+    .bind(user_id)
+    .bind(MAX_DEPTH)
+    .fetch_all(&mut *conn)
+    .await?;
+    if ancestors.is_empty() {
+        return Err(WriteError::ParentNotFound);
+    }
+
+    let height = match id {
+        Some(id) if ancestors.contains(&id) => return Err(WriteError::Cycle),
+        Some(id) => sqlx::query_scalar::<_, Option<i32>>(
+            "WITH RECURSIVE subtasks AS (SELECT id, 1 AS depth FROM todos WHERE id = $1 UNION ALL SELECT todos.id, subtasks.depth + 1 FROM todos JOIN subtasks ON todos.parent_id = subtasks.id WHERE todos.deleted_at IS NULL AND subtasks.depth <= $2) SELECT MAX(depth) FROM subtasks",
+        )
+        .bind(id)
+        .bind(MAX_DEPTH)
+        .fetch_one(&mut *conn)
+        .await?
+        .unwrap_or(1),
+        None => 1,
+    };
+
+    if ancestors.len() as i32 + height > MAX_DEPTH {
+        return Err(WriteError::TooDeep);
+    }
+    Ok(())
+}
+
+/// The user's todo with its subtasks at any depth, oldest first.
+pub async fn tree(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<Option<ToDoTree>, sqlx::Error> {
+    let todo = match find(&mut *conn, user_id, id).await? {
+        Some(todo) => todo,
+        None => return Ok(None),
+    };
+
+    let ids = subtasks(&mut *conn, id, None).await?;
+    let mut todos = sqlx::query_as::<_, ToDoModel>(
+        "SELECT * FROM todos WHERE id = ANY($1) ORDER BY created_at, id",
+    )
+    .bind(&ids)
+    .fetch_all(&mut *conn)
+    .await?;
+    tag::attach(conn, &mut todos).await?;
+
+    let mut children: HashMap<Uuid, Vec<ToDoModel>> = HashMap::new();
+    for todo in todos {
+        if let Some(parent_id) = todo.parent_id {
+            children.entry(parent_id).or_default().push(todo);
+        }
+    }
+
+    Ok(Some(grow(todo, &mut children)))
+}
+
+fn grow(todo: ToDoModel, children: &mut HashMap<Uuid, Vec<ToDoModel>>) -> ToDoTree {
+    let subtasks: Vec<ToDoTree> = children
+        .remove(&todo.id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|child| grow(child, children))
+        .collect();
+
+    let subtask_count = subtasks
+        .iter()
+        .map(|subtask| 1 + subtask.subtask_count)
+        .sum();
+    let completed_subtask_count = subtasks
+        .iter()
+        .map(|subtask| {
+            subtask.completed_subtask_count + usize::from(subtask.todo.complete == Some(true))
+        })
+        .sum();
+    let progress = match subtask_count {
+        0 if todo.complete == Some(true) => 100.0,
+        0 => 0.0,
+        _ => (1000.0 * completed_subtask_count as f64 / subtask_count as f64).round() / 10.0,
+    };
+
+    ToDoTree {
+        todo,
+        subtask_count,
+        completed_subtask_count,
+        progress,
+        subtasks,
+    }
+}
+
+pub async fn create(
+    conn: &mut PgConnection,
+    actor: Actor,
+    todo: &CreateToDo,
+) -> Result<ToDoModel, WriteError> {
+    let mut tx = conn.begin().await?;
+    let todo_tags = &todo.tags;
+    if let Some(parent_id) = todo.parent_id {
+        check_parent(&mut tx, actor.owner_id, None, parent_id).await?;
+    }
+
+    // New todos go last.
+    let last: Option<String> =
+        sqlx::query_scalar("SELECT MAX(position) FROM todos WHERE user_id = $1")
+            .bind(actor.owner_id)
+            .fetch_one(&mut *tx)
+            .await?;
+    let position = rank::between(last.as_deref(), None).ok_or(WriteError::InvalidNeighbors)?;
+
+    let mut todo = sqlx::query_as::<_, ToDoModel>(
+        "INSERT INTO todos (title,content,complete,completed_at,due_at,priority,rrule,timezone,list_id,parent_id,position,user_id) VALUES ($1, $2, COALESCE($3, FALSE), CASE WHEN $3 THEN NOW() END, $4, COALESCE($5, 'normal'), $6, COALESCE($7, 'UTC'), $8, $9, $10, $11) RETURNING *",
+    )
+    .bind(&todo.title)
+    .bind(&todo.content)
+    .bind(todo.complete)
+    .bind(todo.due_at)
+    .bind(todo.priority)
+    .bind(&todo.rrule)
+    .bind(&todo.timezone)
+    .bind(todo.list_id)
+    .bind(todo.parent_id)
+    .bind(position)
+    .bind(actor.owner_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if let Some(tags) = &todo_tags {
+        tag::set(&mut tx, actor.owner_id, todo.id, tags).await?;
+        tag::attach(&mut *tx, std::slice::from_mut(&mut todo)).await?;
+    }
+    event::record(
+        &mut tx,
+        actor.user_id,
+        "created",
+        &todo,
+        event::diff(None, &todo),
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(todo)
+}
+
+/// Whether the todo is in the list and whether it has one of the tags, where
+/// no list or no tags match any todo. Both `false` for a purged todo.
+pub async fn matches<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    id: Uuid,
+    list_id: Option<Uuid>,
+    tags: &[Uuid],
+) -> Result<(bool, bool), sqlx::Error> {
+    let matches = sqlx::query_as::<_, (bool, bool)>(
+        "SELECT $2::UUID IS NULL OR list_id IS NOT DISTINCT FROM $2, CARDINALITY($3::UUID[]) = 0 OR EXISTS(SELECT 1 FROM todo_tags WHERE todo_id = todos.id AND tag_id = ANY($3)) FROM todos WHERE id = $1",
+    )
+    .bind(id)
+    .bind(list_id)
+    .bind(tags)
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(matches.unwrap_or_default())
+}
+
+/// Locks the user's todo for a write, provided it is not trashed and, when
+/// `version` is given, still at that version.
+async fn lock(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    id: Uuid,
+    version: Option<i32>,
+) -> Result<Option<ToDoModel>, sqlx::Error> {
+    let mut todo = sqlx::query_as::<_, ToDoModel>(
+        "SELECT * FROM todos WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL AND ($3::INTEGER IS NULL OR version = $3) FOR UPDATE",
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(version)
+    .fetch_optional(&mut *conn)
+    .await?;
+    if let Some(todo) = &mut todo {
+        tag::attach(conn, std::slice::from_mut(todo)).await?;
+    }
+
+    Ok(todo)
+}
+
+/// Writes `changes` over the locked `before` and records the event. The kind
+/// is derived from the diff unless given.
+async fn apply(
+    conn: &mut PgConnection,
+    actor: Actor,
+    before: &ToDoModel,
+    changes: &UpdateToDo,
+    kind: Option<&str>,
+) -> Result<ToDoModel, WriteError> {
+    if let Some(Some(parent_id)) = changes.parent_id {
+        if before.parent_id != Some(parent_id) {
+            check_parent(conn, actor.owner_id, Some(before.id), parent_id).await?;
+        }
+    }
+
+    // A new due date gets a new reminder.
+    let mut after = sqlx::query_as::<_, ToDoModel>(
+        "UPDATE todos SET title = COALESCE($1, title), content = COALESCE($2, content), complete = COALESCE($3, complete), completed_at = CASE WHEN COALESCE($3, complete) THEN COALESCE(completed_at, NOW()) END, due_at = CASE WHEN $4 THEN $5 ELSE due_at END, reminded_at = CASE WHEN $4 THEN NULL ELSE reminded_at END, priority = COALESCE($6, priority), rrule = CASE WHEN $7 THEN $8 ELSE rrule END, timezone = COALESCE($9, timezone), list_id = CASE WHEN $10 THEN $11 ELSE list_id END, parent_id = CASE WHEN $12 THEN $13 ELSE parent_id END, updated_at = NOW(), version = version + 1 WHERE id = $14 RETURNING *",
+    )
+    .bind(&changes.title)
+    .bind(&changes.content)
+    .bind(changes.complete)
+    .bind(changes.due_at.is_some())
+    .bind(changes.due_at.flatten())
+    .bind(changes.priority)
+    .bind(changes.rrule.is_some())
+    .bind(changes.rrule.clone().flatten())
+    .bind(&changes.timezone)
+    .bind(changes.list_id.is_some())
+    .bind(changes.list_id.flatten())
+    .bind(changes.parent_id.is_some())
+    .bind(changes.parent_id.flatten())
+    .bind(before.id)
+    .fetch_one(&mut *conn)
+    .await?;
+    if let Some(tags) = &changes.tags {
+        tag::set(conn, actor.owner_id, before.id, tags).await?;
+    }
+    tag::attach(&mut *conn, std::slice::from_mut(&mut after)).await?;
+
+    let diff = event::diff(Some(before), &after);
+    let kind = kind.unwrap_or(match diff.get("complete") {
+        Some(_) if after.complete.unwrap_or_default() => "completed",
+        Some(_) => "reopened",
+        None if !diff.is_empty()
+            && diff
+                .keys()
+                .all(|field| field == "listId" || field == "parentId") =>
+        {
+            "moved"
+        }
+        None => "updated",
+    });
+    event::record(conn, actor.user_id, kind, &after, diff).await?;
+
+    Ok(after)
+}
+
+/// Applies the given fields and bumps the version. `Rejected` if the user
+/// has no todo with that id or, when `version` is given, it does not match.
+/// With `cascade`, completing the todo completes its open subtasks as well.
+pub async fn update(
+    conn: &mut PgConnection,
+    actor: Actor,
+    id: Uuid,
+    version: Option<i32>,
+    changes: &UpdateToDo,
+    cascade: bool,
+) -> Result<ToDoModel, WriteError> {
+    let mut tx = conn.begin().await?;
+
+    let before = lock(&mut tx, actor.owner_id, id, version)
+        .await?
+        .ok_or(WriteError::Rejected)?;
+    let after = write(&mut tx, actor, &before, changes).await?;
+
+    if cascade && changes.complete == Some(true) {
+        let completed = UpdateToDo {
+            complete: Some(true),
+            ..Default::default()
+        };
+        for subtask_id in subtasks(&mut tx, id, None).await? {
+            match lock(&mut tx, actor.owner_id, subtask_id, None).await? {
+                Some(subtask) if subtask.complete != Some(true) => {
+                    write(&mut tx, actor, &subtask, &completed).await?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    tx.commit().await?;
+    Ok(after)
+}
+
+/// Writes `changes` over the locked `before`.
+///
+/// Completing an occurrence of a recurring todo spawns the next one, which
+/// takes over the rule. The completed occurrence leaves the series.
+async fn write(
+    conn: &mut PgConnection,
+    actor: Actor,
+    before: &ToDoModel,
+    changes: &UpdateToDo,
+) -> Result<ToDoModel, WriteError> {
+    let completing = changes.complete == Some(true) && before.complete != Some(true);
+    let rrule = changes.rrule.clone().unwrap_or(before.rrule.clone());
+    let timezone = changes.timezone.clone().unwrap_or(before.timezone.clone());
+    let due_at = changes.due_at.unwrap_or(before.due_at);
+    // Rules are validated on input, so one that fails here has no next
+    // occurrence either.
+    let next = match (completing, &rrule, due_at) {
+        (true, Some(rrule), Some(due_at)) => {
+            recurrence::next(rrule, &timezone, due_at).ok().flatten()
+        }
+        _ => None,
+    };
+
+    let after = match &next {
+        Some((next_due_at, next_rrule)) => {
+            let completed = UpdateToDo {
+                rrule: Some(None),
+                ..changes.clone()
+            };
+            let after = apply(conn, actor, before, &completed, None).await?;
+
+            let occurrence = CreateToDo {
+                title: after.title.clone(),
+                content: after.content.clone(),
+                complete: None,
+                due_at: Some(*next_due_at),
+                priority: Some(after.priority),
+                rrule: Some(next_rrule.clone()),
+                timezone: Some(timezone),
+                list_id: after.list_id,
+                tags: Some(tag::ids(&after)),
+                parent_id: after.parent_id,
+            };
+            create(conn, actor, &occurrence).await?;
+            after
+        }
+        None => apply(conn, actor, before, changes, None).await?,
+    };
+
+    Ok(after)
+}
+
+/// Puts a todo between `after` and `before` in the manual order by giving it
+/// a rank between theirs. With only one of them, the todo goes right next
+/// to it.
+pub async fn reorder(
+    conn: &mut PgConnection,
+    actor: Actor,
+    id: Uuid,
+    version: Option<i32>,
+    after: Option<Uuid>,
+    before: Option<Uuid>,
+) -> Result<ToDoModel, WriteError> {
+    let mut tx = conn.begin().await?;
+
+    let todo = lock(&mut tx, actor.owner_id, id, version)
+        .await?
+        .ok_or(WriteError::Rejected)?;
+    if after == Some(id) || before == Some(id) {
+        return Err(WriteError::InvalidNeighbors);
+    }
+
+    let position_of = |neighbor: Uuid| {
+        sqlx::query_scalar::<_, String>(
+            "SELECT position FROM todos WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL",
+        )
+        .bind(neighbor)
+        .bind(actor.owner_id)
+    };
+    let mut after_position = match after {
+        Some(after) => Some(
+            position_of(after)
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or(WriteError::NeighborNotFound)?,
+        ),
+        None => None,
+    };
+    let mut before_position = match before {
+        Some(before) => Some(
+            position_of(before)
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or(WriteError::NeighborNotFound)?,
+        ),
+        None => None,
+    };
+
+    // The closest todo on the open side, so nothing else ends up in between.
+    match (&after_position, &before_position) {
+        (Some(after), None) => {
+            before_position = sqlx::query_scalar(
+                "SELECT position FROM todos WHERE user_id = $1 AND id <> $2 AND position > $3 ORDER BY position LIMIT 1",
+            )
+            .bind(actor.owner_id)
+            .bind(id)
+            .bind(after)
+            .fetch_optional(&mut *tx)
+            .await?;
+        }
+        (None, Some(before)) => {
+            after_position = sqlx::query_scalar(
+                "SELECT position FROM todos WHERE user_id = $1 AND id <> $2 AND position < $3 ORDER BY position DESC LIMIT 1",
+            )
+            .bind(actor.owner_id)
+            .bind(id)
+            .bind(before)
+            .fetch_optional(&mut *tx)
+            .await?;
+        }
+        _ => {}
+    }
+
+    let position = rank::between(after_position.as_deref(), before_position.as_deref())
+        .ok_or(WriteError::InvalidNeighbors)?;
+    let mut todo = sqlx::query_as::<_, ToDoModel>(
+        "UPDATE todos SET position = $1, updated_at = NOW(), version = version + 1 WHERE id = $2 RETURNING *",
+    )
+    .bind(position)
+    .bind(todo.id)
+    .fetch_one(&mut *tx)
+    .await?;
+    tag::attach(&mut *tx, std::slice::from_mut(&mut todo)).await?;
+    event::record(&mut tx, actor.user_id, "reordered", &todo, Map::new()).await?;
+
+    tx.commit().await?;
+    Ok(todo)
+}
+
+/// Respaces the ranks of every user with a rank longer than `length`,
+/// keeping their order, and returns how many users there were. Ranks are not
+/// part of a todo's history, so versions stay as they are.
+pub async fn rebalance_positions<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    length: i32,
+) -> Result<u64, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>(
+        "WITH users AS (SELECT DISTINCT user_id FROM todos WHERE LENGTH(position) > $1), ranked AS (SELECT id, LPAD(TO_HEX(ROW_NUMBER() OVER (PARTITION BY user_id ORDER BY position, id)), 8, '0') || 'V' AS position FROM todos WHERE user_id IN (SELECT user_id FROM users)), updated AS (UPDATE todos SET position = ranked.position FROM ranked WHERE todos.id = ranked.id) SELECT COUNT(*) FROM users",
+    )
+    .bind(length)
+    .fetch_one(executor)
+    .await
+    .map(|users| users as u64)
+}
+
+/// Moves a recurring todo on to its next occurrence without completing it.
+pub async fn skip(
+    conn: &mut PgConnection,
+    actor: Actor,
+    id: Uuid,
+    version: Option<i32>,
+) -> Result<ToDoModel, WriteError> {
+    let mut tx = conn.begin().await?;
+
+    let before = lock(&mut tx, actor.owner_id, id, version)
+        .await?
+        .ok_or(WriteError::Rejected)?;
+    let (rrule, due_at) = match (&before.rrule, before.due_at) {
+        (Some(rrule), Some(due_at)) => (rrule, due_at),
+        _ => return Err(WriteError::NotRecurring),
+    };
+    let (next_due_at, next_rrule) = recurrence::next(rrule, &before.timezone, due_at)
+        .ok()
+        .flatten()
+        .ok_or(WriteError::SeriesEnded)?;
+
+    let changes = UpdateToDo {
+        due_at: Some(Some(next_due_at)),
+        rrule: Some(Some(next_rrule)),
+        ..Default::default()
+    };
+    let after = apply(&mut tx, actor, &before, &changes, Some("skipped")).await?;
+
+    tx.commit().await?;
+    Ok(after)
+}
+
+/// Drops the RRULE so completing the todo no longer spawns a next one.
+pub async fn end_series(
+    conn: &mut PgConnection,
+    actor: Actor,
+    id: Uuid,
+    version: Option<i32>,
+) -> Result<ToDoModel, WriteError> {
+    let mut tx = conn.begin().await?;
+
+    let before = lock(&mut tx, actor.owner_id, id, version)
+        .await?
+        .ok_or(WriteError::Rejected)?;
+    if before.rrule.is_none() {
+        return Err(WriteError::NotRecurring);
+    }
+
+    let changes = UpdateToDo {
+        rrule: Some(None),
+        ..Default::default()
+    };
+    let after = apply(&mut tx, actor, &before, &changes, Some("series ended")).await?;
+
+    tx.commit().await?;
+    Ok(after)
+}
+
+/// Restores the tracked fields as they were at `revision`, as a new
+/// revision. Walks the recorded diffs back from the current state, so every
+/// revision after `revision` must have been recorded.
+pub async fn revert(
+    conn: &mut PgConnection,
+    actor: Actor,
+    id: Uuid,
+    version: Option<i32>,
+    revision: i32,
+) -> Result<ToDoModel, WriteError> {
+    let mut tx = conn.begin().await?;
+
+    let before = lock(&mut tx, actor.owner_id, id, version)
+        .await?
+        .ok_or(WriteError::Rejected)?;
+    if revision < 1 || revision >= before.version {
+        return Err(WriteError::UnknownRevision);
+    }
+
+    let events = event::since(&mut *tx, id, revision).await?;
+    if events.len() as i32 != before.version - revision {
+        return Err(WriteError::UnknownRevision);
+    }
+
+    let mut target = UpdateToDo {
+        title: Some(before.title.clone()),
+        content: Some(before.content.clone()),
+        complete: before.complete,
+        due_at: Some(before.due_at),
+        priority: Some(before.priority),
+        rrule: Some(before.rrule.clone()),
+        timezone: Some(before.timezone.clone()),
+        list_id: Some(before.list_id),
+        tags: Some(tag::ids(&before)),
+        parent_id: Some(before.parent_id),
+        version: None,
+    };
+    for event in &events {
+        let from = |field: &str| event.changes.get(field).map(|change| &change["from"]);
+        if let Some(title) = from("title").and_then(|v| v.as_str()) {
+            target.title = Some(title.to_string());
+        }
+        if let Some(content) = from("content").and_then(|v| v.as_str()) {
+            target.content = Some(content.to_string());
+        }
+        if let Some(complete) = from("complete").and_then(|v| v.as_bool()) {
+            target.complete = Some(complete);
+        }
+        if let Some(due_at) = from("dueAt") {
+            target.due_at = Some(serde_json::from_value(due_at.clone()).unwrap_or_default());
+        }
+        if let Some(priority) =
+            from("priority").and_then(|v| serde_json::from_value(v.clone()).ok())
+        {
+            target.priority = Some(priority);
+        }
+        if let Some(rrule) = from("rrule") {
+            target.rrule = Some(rrule.as_str().map(str::to_string));
+        }
+        if let Some(timezone) = from("timezone").and_then(|v| v.as_str()) {
+            target.timezone = Some(timezone.to_string());
+        }
+        if let Some(list_id) = from("listId") {
+            target.list_id = Some(serde_json::from_value(list_id.clone()).unwrap_or_default());
+        }
+        if let Some(tags) = from("tags") {
+            target.tags = Some(serde_json::from_value(tags.clone()).unwrap_or_default());
+        }
+        if let Some(parent_id) = from("parentId") {
+            target.parent_id = Some(serde_json::from_value(parent_id.clone()).unwrap_or_default());
+        }
+    }
+
+    let after = apply(&mut tx, actor, &before, &target, Some("reverted")).await?;
+
+    tx.commit().await?;
+    Ok(after)
+}
+
+/// Moves a todo to the trash and returns whether it was there to move. Like
+/// `update`, a given `version` must match. With `cascade` its subtasks go
+/// along, otherwise they move up to its parent.
+pub async fn delete(
+    conn: &mut PgConnection,
+    actor: Actor,
+    id: Uuid,
+    version: Option<i32>,
+    cascade: bool,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = conn.begin().await?;
+
+    let todo = match lock(&mut tx, actor.owner_id, id, version).await? {
+        Some(todo) => todo,
+        None => return Ok(false),
+    };
+
+    let mut trashed = vec![id];
+    if cascade {
+        trashed.extend(subtasks(&mut tx, id, None).await?);
+    } else {
+        let moved = sqlx::query_as::<_, ToDoModel>(
+            "UPDATE todos SET parent_id = $1, updated_at = NOW(), version = version + 1 WHERE parent_id = $2 AND deleted_at IS NULL RETURNING *",
+        )
+        .bind(todo.parent_id)
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+        for subtask in &moved {
+            let mut changes = Map::new();
+            changes.insert(
+                "parentId".to_string(),
+                json!({ "from": id, "to": todo.parent_id }),
+            );
+            event::record(&mut tx, actor.user_id, "moved", subtask, changes).await?;
+        }
+    }
+
+    // Subtasks trashed along share the `deleted_at`, which is how `restore`
+    // finds them.
+    let todos = sqlx::query_as::<_, ToDoModel>(
+        "UPDATE todos SET deleted_at = NOW(), version = version + 1 WHERE id = ANY($1) RETURNING *",
+    )
+    .bind(&trashed)
+    .fetch_all(&mut *tx)
+    .await?;
+    for todo in &todos {
+        event::record(&mut tx, actor.user_id, "deleted", todo, Map::new()).await?;
+    }
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn list_trash(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    limit: usize,
+    offset: usize,
+) -> Result<Vec<ToDoModel>, sqlx::Error> {
+    let mut todos = sqlx::query_as::<_, ToDoModel>(
+        "SELECT * FROM todos WHERE user_id = $1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC LIMIT $2 OFFSET $3",
+    )
+    .bind(user_id)
+    .bind(limit as i64)
+    .bind(offset as i64)
+    .fetch_all(&mut *conn)
+    .await?;
+    tag::attach(conn, &mut todos).await?;
+
+    Ok(todos)
+}
+
+/// Takes a todo out of the trash, together with the subtasks trashed along
+/// with it, or returns `None` if it is not in there. A todo whose parent is
+/// still in the trash becomes a top level todo.
+pub async fn restore(
+    conn: &mut PgConnection,
+    actor: Actor,
+    id: Uuid,
+) -> Result<Option<ToDoModel>, sqlx::Error> {
+    let mut tx = conn.begin().await?;
+
+    let before = sqlx::query_as::<_, ToDoModel>(
+        "SELECT * FROM todos WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL FOR UPDATE",
+    )
+    .bind(id)
+    .bind(actor.owner_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let before = match before {
+        Some(before) => before,
+        None => return Ok(None),
+    };
+    let subtask_ids = subtasks(&mut tx, id, before.deleted_at).await?;
+
+    let mut todo = sqlx::query_as::<_, ToDoModel>(
+        "UPDATE todos SET deleted_at = NULL, parent_id = (SELECT parent.id FROM todos parent WHERE parent.id = todos.parent_id AND parent.deleted_at IS NULL), updated_at = NOW(), version = version + 1 WHERE id = $1 RETURNING *",
+    )
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await?;
+    tag::attach(&mut *tx, std::slice::from_mut(&mut todo)).await?;
+    let mut changes = Map::new();
+    if todo.parent_id != before.parent_id {
+        changes.insert(
+            "parentId".to_string(),
+            json!({ "from": before.parent_id, "to": todo.parent_id }),
+        );
+    }
+    event::record(&mut tx, actor.user_id, "restored", &todo, changes).await?;
+
+    let subtasks = sqlx::query_as::<_, ToDoModel>(
+        "UPDATE todos SET deleted_at = NULL, updated_at = NOW(), version = version + 1 WHERE id = ANY($1) RETURNING *",
+    )
+    .bind(&subtask_ids)
+    .fetch_all(&mut *tx)
+    .await?;
+    for subtask in &subtasks {
+        event::record(&mut tx, actor.user_id, "restored", subtask, Map::new()).await?;
+    }
+
+    tx.commit().await?;
+    Ok(Some(todo))
+}
+
+/// Permanently deletes a trashed todo and returns whether there was one. Its
+/// history is kept.
+pub async fn purge<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM todos WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL",
+        id,
+        user_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Permanently deletes everyone's todos trashed before `before` and returns
+/// how many there were.
+pub async fn purge_trashed_before<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    before: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!("DELETE FROM todos WHERE deleted_at < $1", before)
+        .execute(executor)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Marks open todos falling due before `until` as reminded and returns them.
+/// Concurrent callers never claim the same todo.
+pub async fn claim_reminders<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    until: DateTime<Utc>,
+) -> Result<Vec<ToDoModel>, sqlx::Error> {
+    sqlx::query_as::<_, ToDoModel>(
+        "UPDATE todos SET reminded_at = NOW() WHERE id IN (SELECT id FROM todos WHERE reminded_at IS NULL AND deleted_at IS NULL AND complete IS NOT TRUE AND due_at > NOW() AND due_at <= $1 ORDER BY due_at LIMIT 100 FOR UPDATE SKIP LOCKED) RETURNING *",
+    )
+    .bind(until)
+    .fetch_all(executor)
+    .await
+}
+
+/// The user's todos outside the trash for a calendar, only those in the
+/// list if one is given, in the manual order.
+pub async fn calendar(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    list_id: Option<Uuid>,
+) -> Result<Vec<ToDoModel>, sqlx::Error> {
+    let mut todos = sqlx::query_as!(
+        ToDoModel,
+        r#"SELECT id, title, content, complete, created_at, updated_at, user_id, version, deleted_at, due_at, priority AS "priority: Priority", completed_at, rrule, timezone, list_id, parent_id, position, '[]'::JSONB AS "tags!: Json<Vec<TagModel>>" FROM todos WHERE user_id = $1 AND deleted_at IS NULL AND ($2::UUID IS NULL OR list_id = $2) ORDER BY position"#,
+        user_id,
+        list_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    tag::attach(conn, &mut todos).await?;
+
+    Ok(todos)
+}
+
+/// The user's todos outside the trash after the one at `after`, a path and
+/// id as last returned, in export order: subtasks right after their parent
+/// and otherwise in the manual order. Starts at the beginning with an empty
+/// path.
+pub async fn export_page<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    after: (&[String], Uuid),
+    limit: i64,
+) -> Result<Vec<ExportRow>, sqlx::Error> {
+    sqlx::query_as!(
+        ExportRow,
+        r#"WITH RECURSIVE tree AS (SELECT todos.*, ARRAY[position] AS path, 0 AS depth FROM todos WHERE user_id = $1 AND parent_id IS NULL AND deleted_at IS NULL UNION ALL SELECT todos.*, tree.path || todos.position, tree.depth + 1 FROM todos JOIN tree ON todos.parent_id = tree.id WHERE todos.deleted_at IS NULL) SELECT tree.id AS "id!", tree.path AS "path!", tree.title AS "title!", tree.content AS "content!", tree.complete, tree.due_at, tree.priority AS "priority!: Priority", tree.rrule, tree.timezone AS "timezone!", lists.name AS "list?", ARRAY(SELECT tags.name FROM todo_tags JOIN tags ON tags.id = todo_tags.tag_id WHERE todo_tags.todo_id = tree.id ORDER BY tags.name) AS "tags!", parents.title AS "parent?", tree.created_at, tree.completed_at, tree.depth AS "depth!" FROM tree LEFT JOIN lists ON lists.id = tree.list_id LEFT JOIN todos parents ON parents.id = tree.parent_id WHERE (tree.path, tree.id) > ($2::TEXT[], $3::UUID) ORDER BY tree.path, tree.id LIMIT $4"#,
+        user_id,
+        after.0,
+        after.1,
+        limit
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// The user's todo outside the trash with this title, an open one before a
+/// completed one.
+pub async fn find_by_title<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    title: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT id FROM todos WHERE user_id = $1 AND title = $2 AND deleted_at IS NULL ORDER BY complete IS TRUE, created_at DESC LIMIT 1",
+        user_id,
+        title
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// The first of `title (2)`, `title (3)`, … that none of the user's todos
+/// outside the trash has.
+pub async fn free_title(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    title: &str,
+) -> Result<String, sqlx::Error> {
+    for n in 2.. {
+        let candidate = format!("{} ({})", title, n);
+        if find_by_title(&mut *conn, user_id, &candidate)
+            .await?
+            .is_none()
+        {
+            return Ok(candidate);
+        }
+    }
+    unreachable!()
+}
+
+/// Todos outside the trash by id, whoever owns them.
+pub async fn find_many(
+    conn: &mut PgConnection,
+    ids: &[Uuid],
+) -> Result<Vec<ToDoModel>, sqlx::Error> {
+    fetch_any(conn, "id", ids).await
+}
+
+/// The todos outside the trash in any of the lists, in the manual order.
+pub async fn in_lists(
+    conn: &mut PgConnection,
+    list_ids: &[Uuid],
+) -> Result<Vec<ToDoModel>, sqlx::Error> {
+    fetch_any(conn, "list_id", list_ids).await
+}
+
+/// The direct subtasks outside the trash of any of the todos, in the manual
+/// order.
+pub async fn children_of(
+    conn: &mut PgConnection,
+    parent_ids: &[Uuid],
+) -> Result<Vec<ToDoModel>, sqlx::Error> {
+    fetch_any(conn, "parent_id", parent_ids).await
+}
+
+/// Todos outside the trash whose `column` is one of `ids`, with their tags.
+async fn fetch_any(
+    conn: &mut PgConnection,
+    column: &str,
+    ids: &[Uuid],
+) -> Result<Vec<ToDoModel>, sqlx::Error> {
+    let mut todos = sqlx::query_as::<_, ToDoModel>(&format!(
+        "SELECT * FROM todos WHERE {} = ANY($1) AND deleted_at IS NULL ORDER BY position",
+        column
+    ))
+    .bind(ids)
+    .fetch_all(&mut *conn)
+    .await?;
+    tag::attach(conn, &mut todos).await?;
+
+    Ok(todos)
+}
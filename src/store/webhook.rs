@@ -0,0 +1,241 @@
+use crate::model::{WebhookDeliveryModel, WebhookDeliveryStatus, WebhookModel};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{Executor, FromRow, Postgres};
+use uuid::Uuid;
+
+/// Events a webhook can subscribe to.
+pub const EVENTS: [&str; 5] = [
+    "todo.created",
+    "todo.updated",
+    "todo.completed",
+    "todo.deleted",
+    "user.signed_up",
+];
+
+/// The webhook event for a todo event of the given kind. Moves, restores and
+/// the like count as updates.
+pub fn todo_event(kind: &str) -> &'static str {
+    match kind {
+        "created" => "todo.created",
+        "completed" => "todo.completed",
+        "deleted" => "todo.deleted",
+        _ => "todo.updated",
+    }
+}
+
+/// Queues a delivery of the event to every active webhook subscribed to it,
+/// those of `user_id` and those of the admins. Returns how many were queued.
+pub async fn enqueue<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Option<Uuid>,
+    event: &str,
+    payload: &Value,
+) -> Result<u64, sqlx::Error> {
+    let queued = sqlx::query!(
+        "INSERT INTO webhook_deliveries (webhook_id,event,payload) SELECT id, $2::VARCHAR, $3 FROM webhooks WHERE active AND $2 = ANY(events) AND (user_id IS NULL OR user_id = $1)",
+        user_id,
+        event,
+        payload
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(queued.rows_affected())
+}
+
+/// Webhooks of the user and, for admins, those of the admins.
+pub async fn list<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    admin: bool,
+) -> Result<Vec<WebhookModel>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookModel,
+        "SELECT id, user_id, url, events, active, created_at, updated_at FROM webhooks WHERE user_id = $1 OR ($2 AND user_id IS NULL) ORDER BY created_at",
+        user_id,
+        admin
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// A webhook the user may manage.
+pub async fn find<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Uuid,
+    admin: bool,
+    id: Uuid,
+) -> Result<Option<WebhookModel>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookModel,
+        "SELECT id, user_id, url, events, active, created_at, updated_at FROM webhooks WHERE id = $1 AND (user_id = $2 OR ($3 AND user_id IS NULL))",
+        id,
+        user_id,
+        admin
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Registers a webhook, of the admins without `user_id`.
+pub async fn create<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    user_id: Option<Uuid>,
+    url: &str,
+    secret: &str,
+    events: &[String],
+) -> Result<WebhookModel, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookModel,
+        "INSERT INTO webhooks (user_id,url,secret,events) VALUES ($1, $2, $3, $4) RETURNING id, user_id, url, events, active, created_at, updated_at",
+        user_id,
+        url,
+        secret,
+        events
+    )
+    .fetch_one(executor)
+    .await
+}
+
+pub async fn update<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    id: Uuid,
+    url: Option<&str>,
+    events: Option<&[String]>,
+    active: Option<bool>,
+) -> Result<WebhookModel, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookModel,
+        "UPDATE webhooks SET url = COALESCE($1, url), events = COALESCE($2, events), active = COALESCE($3, active), updated_at = NOW() WHERE id = $4 RETURNING id, user_id, url, events, active, created_at, updated_at",
+        url,
+        events,
+        active,
+        id
+    )
+    .fetch_one(executor)
+    .await
+}
+
+pub async fn delete<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM webhooks WHERE id = $1", id)
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
+
+/// The latest deliveries to a webhook, newest first.
+pub async fn deliveries<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    webhook_id: Uuid,
+    limit: i64,
+) -> Result<Vec<WebhookDeliveryModel>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookDeliveryModel,
+        r#"SELECT id, webhook_id, event, payload, status AS "status: _", attempts, next_attempt_at, response_status, response_body, error, duration_ms, created_at, delivered_at FROM webhook_deliveries WHERE webhook_id = $1 ORDER BY created_at DESC LIMIT $2"#,
+        webhook_id,
+        limit
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Queues the event of a past delivery once more, as a new delivery.
+/// Returns `None` if the webhook has no such delivery.
+pub async fn redeliver<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    webhook_id: Uuid,
+    id: Uuid,
+) -> Result<Option<WebhookDeliveryModel>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookDeliveryModel,
+        r#"INSERT INTO webhook_deliveries (webhook_id,event,payload) SELECT webhook_id, event, payload FROM webhook_deliveries WHERE id = $1 AND webhook_id = $2 RETURNING id, webhook_id, event, payload, status AS "status: _", attempts, next_attempt_at, response_status, response_body, error, duration_ms, created_at, delivered_at"#,
+        id,
+        webhook_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// A delivery that is due, together with where it goes.
+#[derive(FromRow)]
+pub struct Due {
+    pub id: Uuid,
+    pub event: String,
+    pub payload: Value,
+    pub attempts: i32,
+    pub url: String,
+    pub secret: String,
+}
+
+/// Takes up to `limit` due deliveries, leaving them alone for `lease` so no
+/// other instance sends them meanwhile. A delivery whose attempt is never
+/// finished is due again once the lease is over.
+pub async fn claim<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    limit: i64,
+    lease: chrono::Duration,
+) -> Result<Vec<Due>, sqlx::Error> {
+    sqlx::query_as::<_, Due>(
+        "UPDATE webhook_deliveries SET next_attempt_at = NOW() + $2 * INTERVAL '1 second' FROM webhooks WHERE webhooks.id = webhook_deliveries.webhook_id AND webhook_deliveries.id IN (SELECT id FROM webhook_deliveries WHERE status = 'pending' AND next_attempt_at <= NOW() ORDER BY next_attempt_at LIMIT $1 FOR UPDATE SKIP LOCKED) RETURNING webhook_deliveries.id, webhook_deliveries.event, webhook_deliveries.payload, webhook_deliveries.attempts, webhooks.url, webhooks.secret",
+    )
+    .bind(limit)
+    .bind(lease.num_seconds() as f64)
+    .fetch_all(executor)
+    .await
+}
+
+/// How an attempt went.
+pub struct Attempt {
+    pub response_status: Option<i32>,
+    pub response_body: Option<String>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+}
+
+/// Records an attempt and leaves the delivery with `status`. A pending one
+/// is due again at `next_attempt_at`.
+pub async fn finish<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    id: Uuid,
+    attempt: Attempt,
+    status: WebhookDeliveryStatus,
+    next_attempt_at: Option<DateTime<Utc>>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE webhook_deliveries SET attempts = attempts + 1, status = $1, next_attempt_at = COALESCE($2, next_attempt_at), response_status = $3, response_body = $4, error = $5, duration_ms = $6, delivered_at = CASE WHEN $1 = 'delivered'::webhook_delivery_status THEN NOW() END WHERE id = $7",
+        status as WebhookDeliveryStatus,
+        next_attempt_at,
+        attempt.response_status,
+        attempt.response_body,
+        attempt.error,
+        attempt.duration_ms,
+        id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Queues an event for one webhook only, whatever it is subscribed to.
+pub async fn enqueue_for<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    webhook_id: Uuid,
+    event: &str,
+    payload: &Value,
+) -> Result<WebhookDeliveryModel, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookDeliveryModel,
+        r#"INSERT INTO webhook_deliveries (webhook_id,event,payload) VALUES ($1, $2, $3) RETURNING id, webhook_id, event, payload, status AS "status: _", attempts, next_attempt_at, response_status, response_body, error, duration_ms, created_at, delivered_at"#,
+        webhook_id,
+        event,
+        payload
+    )
+    .fetch_one(executor)
+    .await
+}
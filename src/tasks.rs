@@ -0,0 +1,161 @@
+//! Background work that runs alongside the HTTP server on every instance.
+//! Work that should run once per occurrence goes through `jobs` instead.
+
+use crate::{
+    events::{self, Change, UserChange},
+    model::WebhookDeliveryStatus,
+    store, webhook, AppState,
+};
+use sqlx::{
+    postgres::{PgListener, PgNotification},
+    Pool, Postgres,
+};
+use std::{sync::Arc, time::Duration};
+
+/// How long to wait before listening again after the connection failed.
+const LISTEN_RETRY: Duration = Duration::from_secs(5);
+/// How many missed events are loaded at once when catching up.
+const CATCH_UP_BATCH: i64 = 500;
+const WEBHOOK_INTERVAL: Duration = Duration::from_secs(2);
+/// How many webhook deliveries are sent at once.
+const WEBHOOK_BATCH: i64 = 20;
+/// How long a claimed delivery is left to this instance. Longer than a
+/// request to a webhook may take.
+const WEBHOOK_LEASE_SECONDS: i64 = 60;
+
+/// Publishes the changes announced by any instance to the local
+/// subscribers. When the listener connection is lost it reconnects and
+/// publishes the todo events recorded in the meantime from `todo_events`.
+pub async fn listen_events(data: Arc<AppState>) {
+    // Position of the last todo event published.
+    let mut cursor = None;
+    loop {
+        let mut listener = match listen(&data.db).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                println!("🔥 Failed to listen for events: {:?}", err);
+                tokio::time::sleep(LISTEN_RETRY).await;
+                continue;
+            }
+        };
+
+        // Listening before catching up, so nothing falls in between.
+        if let Err(err) = catch_up(&data, &mut cursor).await {
+            println!("🔥 Failed to catch up on todo events: {:?}", err);
+        }
+
+        loop {
+            match listener.try_recv().await {
+                Ok(Some(notification)) => {
+                    if let Err(err) = relay(&data, &mut cursor, &notification).await {
+                        println!("🔥 Failed to relay event: {:?}", err);
+                    }
+                }
+                Ok(None) => {
+                    println!("🔌 Lost the event listener connection, reconnecting");
+                    break;
+                }
+                Err(err) => {
+                    println!("🔥 Failed to receive events: {:?}", err);
+                    tokio::time::sleep(LISTEN_RETRY).await;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn listen(db: &Pool<Postgres>) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(db).await?;
+    listener
+        .listen_all([events::TODO_CHANNEL, events::USER_CHANNEL])
+        .await?;
+    Ok(listener)
+}
+
+/// Publishes the todo events after position `cursor`. Only events recorded after the
+/// server started are published, clients catch up on older ones from
+/// `todo_events` themselves.
+async fn catch_up(data: &AppState, cursor: &mut Option<i64>) -> Result<(), sqlx::Error> {
+    let Some(mut after) = *cursor else {
+        *cursor = Some(store::event::latest_position(&data.db).await?);
+        return Ok(());
+    };
+
+    loop {
+        let events = store::event::after(&data.db, after, CATCH_UP_BATCH).await?;
+        let done = (events.len() as i64) < CATCH_UP_BATCH;
+        for event in events {
+            after = events::position(&event);
+            *cursor = Some(after);
+            data.events.publish(Change::Todo(Arc::new(event)));
+        }
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+async fn relay(
+    data: &AppState,
+    cursor: &mut Option<i64>,
+    notification: &PgNotification,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match notification.channel() {
+        events::TODO_CHANNEL => {
+            let id: i64 = notification.payload().parse()?;
+            // Gone if the todo was purged in the meantime.
+            if let Some(event) = store::event::find(&data.db, id).await? {
+                let position = events::position(&event);
+                *cursor = Some(cursor.map_or(position, |cursor| cursor.max(position)));
+                data.events.publish(Change::Todo(Arc::new(event)));
+            }
+        }
+        events::USER_CHANNEL => {
+            let change: UserChange = serde_json::from_str(notification.payload())?;
+            data.events.publish(Change::User(change));
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Sends due webhook deliveries, retrying failed ones with exponential
+/// backoff until `webhook::MAX_ATTEMPTS`.
+pub async fn deliver_webhooks(data: Arc<AppState>) {
+    let mut interval = tokio::time::interval(WEBHOOK_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let lease = chrono::Duration::seconds(WEBHOOK_LEASE_SECONDS);
+        let due = match store::webhook::claim(&data.db, WEBHOOK_BATCH, lease).await {
+            Ok(due) => due,
+            Err(err) => {
+                println!("🔥 Failed to load webhook deliveries: {:?}", err);
+                continue;
+            }
+        };
+
+        let allowed_hosts = &data.env.webhook_allowed_hosts;
+        let sent =
+            futures::future::join_all(due.iter().map(|due| webhook::send(due, allowed_hosts)));
+        for (due, (success, attempt)) in due.iter().zip(sent.await) {
+            let attempts = due.attempts + 1;
+            let (status, next_attempt_at) = if success {
+                (WebhookDeliveryStatus::Delivered, None)
+            } else if attempts >= webhook::MAX_ATTEMPTS {
+                (WebhookDeliveryStatus::Failed, None)
+            } else {
+                (
+                    WebhookDeliveryStatus::Pending,
+                    Some(webhook::retry_at(attempts)),
+                )
+            };
+            if let Err(err) =
+                store::webhook::finish(&data.db, due.id, attempt, status, next_attempt_at).await
+            {
+                println!("🔥 Failed to record webhook delivery {}: {:?}", due.id, err);
+            }
+        }
+    }
+}
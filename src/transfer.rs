@@ -0,0 +1,614 @@
+//! The formats todos are exported and imported in. Todos refer to their
+//! list, tags and parent by name, so a file moves between accounts.
+//!
+//! - `json`: an array of `TodoRecord`s.
+//! - `csv`: a header row naming the columns, tags separated by `;`.
+//! - `md`: a checklist, `- [ ] Title` or `- [x] Title`, followed by its
+//!   content quoted with `> ` and its subtasks indented by two spaces. Fields
+//!   other than title, content and completion trail the title as code spans
+//!   such as `` `due:2023-06-20T10:00:00Z` ``. Backticks and backslashes in
+//!   the title are escaped with a backslash, so it cannot end in such a span.
+
+use crate::model::Priority;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Json,
+    Csv,
+    Md,
+}
+
+impl Format {
+    /// The format of a body sent with the given `Content-Type`.
+    pub fn from_content_type(content_type: &str) -> Option<Format> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        match mime {
+            "application/json" => Some(Format::Json),
+            "text/csv" => Some(Format::Csv),
+            "text/markdown" => Some(Format::Md),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Md => "text/markdown; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Csv => "csv",
+            Format::Md => "md",
+        }
+    }
+}
+
+/// A todo as it is exported and imported. `createdAt` and `completedAt` are
+/// informational and ignored on import.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TodoRecord {
+    pub title: String,
+    #[serde(default)]
+    pub content: String,
+    #[serde(default)]
+    pub complete: bool,
+    #[serde(default, rename = "dueAt")]
+    pub due_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default)]
+    pub rrule: Option<String>,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Name of the list.
+    #[serde(default)]
+    pub list: Option<String>,
+    /// Names of the tags.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Title of the todo this is a subtask of.
+    #[serde(default)]
+    pub parent: Option<String>,
+    #[serde(default, rename = "createdAt")]
+    pub created_at: Option<DateTime<Utc>>,
+    #[serde(default, rename = "completedAt")]
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// A todo read for export, in tree order.
+#[derive(Debug)]
+pub struct ExportRow {
+    pub id: Uuid,
+    /// The positions of the todo and its ancestors, top level first, which
+    /// export order sorts by.
+    pub path: Vec<String>,
+    pub title: String,
+    pub content: String,
+    pub complete: Option<bool>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub priority: Priority,
+    pub rrule: Option<String>,
+    pub timezone: String,
+    pub list: Option<String>,
+    pub tags: Vec<String>,
+    pub parent: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    /// How deep the todo is nested, 0 for a top level todo.
+    pub depth: i32,
+}
+
+impl ExportRow {
+    fn into_record(self) -> (TodoRecord, i32) {
+        let record = TodoRecord {
+            title: self.title,
+            content: self.content,
+            complete: self.complete.unwrap_or_default(),
+            due_at: self.due_at,
+            priority: Some(self.priority),
+            rrule: self.rrule,
+            timezone: Some(self.timezone),
+            list: self.list,
+            tags: self.tags,
+            parent: self.parent,
+            created_at: self.created_at,
+            completed_at: self.completed_at,
+        };
+        (record, self.depth)
+    }
+}
+
+const CSV_COLUMNS: [&str; 12] = [
+    "title",
+    "content",
+    "complete",
+    "dueAt",
+    "priority",
+    "rrule",
+    "timezone",
+    "list",
+    "tags",
+    "parent",
+    "createdAt",
+    "completedAt",
+];
+
+/// Turns export rows into a file chunk by chunk.
+pub struct Encoder {
+    format: Format,
+    rows: usize,
+}
+
+impl Encoder {
+    pub fn new(format: Format) -> Self {
+        Encoder { format, rows: 0 }
+    }
+
+    pub fn header(&self) -> Vec<u8> {
+        match self.format {
+            Format::Json => b"[".to_vec(),
+            Format::Csv => csv_line(CSV_COLUMNS.map(String::from)),
+            Format::Md => Vec::new(),
+        }
+    }
+
+    pub fn rows(&mut self, rows: Vec<ExportRow>) -> Vec<u8> {
+        let mut out = Vec::new();
+        for row in rows {
+            let (record, depth) = row.into_record();
+            match self.format {
+                Format::Json => {
+                    if self.rows > 0 {
+                        out.push(b',');
+                    }
+                    out.push(b'\n');
+                    out.extend(serde_json::to_vec(&record).unwrap_or_default());
+                }
+                Format::Csv => out.extend(csv_line(csv_fields(&record))),
+                Format::Md => out.extend(markdown_item(&record, depth).into_bytes()),
+            }
+            self.rows += 1;
+        }
+        out
+    }
+
+    pub fn footer(&self) -> Vec<u8> {
+        match self.format {
+            Format::Json => b"\n]\n".to_vec(),
+            Format::Csv | Format::Md => Vec::new(),
+        }
+    }
+}
+
+fn csv_line<const N: usize>(fields: [String; N]) -> Vec<u8> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let _ = writer.write_record(&fields);
+    writer.into_inner().unwrap_or_default()
+}
+
+fn csv_fields(record: &TodoRecord) -> [String; 12] {
+    let time = |at: Option<DateTime<Utc>>| at.map(|at| at.to_rfc3339()).unwrap_or_default();
+    [
+        record.title.clone(),
+        record.content.clone(),
+        record.complete.to_string(),
+        time(record.due_at),
+        record
+            .priority
+            .map(priority_name)
+            .unwrap_or_default()
+            .to_string(),
+        record.rrule.clone().unwrap_or_default(),
+        record.timezone.clone().unwrap_or_default(),
+        record.list.clone().unwrap_or_default(),
+        record.tags.join(";"),
+        record.parent.clone().unwrap_or_default(),
+        time(record.created_at),
+        time(record.completed_at),
+    ]
+}
+
+fn markdown_item(record: &TodoRecord, depth: i32) -> String {
+    let indent = "  ".repeat(depth.max(0) as usize);
+    let mut line = format!(
+        "{}- [{}] {}",
+        indent,
+        if record.complete { 'x' } else { ' ' },
+        escape_markdown(&record.title)
+    );
+    let mut meta = Vec::new();
+    if let Some(due_at) = record.due_at {
+        meta.push(format!("due:{}", due_at.to_rfc3339()));
+    }
+    if let Some(priority) = record.priority.filter(|p| *p != Priority::Normal) {
+        meta.push(format!("priority:{}", priority_name(priority)));
+    }
+    if let Some(rrule) = &record.rrule {
+        meta.push(format!("rrule:{}", rrule));
+    }
+    if let Some(timezone) = record.timezone.as_ref().filter(|tz| *tz != "UTC") {
+        meta.push(format!("timezone:{}", timezone));
+    }
+    if let Some(list) = &record.list {
+        meta.push(format!("list:{}", list));
+    }
+    if !record.tags.is_empty() {
+        meta.push(format!("tags:{}", record.tags.join(";")));
+    }
+    for field in meta {
+        line.push_str(&format!(" `{}`", field));
+    }
+    line.push('\n');
+
+    for content in record.content.lines() {
+        line.push_str(&format!("{}  > {}\n", indent, content));
+    }
+    line
+}
+
+fn escape_markdown(title: &str) -> String {
+    let mut escaped = String::with_capacity(title.len());
+    for c in title.chars() {
+        if matches!(c, '`' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn unescape_markdown(title: &str) -> String {
+    let mut unescaped = String::with_capacity(title.len());
+    let mut chars = title.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some('`' | '\\') if c == '\\' => unescaped.extend(chars.next()),
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Whether the text ends in a backslash that escapes what follows.
+fn ends_escaped(text: &str) -> bool {
+    text.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+fn priority_name(priority: Priority) -> &'static str {
+    match priority {
+        Priority::Low => "low",
+        Priority::Normal => "normal",
+        Priority::High => "high",
+        Priority::Urgent => "urgent",
+    }
+}
+
+/// A record read from an import, or why it could not be read, with its row:
+/// the position in a JSON array, the line of a CSV record or Markdown item.
+pub type Parsed = (usize, Result<TodoRecord, String>);
+
+/// Reads an import. Fails as a whole only if the file cannot be read at all.
+pub fn parse(format: Format, body: &[u8]) -> Result<Vec<Parsed>, String> {
+    match format {
+        Format::Json => parse_json(body),
+        Format::Csv => parse_csv(body),
+        Format::Md => {
+            let text = std::str::from_utf8(body).map_err(|_| "File is not UTF-8")?;
+            Ok(parse_markdown(text))
+        }
+    }
+}
+
+fn parse_json(body: &[u8]) -> Result<Vec<Parsed>, String> {
+    let values: Vec<Value> = serde_json::from_slice(body)
+        .map_err(|err| format!("Expected a JSON array of ToDos: {}", err))?;
+    Ok(values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let record = serde_json::from_value::<TodoRecord>(value).map_err(|err| err.to_string());
+            (i + 1, record)
+        })
+        .collect())
+}
+
+fn parse_csv(body: &[u8]) -> Result<Vec<Parsed>, String> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(body);
+    let columns: Vec<String> = reader
+        .headers()
+        .map_err(|err| format!("Invalid CSV header: {}", err))?
+        .iter()
+        .map(|column| column.trim().to_string())
+        .collect();
+    if !columns.iter().any(|column| column == "title") {
+        return Err("The CSV header has no title column".to_string());
+    }
+
+    Ok(reader
+        .records()
+        .enumerate()
+        .map(|(i, record)| {
+            let line = record
+                .as_ref()
+                .ok()
+                .and_then(|record| record.position())
+                .map_or(i + 2, |position| position.line() as usize);
+            let record = record
+                .map_err(|err| err.to_string())
+                .and_then(|record| csv_record(&columns, &record));
+            (line, record)
+        })
+        .collect())
+}
+
+fn csv_record(columns: &[String], row: &csv::StringRecord) -> Result<TodoRecord, String> {
+    let mut record = TodoRecord::default();
+    for (column, value) in columns.iter().zip(row.iter()) {
+        let value = value.trim();
+        if value.is_empty() && column != "content" {
+            continue;
+        }
+        match column.as_str() {
+            "title" => record.title = value.to_string(),
+            "content" => record.content = value.to_string(),
+            "complete" => record.complete = parse_bool(value)?,
+            "dueAt" => record.due_at = Some(parse_time(value)?),
+            "priority" => record.priority = Some(parse_priority(value)?),
+            "rrule" => record.rrule = Some(value.to_string()),
+            "timezone" => record.timezone = Some(value.to_string()),
+            "list" => record.list = Some(value.to_string()),
+            "tags" => record.tags = parse_tags(value),
+            "parent" => record.parent = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    Ok(record)
+}
+
+fn parse_markdown(text: &str) -> Vec<Parsed> {
+    let mut parsed: Vec<Parsed> = Vec::new();
+    // Indentation and title of the items enclosing the current line.
+    let mut ancestors: Vec<(usize, String)> = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - trimmed.len();
+
+        let item = ["- [ ] ", "* [ ] "]
+            .iter()
+            .find_map(|prefix| trimmed.strip_prefix(prefix).map(|rest| (false, rest)))
+            .or_else(|| {
+                ["- [x] ", "- [X] ", "* [x] ", "* [X] "]
+                    .iter()
+                    .find_map(|prefix| trimmed.strip_prefix(prefix).map(|rest| (true, rest)))
+            });
+
+        if let Some((complete, rest)) = item {
+            while ancestors.last().is_some_and(|(depth, _)| *depth >= indent) {
+                ancestors.pop();
+            }
+            let record = markdown_record(rest).map(|mut record| {
+                record.complete = complete;
+                record.parent = ancestors.last().map(|(_, title)| title.clone());
+                record
+            });
+            if let Ok(record) = &record {
+                ancestors.push((indent, record.title.clone()));
+            }
+            parsed.push((i + 1, record));
+            continue;
+        }
+
+        // Anything else is content of the item above.
+        match parsed.last_mut() {
+            Some((_, Ok(record))) => {
+                let content = trimmed
+                    .strip_prefix("> ")
+                    .or_else(|| trimmed.strip_prefix('>'))
+                    .unwrap_or(trimmed);
+                if !record.content.is_empty() {
+                    record.content.push('\n');
+                }
+                record.content.push_str(content);
+            }
+            Some((_, Err(_))) => {}
+            None => parsed.push((i + 1, Err("Expected a task item like - [ ] Title".into()))),
+        }
+    }
+    parsed
+}
+
+/// Reads a task line after the checkbox, taking trailing `key:value` code
+/// spans as fields.
+fn markdown_record(line: &str) -> Result<TodoRecord, String> {
+    let mut record = TodoRecord::default();
+    let mut title = line.trim_end();
+    while let Some(rest) = title.strip_suffix('`') {
+        if ends_escaped(rest) {
+            break;
+        }
+        let Some(start) = rest.rfind('`') else {
+            break;
+        };
+        let Some((key, value)) = rest[start + 1..].split_once(':') else {
+            break;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "due" => record.due_at = Some(parse_time(value)?),
+            "priority" => record.priority = Some(parse_priority(value)?),
+            "rrule" => record.rrule = Some(value.to_string()),
+            "timezone" => record.timezone = Some(value.to_string()),
+            "list" => record.list = Some(value.to_string()),
+            "tags" => record.tags = parse_tags(value),
+            _ => break,
+        }
+        title = rest[..start].trim_end();
+    }
+    record.title = unescape_markdown(title);
+    Ok(record)
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "1" | "x" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("Invalid complete value: {}", value)),
+    }
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|at| at.with_timezone(&Utc))
+        .map_err(|_| format!("Invalid date, expected RFC 3339: {}", value))
+}
+
+fn parse_priority(value: &str) -> Result<Priority, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "low" => Ok(Priority::Low),
+        "normal" => Ok(Priority::Normal),
+        "high" => Ok(Priority::High),
+        "urgent" => Ok(Priority::Urgent),
+        _ => Err(format!("Invalid priority: {}", value)),
+    }
+}
+
+fn parse_tags(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(title: &str, depth: i32) -> ExportRow {
+        ExportRow {
+            id: Uuid::nil(),
+            path: Vec::new(),
+            title: title.to_string(),
+            content: String::new(),
+            complete: Some(false),
+            due_at: None,
+            priority: Priority::Normal,
+            rrule: None,
+            timezone: "UTC".to_string(),
+            list: None,
+            tags: Vec::new(),
+            parent: None,
+            created_at: None,
+            completed_at: None,
+            depth,
+        }
+    }
+
+    fn export(format: Format, rows: Vec<ExportRow>) -> Vec<u8> {
+        let mut encoder = Encoder::new(format);
+        let mut file = encoder.header();
+        file.extend(encoder.rows(rows));
+        file.extend(encoder.footer());
+        file
+    }
+
+    fn records(parsed: Vec<Parsed>) -> Vec<TodoRecord> {
+        parsed
+            .into_iter()
+            .map(|(line, record)| record.unwrap_or_else(|err| panic!("line {}: {}", line, err)))
+            .collect()
+    }
+
+    #[test]
+    fn markdown_round_trips_nesting_and_backticks() {
+        let due_at = "2023-06-20T10:00:00Z".parse().unwrap();
+        let mut release = row("Release `v2`", 0);
+        release.content = "Notes\nmore notes".to_string();
+        release.due_at = Some(due_at);
+        release.priority = Priority::High;
+        release.tags = vec!["work".to_string(), "ops".to_string()];
+        let mut lookalike = row("Tag with `due:tomorrow`", 1);
+        lookalike.complete = Some(true);
+        let escaped = row(r"Escape \` and \\", 2);
+        let announce = row("Announce", 1);
+        let trailing = row("Ends in a backtick `", 0);
+
+        let file = export(
+            Format::Md,
+            vec![release, lookalike, escaped, announce, trailing],
+        );
+        let records = records(parse(Format::Md, &file).unwrap());
+
+        let tree: Vec<(&str, Option<&str>, bool)> = records
+            .iter()
+            .map(|record| {
+                (
+                    record.title.as_str(),
+                    record.parent.as_deref(),
+                    record.complete,
+                )
+            })
+            .collect();
+        assert_eq!(
+            tree,
+            [
+                ("Release `v2`", None, false),
+                ("Tag with `due:tomorrow`", Some("Release `v2`"), true),
+                (r"Escape \` and \\", Some("Tag with `due:tomorrow`"), false),
+                ("Announce", Some("Release `v2`"), false),
+                ("Ends in a backtick `", None, false),
+            ]
+        );
+        assert_eq!(records[0].content, "Notes\nmore notes");
+        assert_eq!(records[0].due_at, Some(due_at));
+        assert_eq!(records[0].priority, Some(Priority::High));
+        assert_eq!(records[0].tags, ["work", "ops"]);
+        assert_eq!(records[1].due_at, None);
+    }
+
+    #[test]
+    fn csv_reads_the_columns_it_has() {
+        let file = "title,complete,tags\nWrite report,true,work; urgent\nCall Bob\n";
+        let parsed = parse(Format::Csv, file.as_bytes()).unwrap();
+        assert_eq!(
+            parsed.iter().map(|(line, _)| *line).collect::<Vec<_>>(),
+            [2, 3]
+        );
+
+        let records = records(parsed);
+        assert_eq!(records[0].title, "Write report");
+        assert!(records[0].complete);
+        assert_eq!(records[0].tags, ["work", "urgent"]);
+        assert_eq!(records[0].due_at, None);
+        assert_eq!(records[1].title, "Call Bob");
+        assert!(!records[1].complete);
+        assert!(records[1].tags.is_empty());
+    }
+
+    #[test]
+    fn csv_fails_only_the_rows_it_cannot_read() {
+        let file = "title,complete\nA,maybe\nB,no\n";
+        let parsed = parse(Format::Csv, file.as_bytes()).unwrap();
+        assert!(parsed[0].1.is_err());
+        assert_eq!(parsed[1].1.as_ref().unwrap().title, "B");
+    }
+
+    #[test]
+    fn csv_needs_a_title_column() {
+        assert!(parse(Format::Csv, b"content,complete\nx,true\n").is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! Outbound webhooks. Deliveries are queued in `webhook_deliveries` together
+//! with the write that caused them and sent by `tasks::deliver_webhooks`.
+//!
+//! Every request carries the headers `X-Webhook-Event`, `X-Webhook-Delivery`,
+//! `X-Webhook-Timestamp` (Unix seconds) and `X-Webhook-Signature`, which is
+//! `sha256=` and the hex HMAC-SHA256 of `{timestamp}.{body}` keyed with the
+//! secret of the webhook.
+//!
+//! Webhooks may only point at public addresses, checked when one is saved
+//! and again on every delivery, which connects to the addresses it checked.
+//! Hosts in `WEBHOOK_ALLOWED_HOSTS`, such as a local test receiver, are
+//! exempt.
+
+use crate::store::webhook::{Attempt, Due};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{redirect::Policy, Url};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+use uuid::Uuid;
+
+/// Attempts before a delivery is given up on.
+pub const MAX_ATTEMPTS: i32 = 8;
+/// Wait after the first failed attempt, doubled after each further one.
+const BACKOFF_BASE_SECONDS: i64 = 30;
+const BACKOFF_MAX_SECONDS: i64 = 6 * 60 * 60;
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// How much of a response is kept for the delivery log.
+const RESPONSE_BODY_LIMIT: usize = 1024;
+
+/// What is sent for an event.
+pub fn payload(event: &str, data: Value) -> Value {
+    json!({ "event": event, "createdAt": Utc::now(), "data": data })
+}
+
+/// What is sent when someone signs up, only to the admins' webhooks.
+pub fn signed_up(id: Uuid, name: &str, mail: &str) -> Value {
+    payload(
+        "user.signed_up",
+        json!({ "id": id, "name": name, "mail": mail }),
+    )
+}
+
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// When to try again after `attempts` failed attempts.
+pub fn retry_at(attempts: i32) -> DateTime<Utc> {
+    let seconds = BACKOFF_BASE_SECONDS
+        .saturating_mul(1 << (attempts - 1).clamp(0, 20))
+        .min(BACKOFF_MAX_SECONDS);
+    Utc::now() + chrono::Duration::seconds(seconds)
+}
+
+/// Whether the address belongs to the public internet rather than to this
+/// host or a private network.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || first == 0
+                // Carrier-grade NAT, 100.64.0.0/10.
+                || (first == 100 && second & 0xc0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local, fc00::/7, and link-local, fe80::/10.
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Checks a webhook URL and resolves its host. Fails for anything but http
+/// and https and for hosts with a non-public address, unless the host is in
+/// `allowed_hosts`. Returns the host and the addresses to connect to.
+pub async fn resolve(
+    url: &str,
+    allowed_hosts: &[String],
+) -> Result<(String, Vec<SocketAddr>), String> {
+    let url = Url::parse(url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .ok_or("Webhook URL must be an http or https URL")?;
+    let (host, port) = match (url.host_str(), url.port_or_known_default()) {
+        (Some(host), Some(port)) => (host.to_string(), port),
+        _ => return Err("Webhook URL must be an http or https URL".to_string()),
+    };
+
+    let lookup = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((lookup, port))
+        .await
+        .map_err(|_| format!("Webhook host {} could not be resolved", host))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("Webhook host {} could not be resolved", host));
+    }
+    if !allowed_hosts.contains(&host) && !addrs.iter().all(|addr| is_public(addr.ip())) {
+        return Err(format!("Webhook host {} is not a public address", host));
+    }
+    Ok((host, addrs))
+}
+
+/// Posts a delivery to its webhook. Anything but a 2xx response counts as a
+/// failure, redirects are not followed.
+pub async fn send(due: &Due, allowed_hosts: &[String]) -> (bool, Attempt) {
+    let body = due.payload.to_string().into_bytes();
+    let timestamp = Utc::now().timestamp();
+    let started = Instant::now();
+
+    let client = resolve(&due.url, allowed_hosts)
+        .await
+        .and_then(|(host, addrs)| {
+            reqwest::Client::builder()
+                .redirect(Policy::none())
+                .resolve_to_addrs(&host, &addrs)
+                .build()
+                .map_err(|err| err.to_string())
+        });
+    let client = match client {
+        Ok(client) => client,
+        Err(error) => {
+            let attempt = Attempt {
+                response_status: None,
+                response_body: None,
+                error: Some(error),
+                duration_ms: 0,
+            };
+            return (false, attempt);
+        }
+    };
+
+    let sent = client
+        .post(&due.url)
+        .timeout(TIMEOUT)
+        .header("content-type", "application/json")
+        .header("user-agent", "todo-webhooks")
+        .header("x-webhook-event", &due.event)
+        .header("x-webhook-delivery", due.id.to_string())
+        .header("x-webhook-timestamp", timestamp.to_string())
+        .header(
+            "x-webhook-signature",
+            signature(&due.secret, timestamp, &body),
+        )
+        .body(body)
+        .send()
+        .await;
+
+    let (success, response_status, response_body, error) = match sent {
+        Ok(response) => {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            let text: String = text.chars().take(RESPONSE_BODY_LIMIT).collect();
+            let error = (!status.is_success()).then(|| format!("Responded with {}", status));
+            (
+                status.is_success(),
+                Some(status.as_u16() as i32),
+                Some(text),
+                error,
+            )
+        }
+        Err(err) => (false, None, None, Some(err.to_string())),
+    };
+
+    let attempt = Attempt {
+        response_status,
+        response_body,
+        error,
+        duration_ms: started.elapsed().as_millis().min(i32::MAX as u128) as i32,
+    };
+    (success, attempt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn rejects_private_and_local_ipv4() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "0.1.2.3",
+            "255.255.255.255",
+            "224.0.0.1",
+            "192.0.2.1",
+        ] {
+            assert!(!public(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn rejects_carrier_grade_nat() {
+        for ip in ["100.64.0.1", "100.100.100.200", "100.127.255.254"] {
+            assert!(!public(ip), "{}", ip);
+        }
+        for ip in ["100.63.255.255", "100.128.0.1"] {
+            assert!(public(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn rejects_ipv4_mapped_addresses_like_their_ipv4_address() {
+        for ip in [
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "::ffff:169.254.169.254",
+            "::ffff:100.64.0.1",
+        ] {
+            assert!(!public(ip), "{}", ip);
+        }
+        assert!(public("::ffff:8.8.8.8"));
+    }
+
+    #[test]
+    fn rejects_unique_local_and_other_local_ipv6() {
+        for ip in ["fc00::1", "fd12:3456::1", "fe80::1", "::1", "::", "ff02::1"] {
+            assert!(!public(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn accepts_public_addresses() {
+        for ip in ["8.8.8.8", "1.1.1.1", "2606:4700:4700::1111"] {
+            assert!(public(ip), "{}", ip);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Avatar uploads, against whichever storage the server is configured with:
+//! the local directory or the MinIO bucket from `compose.yml`.
+
+mod common;
+
+use common::{app_url, client, sign_up};
+use image::{ImageOutputFormat, Rgb, RgbImage};
+use reqwest::{header::CONTENT_TYPE, Client, Response, StatusCode};
+use serde_json::{json, Value};
+use std::io::Cursor;
+
+const BOUNDARY: &str = "avatar-test-boundary";
+
+fn png(color: [u8; 3]) -> Vec<u8> {
+    let mut png = Cursor::new(Vec::new());
+    RgbImage::from_pixel(300, 200, Rgb(color))
+        .write_to(&mut png, ImageOutputFormat::Png)
+        .unwrap();
+    png.into_inner()
+}
+
+async fn upload(client: &Client, token: &str, file: &[u8]) -> Response {
+    let mut body = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"avatar\"; filename=\"avatar.png\"\r\nContent-Type: image/png\r\n\r\n",
+        BOUNDARY
+    )
+    .into_bytes();
+    body.extend_from_slice(file);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+
+    client
+        .post(format!("{}/api/users/me/avatar", app_url()))
+        .bearer_auth(token)
+        .header(
+            CONTENT_TYPE,
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        )
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// The photo of the user in a response.
+async fn photo(response: Response) -> String {
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    body["data"]["photo"].as_str().unwrap().to_string()
+}
+
+/// The URLs of every size stored for a photo.
+fn sizes(photo: &str) -> Vec<(u32, String)> {
+    let base = photo
+        .strip_suffix("-256.png")
+        .expect("photo is the 256px size");
+    [256, 128, 64]
+        .into_iter()
+        .map(|size| (size, format!("{}-{}.png", base, size)))
+        .collect()
+}
+
+async fn assert_stored(client: &Client, photo: &str) {
+    for (size, url) in sizes(photo) {
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{}", url);
+        assert_eq!(response.headers()[CONTENT_TYPE], "image/png");
+        let file = image::load_from_memory(&response.bytes().await.unwrap()).unwrap();
+        assert_eq!((file.width(), file.height()), (size, size));
+    }
+}
+
+async fn assert_removed(client: &Client, photo: &str) {
+    for (_, url) in sizes(photo) {
+        let response = client.get(&url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", url);
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs the server and the compose services"]
+async fn uploads_replaces_and_deletes_an_avatar() {
+    let client = client();
+    let (user, token) = sign_up(&client).await;
+
+    let first = photo(upload(&client, &token, &png([200, 30, 30])).await).await;
+    assert!(first.contains(&format!("avatars/{}/", user["id"].as_str().unwrap())));
+    assert_stored(&client, &first).await;
+
+    let second = photo(upload(&client, &token, &png([30, 30, 200])).await).await;
+    assert_ne!(first, second);
+    assert_stored(&client, &second).await;
+    assert_removed(&client, &first).await;
+
+    let response = client
+        .delete(format!("{}/api/users/me/avatar", app_url()))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(photo(response).await, "");
+    assert_removed(&client, &second).await;
+}
+
+#[tokio::test]
+#[ignore = "needs the server and the compose services"]
+async fn rejects_files_that_are_not_images() {
+    let client = client();
+    let (_, token) = sign_up(&client).await;
+
+    let response = upload(&client, &token, b"not an image").await;
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(common::me(&client, &token).await["photo"], "");
+}
+
+#[tokio::test]
+#[ignore = "needs the server and the compose services"]
+async fn keeps_the_photo_to_uploads() {
+    let client = client();
+    let (_, token) = sign_up(&client).await;
+    let uploaded = photo(upload(&client, &token, &png([30, 200, 30])).await).await;
+
+    let response = client
+        .patch(format!("{}/api/users/me", app_url()))
+        .bearer_auth(&token)
+        .json(&json!({ "photo": format!("{}/uploads/avatars/elsewhere.png", app_url()) }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(common::me(&client, &token).await["photo"], uploaded);
+    assert_stored(&client, &uploaded).await;
+}
+
+#[tokio::test]
+#[ignore = "needs the server and the compose services"]
+async fn deleting_the_account_removes_the_avatar() {
+    let client = client();
+    let (_, token) = sign_up(&client).await;
+    let photo = photo(upload(&client, &token, &png([0, 0, 255])).await).await;
+    assert_stored(&client, &photo).await;
+
+    let response = client
+        .delete(format!("{}/api/users/me", app_url()))
+        .bearer_auth(&token)
+        .json(&json!({ "password": "password1" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_removed(&client, &photo).await;
+}
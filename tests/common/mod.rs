@@ -0,0 +1,87 @@
+//! Helpers for the integration tests, which talk to a running server over
+//! HTTP the way clients do. Start the compose services, then the server with
+//! the `mock` login provider from `.env.template` and
+//! `WEBHOOK_ALLOWED_HOSTS=127.0.0.1`, and run them with
+//! `cargo test -- --ignored`. `TEST_APP_URL` points them at a server other
+//! than `http://localhost:3000`.
+
+// Every test file uses its own share of these.
+#![allow(dead_code)]
+
+use reqwest::{
+    header::{LOCATION, SET_COOKIE},
+    redirect::Policy,
+    Client, Response, Url,
+};
+use serde_json::{json, Value};
+
+pub fn app_url() -> String {
+    std::env::var("TEST_APP_URL").unwrap_or_else(|_| "http://localhost:3000".to_string())
+}
+
+/// A client that leaves redirects and cookies to the test.
+pub fn client() -> Client {
+    Client::builder().redirect(Policy::none()).build().unwrap()
+}
+
+/// Signs up a new user with a password and signs them in. Returns the user
+/// and a session token.
+pub async fn sign_up(client: &Client) -> (Value, String) {
+    let mail = format!("{}@test.io", uuid::Uuid::new_v4());
+    let response = client
+        .post(format!("{}/auth/signup", app_url()))
+        .json(&json!({ "name": "Test", "mail": mail, "password": "password1" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let user: Value = response.json().await.unwrap();
+
+    let response = client
+        .post(format!("{}/auth/signin", app_url()))
+        .json(&json!({ "mail": mail, "password": "password1" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let session: Value = response.json().await.unwrap();
+
+    let token = session["token"].as_str().unwrap().to_string();
+    (user["data"].clone(), token)
+}
+
+/// The signed in user.
+pub async fn me(client: &Client, token: &str) -> Value {
+    let response = client
+        .get(format!("{}/api/users/me", app_url()))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    body["data"].clone()
+}
+
+/// The value of a cookie the response sets.
+pub fn cookie(response: &Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|value| value.split(';').next()?.split_once('='))
+        .find(|(cookie, _)| *cookie == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Where a redirect points.
+pub fn location(response: &Response) -> Url {
+    assert!(
+        response.status().is_redirection(),
+        "expected a redirect, got {}",
+        response.status()
+    );
+    let location = response.headers()[LOCATION].to_str().unwrap();
+    response.url().join(location).unwrap()
+}
@@ -0,0 +1,155 @@
+//! The login through the `mock` provider, the mock-oauth2-server from
+//! `compose.yml`, and how the callback holds it to the state, PKCE verifier
+//! and nonce of the login it started.
+
+mod common;
+
+use common::{app_url, client, cookie, location};
+use reqwest::{header::COOKIE, Client, Response, StatusCode, Url};
+use serde_json::{json, Value};
+
+/// Starts a login. Returns the authorization URL at the provider and the
+/// login cookie.
+async fn start(client: &Client) -> (Url, String) {
+    let response = client
+        .get(format!("{}/auth/oidc/mock", app_url()))
+        .send()
+        .await
+        .unwrap();
+    let login = cookie(&response, "oidc").expect("the login sets its cookie");
+    (location(&response), login)
+}
+
+/// Signs in at the provider as a new identity with a verified mail. Returns
+/// the callback URL the provider sends the browser back to.
+async fn authorize(client: &Client, url: Url, mail: &str) -> Url {
+    let claims = json!({ "email": mail, "email_verified": true });
+    let response = client
+        .post(url)
+        .form(&[
+            ("username", uuid::Uuid::new_v4().to_string()),
+            ("claims", claims.to_string()),
+        ])
+        .send()
+        .await
+        .unwrap();
+    location(&response)
+}
+
+async fn callback(client: &Client, url: Url, login: Option<&str>) -> Response {
+    let mut request = client.get(url);
+    if let Some(login) = login {
+        request = request.header(COOKIE, format!("oidc={}", login));
+    }
+    request.send().await.unwrap()
+}
+
+/// The URL with one query parameter replaced.
+fn with_param(url: &Url, name: &str, value: &str) -> Url {
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(key, current)| {
+            let current = if key == name { value.into() } else { current };
+            (key.into_owned(), current.into_owned())
+        })
+        .collect();
+    let mut url = url.clone();
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+    url
+}
+
+fn new_mail() -> String {
+    format!("{}@oidc.test.io", uuid::Uuid::new_v4())
+}
+
+#[tokio::test]
+#[ignore = "needs the server and the compose services"]
+async fn signs_in_through_the_provider() {
+    let client = client();
+    let (url, login) = start(&client).await;
+    for param in ["state", "nonce", "code_challenge"] {
+        assert!(url.query_pairs().any(|(key, _)| key == param), "{}", param);
+    }
+    assert!(url
+        .query_pairs()
+        .any(|(key, value)| key == "code_challenge_method" && value == "S256"));
+
+    let mail = new_mail();
+    let url = authorize(&client, url, &mail).await;
+    let response = callback(&client, url, Some(&login)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let session: Value = response.json().await.unwrap();
+
+    let user = common::me(&client, session["token"].as_str().unwrap()).await;
+    assert_eq!(user["mail"], mail);
+}
+
+#[tokio::test]
+#[ignore = "needs the server and the compose services"]
+async fn rejects_a_callback_with_another_state() {
+    let client = client();
+    let (url, login) = start(&client).await;
+    let url = authorize(&client, url, &new_mail()).await;
+
+    let url = with_param(&url, "state", "forged");
+    let response = callback(&client, url, Some(&login)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[ignore = "needs the server and the compose services"]
+async fn rejects_a_callback_of_another_login() {
+    let client = client();
+    let (url, _) = start(&client).await;
+    let (_, other_login) = start(&client).await;
+    let url = authorize(&client, url, &new_mail()).await;
+
+    let response = callback(&client, url.clone(), Some(&other_login)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = callback(&client, url, None).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[ignore = "needs the server and the compose services"]
+async fn rejects_a_code_issued_for_another_verifier() {
+    let client = client();
+    let (url, login) = start(&client).await;
+    // A challenge for some other verifier, as if the code was intercepted
+    // from someone else's login.
+    let url = with_param(
+        &url,
+        "code_challenge",
+        "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM",
+    );
+    let url = authorize(&client, url, &new_mail()).await;
+
+    let response = callback(&client, url, Some(&login)).await;
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+}
+
+#[tokio::test]
+#[ignore = "needs the server and the compose services"]
+async fn rejects_an_id_token_with_another_nonce() {
+    let client = client();
+    let (url, login) = start(&client).await;
+    let url = with_param(&url, "nonce", "replayed");
+    let url = authorize(&client, url, &new_mail()).await;
+
+    let response = callback(&client, url, Some(&login)).await;
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+}
+
+#[tokio::test]
+#[ignore = "needs the server and the compose services"]
+async fn does_not_link_an_account_with_an_unverified_mail() {
+    let client = client();
+    // Someone signs up first with a mail they do not own.
+    let (user, _) = common::sign_up(&client).await;
+    let mail = user["mail"].as_str().unwrap();
+
+    let (url, login) = start(&client).await;
+    let url = authorize(&client, url, mail).await;
+    let response = callback(&client, url, Some(&login)).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
@@ -0,0 +1,187 @@
+//! Webhook deliveries to a receiver run by the test, which the server must
+//! be allowed to reach through `WEBHOOK_ALLOWED_HOSTS=127.0.0.1`.
+
+mod common;
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use common::{app_url, client, sign_up};
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// How long a delivery may take. Covers the first retry, 30s after a failed
+/// attempt.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(60);
+
+struct Receiver {
+    url: String,
+    requests: mpsc::UnboundedReceiver<(HeaderMap, Bytes)>,
+}
+
+impl Receiver {
+    /// Answers with `statuses` in turn, then with 200.
+    async fn start(statuses: Vec<StatusCode>) -> Receiver {
+        let (sender, requests) = mpsc::unbounded_channel();
+        let statuses = Arc::new(Mutex::new(VecDeque::from(statuses)));
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    |State((sender, statuses)): State<(
+                        mpsc::UnboundedSender<(HeaderMap, Bytes)>,
+                        Arc<Mutex<VecDeque<StatusCode>>>,
+                    )>,
+                     headers: HeaderMap,
+                     body: Bytes| async move {
+                        sender.send((headers, body)).ok();
+                        statuses
+                            .lock()
+                            .unwrap()
+                            .pop_front()
+                            .unwrap_or(StatusCode::OK)
+                    },
+                ),
+            )
+            .with_state((sender, statuses));
+
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let url = format!("http://{}/hook", server.local_addr());
+        tokio::spawn(server);
+        Receiver { url, requests }
+    }
+
+    async fn next(&mut self) -> (HeaderMap, Bytes) {
+        tokio::time::timeout(DELIVERY_TIMEOUT, self.requests.recv())
+            .await
+            .expect("a delivery arrives")
+            .unwrap()
+    }
+}
+
+/// Registers a webhook for the receiver. Returns its id and secret.
+async fn register(client: &Client, token: &str, url: &str, events: &[&str]) -> (String, String) {
+    let response = client
+        .post(format!("{}/api/webhooks", app_url()))
+        .bearer_auth(token)
+        .json(&json!({ "url": url, "events": events }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body: Value = response.json().await.unwrap();
+    (
+        body["data"]["id"].as_str().unwrap().to_string(),
+        body["secret"].as_str().unwrap().to_string(),
+    )
+}
+
+async fn deliveries(client: &Client, token: &str, webhook: &str) -> Vec<Value> {
+    let body: Value = client
+        .get(format!("{}/api/webhooks/{}/deliveries", app_url(), webhook))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    body["data"].as_array().unwrap().clone()
+}
+
+/// Checks the signature headers of a request and returns its JSON body.
+fn verify(secret: &str, headers: &HeaderMap, body: &[u8]) -> Value {
+    let timestamp: i64 = headers["x-webhook-timestamp"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((chrono::Utc::now().timestamp() - timestamp).abs() < 60);
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+    assert_eq!(headers["x-webhook-signature"], expected.as_str());
+
+    serde_json::from_slice(body).unwrap()
+}
+
+#[tokio::test]
+#[ignore = "needs the server and the compose services"]
+async fn signs_deliveries_with_the_webhook_secret() {
+    let client = client();
+    let (_, token) = sign_up(&client).await;
+    let mut receiver = Receiver::start(vec![]).await;
+    let (_, secret) = register(&client, &token, &receiver.url, &["todo.created"]).await;
+
+    let response = client
+        .post(format!("{}/api/todos", app_url()))
+        .bearer_auth(&token)
+        .json(&json!({ "title": "Signed", "content": "" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let todo: Value = response.json().await.unwrap();
+
+    let (headers, body) = receiver.next().await;
+    assert_eq!(headers["x-webhook-event"], "todo.created");
+    let payload = verify(&secret, &headers, &body);
+    assert_eq!(payload["event"], "todo.created");
+    assert_eq!(payload["data"]["todo"]["id"], todo["data"]["id"]);
+}
+
+#[tokio::test]
+#[ignore = "needs the server and the compose services"]
+async fn retries_failed_deliveries() {
+    let client = client();
+    let (_, token) = sign_up(&client).await;
+    let mut receiver = Receiver::start(vec![StatusCode::INTERNAL_SERVER_ERROR]).await;
+    let (webhook, secret) = register(&client, &token, &receiver.url, &["todo.created"]).await;
+
+    let response = client
+        .post(format!("{}/api/webhooks/{}/ping", app_url(), webhook))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let delivery: Value = response.json().await.unwrap();
+    let delivery = delivery["data"]["id"].as_str().unwrap().to_string();
+
+    let (headers, body) = receiver.next().await;
+    assert_eq!(headers["x-webhook-delivery"], delivery.as_str());
+    let first = verify(&secret, &headers, &body);
+
+    // The failed attempt is logged and the delivery stays pending.
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let log = deliveries(&client, &token, &webhook).await;
+    assert_eq!(log[0]["id"], delivery.as_str());
+    assert_eq!(log[0]["status"], "pending");
+    assert_eq!(log[0]["attempts"], 1);
+    assert_eq!(log[0]["responseStatus"], 500);
+
+    let (headers, body) = receiver.next().await;
+    assert_eq!(headers["x-webhook-delivery"], delivery.as_str());
+    assert_eq!(verify(&secret, &headers, &body), first);
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let log = deliveries(&client, &token, &webhook).await;
+    assert_eq!(log[0]["status"], "delivered");
+    assert_eq!(log[0]["attempts"], 2);
+    assert_eq!(log[0]["responseStatus"], 200);
+}